@@ -1,5 +1,5 @@
 import { ProcessedElevationData } from '../utils/elevationProcessor';
-import { getColorForElevation } from '../utils/colorMapping';
+import { hillshade_image } from '../wasm';
 
 export class Topo2DRenderer {
   private canvas: HTMLCanvasElement;
@@ -118,13 +118,29 @@ export class Topo2DRenderer {
     }
   }
 
-  setupGeometry(data: ProcessedElevationData) {
+  async setupGeometry(data: ProcessedElevationData) {
     if (!this.device) return;
 
     this.dimensions = data.dimensions;
     const width = this.dimensions.width;
     const height = this.dimensions.height;
 
+    // Hillshaded hypsometric colors, one RGBA8 pixel per sample
+    const image = await hillshade_image(
+      data.rawElevations,
+      width,
+      height,
+      data.cellSize
+    );
+    const colorAt = (x: number, y: number) => {
+      const i = (y * width + x) * 4;
+      return {
+        r: image[i] / 255,
+        g: image[i + 1] / 255,
+        b: image[i + 2] / 255,
+      };
+    };
+
     // Create vertices for the grid
     const vertices: number[] = [];
     const colors: number[] = []; // Create colors array to match vertices
@@ -142,18 +158,10 @@ export class Topo2DRenderer {
         const y2 = ((y + 1) / (height - 1)) * 2 - 1;
 
         // Get colors for each vertex
-        const colorTopLeft = getColorForElevation(
-          data.normalizedElevations[y * width + x]
-        );
-        const colorTopRight = getColorForElevation(
-          data.normalizedElevations[y * width + (x + 1)]
-        );
-        const colorBottomLeft = getColorForElevation(
-          data.normalizedElevations[(y + 1) * width + x]
-        );
-        const colorBottomRight = getColorForElevation(
-          data.normalizedElevations[(y + 1) * width + (x + 1)]
-        );
+        const colorTopLeft = colorAt(x, y);
+        const colorTopRight = colorAt(x + 1, y);
+        const colorBottomLeft = colorAt(x, y + 1);
+        const colorBottomRight = colorAt(x + 1, y + 1);
 
         // First triangle
         // Top-left
@@ -17,6 +17,9 @@ export class Topo3DRenderer {
   private vertexBuffer: GPUBuffer | null = null;
   private colorBuffer: GPUBuffer | null = null;
   private normalBuffer: GPUBuffer | null = null;
+  private indexBuffer: GPUBuffer | null = null;
+  private indexFormat: GPUIndexFormat = 'uint32';
+  private indexCount: number = 0;
   private uniformBuffer: GPUBuffer | null = null;
   private bindGroup: GPUBindGroup | null = null;
   private vertexCount: number = 0;
@@ -130,7 +133,8 @@ export class Topo3DRenderer {
     );
 
     // CPU computed mesh properties
-    const { vertices, colors, normals, vertexCount } = meshData;
+    const { vertices, colors, normals, indices, indexFormat, vertexCount } =
+      meshData;
 
     console.log(
       'js normals.length:',
@@ -168,6 +172,27 @@ export class Topo3DRenderer {
     new Float32Array(this.normalBuffer.getMappedRange()).set(normals);
     this.normalBuffer.unmap();
     this.vertexCount = vertexCount;
+
+    this.indexBuffer = null;
+    this.indexCount = 0;
+    if (indices && indexFormat && indices.length > 0) {
+      // Index buffer sizes must be a multiple of 4 bytes
+      const indexBytes = Math.ceil(indices.byteLength / 4) * 4;
+      this.indexBuffer = this.device.createBuffer({
+        size: indexBytes,
+        usage: GPUBufferUsage.INDEX,
+        mappedAtCreation: true,
+      });
+      const mapped = this.indexBuffer.getMappedRange();
+      if (indexFormat === 'uint16') {
+        new Uint16Array(mapped).set(indices);
+      } else {
+        new Uint32Array(mapped).set(indices);
+      }
+      this.indexBuffer.unmap();
+      this.indexFormat = indexFormat;
+      this.indexCount = indices.length;
+    }
   }
 
   async setupGeometryGPUCompute(processed: ProcessedElevationData) {
@@ -269,7 +294,12 @@ export class Topo3DRenderer {
     renderPass.setVertexBuffer(0, this.vertexBuffer);
     renderPass.setVertexBuffer(1, this.colorBuffer);
     renderPass.setVertexBuffer(2, this.normalBuffer);
-    renderPass.draw(this.vertexCount);
+    if (this.indexBuffer) {
+      renderPass.setIndexBuffer(this.indexBuffer, this.indexFormat);
+      renderPass.drawIndexed(this.indexCount);
+    } else {
+      renderPass.draw(this.vertexCount);
+    }
     renderPass.end();
 
     this.device.queue.submit([commandEncoder.finish()]);
@@ -6,6 +6,9 @@ export interface MeshData {
   vertices: Float32Array;
   colors: Float32Array;
   normals: Float32Array;
+  // Only set for indexed meshes (WASM); otherwise draw as a triangle list
+  indices?: Uint16Array | Uint32Array;
+  indexFormat?: GPUIndexFormat;
   vertexCount: number;
   computeMethod: ComputeMethod;
 }
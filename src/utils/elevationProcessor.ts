@@ -9,6 +9,7 @@ export interface ProcessedElevationData {
     width: number;
     height: number;
   };
+  cellSize: { x: number; y: number }; // metres
   bounds: {
     elevation: {
       min: number;
@@ -48,6 +49,7 @@ export function processElevationData(
       width: data.width,
       height: data.height,
     },
+    cellSize: data.metadata.cellSize,
     bounds: {
       elevation: {
         min: minElevation,
@@ -1,5 +1,5 @@
 // src/utils/loadElevation.ts
-import { fromUrl, GeoTIFF } from 'geotiff';
+import { wasmReady } from '../wasm';
 
 export interface ElevationData {
   width: number;
@@ -12,33 +12,37 @@ export interface ElevationData {
       minLat: number;
       maxLat: number;
     };
+    /** Ground size of one cell in metres, at the centre of the raster. */
+    cellSize: { x: number; y: number };
   };
 }
 
 export async function loadElevationData(path: string): Promise<ElevationData> {
-  // Load the GeoTIFF
-  const tiff: GeoTIFF = await fromUrl(path);
-  const image = await tiff.getImage();
+  const instance = await wasmReady;
 
-  // Get the metadata
-  const bbox = image.getBoundingBox();
-  const metadata = {
-    bounds: {
-      minLong: bbox[0],
-      minLat: bbox[1],
-      maxLong: bbox[2],
-      maxLat: bbox[3],
-    },
-  };
-
-  // Read the raster data
-  const raster = await image.readRasters();
-  const elevations = raster[0] as Float32Array;
+  // Fetch and decode the GeoTIFF in WASM; throws with the decoder's message
+  // on malformed or unsupported files
+  const response = await fetch(path);
+  if (!response.ok) {
+    throw new Error(`failed to load ${path}: ${response.status}`);
+  }
+  const bytes = new Uint8Array(await response.arrayBuffer());
+  const grid = instance.decode_geotiff(bytes);
 
-  return {
-    width: image.getWidth(),
-    height: image.getHeight(),
-    elevations,
-    metadata,
-  };
+  try {
+    // Outer edges in the raster's CRS
+    const [minLong, minLat, maxLong, maxLat] = grid.bounds;
+    const [cellX, cellY] = grid.cell_size;
+    return {
+      width: grid.width,
+      height: grid.height,
+      elevations: grid.elevations,
+      metadata: {
+        bounds: { minLong, minLat, maxLong, maxLat },
+        cellSize: { x: cellX, y: cellY },
+      },
+    };
+  } finally {
+    grid.free();
+  }
 }
@@ -11,23 +11,38 @@ export async function mesh_compute(
   width: number,
   height: number,
   tessellationFactor: number,
-  computeMethod: ComputeMethod
+  computeMethod: ComputeMethod,
+  faceted = false
 ): Promise<MeshData> {
   const instance = await wasmReady;
 
-  // ✅ Store the result first (don't destructure yet)
-  const result = instance.mesh_compute(
-    elevations,
-    width,
-    height,
-    tessellationFactor
-  );
+  // Indexed mesh with smooth shading by default: one vertex per sample and a
+  // shared index buffer. A faceted look needs a normal per triangle, so it
+  // falls back to triangle soup
+  const options = new instance.MeshOptions();
+  if (!faceted) {
+    options.normal_mode = instance.NormalMode.Smooth;
+  }
+  const compute = faceted
+    ? instance.mesh_compute
+    : instance.mesh_compute_indexed;
+
+  // ✅ Store the result first (don't destructure yet). Throws with a
+  // readable message on invalid input (bad dimensions, oversized mesh, ...)
+  let result;
+  try {
+    result = compute(elevations, width, height, tessellationFactor, options);
+  } finally {
+    options.free();
+  }
 
   // ✅ Extract data before freeing memory
   const meshData = {
     vertices: result.vertices,
     colors: result.colors,
     normals: result.normals,
+    indices: result.indices as Uint16Array | Uint32Array,
+    indexFormat: result.index_format as GPUIndexFormat,
     vertexCount: result.vertex_count,
     computeMethod,
   };
@@ -38,4 +53,32 @@ export async function mesh_compute(
   return meshData; // ✅ Returns only the extracted JS-safe data
 }
 
+/**
+ * Hillshaded, hypsometrically colored RGBA8 image of the grid (row 0 on top).
+ * `cellSize` is the ground distance between samples in the elevation unit.
+ */
+export async function hillshade_image(
+  elevations: Float32Array,
+  width: number,
+  height: number,
+  cellSize = { x: 1, y: 1 },
+  zFactor = 1,
+  multidirectional = false
+): Promise<Uint8ClampedArray> {
+  const instance = await wasmReady;
+
+  const options = multidirectional
+    ? instance.HillshadeOptions.multidirectional()
+    : new instance.HillshadeOptions();
+  options.cell_size_x = cellSize.x;
+  options.cell_size_y = cellSize.y;
+  options.z_factor = zFactor;
+  try {
+    const pixels = instance.hillshade_image(elevations, width, height, options);
+    return new Uint8ClampedArray(pixels.buffer, pixels.byteOffset, pixels.length);
+  } finally {
+    options.free();
+  }
+}
+
 export default wasm;
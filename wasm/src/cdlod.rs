@@ -0,0 +1,579 @@
+use wasm_bindgen::prelude::*;
+
+use crate::tiles::{decimation_error, kept_samples, sample_bounds};
+use crate::voids::{fill_voids_in_place, mask_nodata};
+use crate::{check_grid, MeshError, MeshFrame, MeshOptions};
+
+/// Fraction of a level's range after which its nodes start morphing into
+/// the next coarser level (Strugar uses 2/3).
+const MORPH_START_RATIO: f32 = 0.66;
+
+/// Quadtree over a heightfield for continuous distance-dependent LOD
+/// (CDLOD, Strugar 2010).
+///
+/// Every node is drawn as the same patch of `leaf_size` × `leaf_size` cells:
+/// leaves (level 0) at full resolution and each level up covering four times
+/// the area at half the resolution. Nodes keep mesh-space bounds from the
+/// min/max elevation below them, and each level a geometric error, so
+/// `select` can turn a pixel tolerance into distance ranges. Everything is in
+/// mesh units, the same space as `mesh_compute` vertices.
+#[wasm_bindgen]
+pub struct LodQuadtree {
+    width: usize,
+    height: usize,
+    leaf_size: usize,
+    /// Per level: node bounds on a `nodes_x` × `nodes_y` grid, row major.
+    bounds: Vec<Vec<[f32; 6]>>,
+    /// Per level: largest vertical error of drawing at that level's
+    /// resolution, non-decreasing.
+    errors: Vec<f32>,
+}
+
+/// Nodes picked by `LodQuadtree.select`, in draw order. Node `i` covers
+/// grid cells `[x, x + size] × [y, y + size]` (clipped to the grid) and is
+/// drawn with one patch cell per `2^level` grid cells.
+#[wasm_bindgen]
+pub struct LodSelection {
+    nodes: Vec<SelectedNode>,
+    morph_ranges: Vec<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct SelectedNode {
+    x: usize,
+    y: usize,
+    size: usize,
+    level: usize,
+    bounds: [f32; 6],
+    morph: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Visit {
+    /// Outside the frustum or the grid: nothing to draw.
+    Culled,
+    /// Too far for this level; the parent draws the area instead.
+    OutOfRange,
+    Selected,
+}
+
+/// Per-call selection parameters.
+struct Viewer<'a> {
+    camera: [f32; 3],
+    planes: &'a [f32],
+    ranges: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl LodQuadtree {
+    /// Build the tree for a `width` × `height` grid with leaves of
+    /// `leaf_size` cells (a power of two, e.g. 32). `options` give the mesh
+    /// space (elevation input, exaggeration, cell size) and void handling.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        elevations: &[f32],
+        width: usize,
+        height: usize,
+        leaf_size: usize,
+        options: &MeshOptions,
+    ) -> Result<LodQuadtree, JsError> {
+        Ok(LodQuadtree::build(
+            elevations, width, height, leaf_size, options,
+        )?)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn level_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn leaf_size(&self) -> usize {
+        self.leaf_size
+    }
+
+    /// Geometric error of every level in mesh units, finest first.
+    #[wasm_bindgen(getter)]
+    pub fn level_errors(&self) -> Vec<f32> {
+        self.errors.clone()
+    }
+
+    /// Pick the nodes to draw from `camera` (mesh-space xyz). `frustum` holds
+    /// six planes as `a, b, c, d` with the inside where
+    /// `a*x + b*y + c*z + d >= 0` (see `frustum_planes`). A level is used
+    /// while its error projects to at most `pixel_error` pixels on a
+    /// viewport `viewport_height` pixels tall with vertical field of view
+    /// `fov_y` radians.
+    pub fn select(
+        &self,
+        camera: &[f32],
+        frustum: &[f32],
+        viewport_height: f32,
+        fov_y: f32,
+        pixel_error: f32,
+    ) -> Result<LodSelection, JsError> {
+        if camera.len() != 3 || frustum.len() != 24 {
+            return Err(JsError::new(
+                "camera needs 3 values and frustum 24 (six planes)",
+            ));
+        }
+        let camera = [camera[0], camera[1], camera[2]];
+        Ok(self.select_nodes(camera, frustum, viewport_height, fov_y, pixel_error))
+    }
+}
+
+impl LodQuadtree {
+    /// Native counterpart of the constructor.
+    pub fn build(
+        elevations: &[f32],
+        width: usize,
+        height: usize,
+        leaf_size: usize,
+        options: &MeshOptions,
+    ) -> Result<LodQuadtree, MeshError> {
+        check_grid(elevations, width, height)?;
+        let leaf_size = leaf_size.max(1).next_power_of_two();
+
+        let mut heights = mask_nodata(elevations, options.nodata);
+        fill_voids_in_place(&mut heights, width, height, options.void_fill);
+        let range = options.elevation_range(&heights);
+        let frame = MeshFrame::new(width, height, range.0, options);
+
+        let cells = (width - 1).max(height - 1);
+        let mut level_count = 1;
+        while leaf_size << (level_count - 1) < cells {
+            level_count += 1;
+        }
+
+        // Leaf bounds from the samples, coarser levels from their children
+        let (leaves_x, leaves_y) = (
+            (width - 1).div_ceil(leaf_size),
+            (height - 1).div_ceil(leaf_size),
+        );
+        let mut leaves = Vec::with_capacity(leaves_x * leaves_y);
+        for ny in 0..leaves_y {
+            for nx in 0..leaves_x {
+                let xs = (nx * leaf_size, ((nx + 1) * leaf_size).min(width - 1));
+                let ys = (ny * leaf_size, ((ny + 1) * leaf_size).min(height - 1));
+                leaves.push(sample_bounds(&heights, (width, height), &frame, xs, ys));
+            }
+        }
+        let mut bounds = vec![leaves];
+        let (mut nodes_x, mut nodes_y) = (leaves_x, leaves_y);
+        for _ in 1..level_count {
+            let (parents_x, parents_y) = (nodes_x.div_ceil(2), nodes_y.div_ceil(2));
+            let children = &bounds[bounds.len() - 1];
+            let mut parents = Vec::with_capacity(parents_x * parents_y);
+            for py in 0..parents_y {
+                for px in 0..parents_x {
+                    let mut b = [f32::MAX, f32::MAX, f32::MAX, f32::MIN, f32::MIN, f32::MIN];
+                    for (cx, cy) in quadrants(px, py) {
+                        if cx < nodes_x && cy < nodes_y {
+                            let c = children[cy * nodes_x + cx];
+                            for k in 0..3 {
+                                b[k] = b[k].min(c[k]);
+                                b[k + 3] = b[k + 3].max(c[k + 3]);
+                            }
+                        }
+                    }
+                    parents.push(b);
+                }
+            }
+            bounds.push(parents);
+            (nodes_x, nodes_y) = (parents_x, parents_y);
+        }
+
+        let mut errors = vec![0.0f32];
+        for level in 1..level_count {
+            let stride = 1 << level;
+            let xs = kept_samples(0, width - 1, stride);
+            let ys = kept_samples(0, height - 1, stride);
+            let error = decimation_error(&heights, width, &xs, &ys) * frame.z_scale.abs();
+            errors.push(error.max(errors[level - 1]));
+        }
+
+        Ok(LodQuadtree {
+            width,
+            height,
+            leaf_size,
+            bounds,
+            errors,
+        })
+    }
+
+    /// Native counterpart of `select`.
+    pub fn select_nodes(
+        &self,
+        camera: [f32; 3],
+        frustum: &[f32],
+        viewport_height: f32,
+        fov_y: f32,
+        pixel_error: f32,
+    ) -> LodSelection {
+        // Distance at which one mesh unit of error covers `pixel_error` pixels
+        let scale = viewport_height / (2.0 * (fov_y * 0.5).tan() * pixel_error.max(1e-6));
+
+        // Level l is used up to where level l + 1 becomes acceptable; ranges
+        // at least double per level so neighbours differ by one level.
+        let top = self.errors.len() - 1;
+        let mut ranges: Vec<f32> = Vec::with_capacity(top + 1);
+        for level in 0..=top {
+            let range = if level == top {
+                f32::INFINITY
+            } else {
+                self.errors[level + 1] * scale
+            };
+            let floor = ranges.last().map_or(0.0, |&r| r * 2.0);
+            ranges.push(range.max(floor));
+        }
+
+        let mut morph_ranges = Vec::with_capacity(ranges.len() * 2);
+        for (level, &end) in ranges.iter().enumerate() {
+            let previous = if level == 0 { 0.0 } else { ranges[level - 1] };
+            morph_ranges.push(previous + (end - previous) * MORPH_START_RATIO);
+            morph_ranges.push(end);
+        }
+
+        let viewer = Viewer {
+            camera,
+            planes: frustum,
+            ranges,
+        };
+        let mut nodes = Vec::new();
+        self.visit(top, 0, 0, &viewer, &morph_ranges, &mut nodes);
+
+        LodSelection {
+            nodes,
+            morph_ranges,
+        }
+    }
+
+    fn node_bounds(&self, level: usize, nx: usize, ny: usize) -> Option<[f32; 6]> {
+        let size = self.leaf_size << level;
+        if nx * size >= self.width - 1 || ny * size >= self.height - 1 {
+            return None;
+        }
+        let nodes_x = (self.width - 1).div_ceil(size);
+        Some(self.bounds[level][ny * nodes_x + nx])
+    }
+
+    fn visit(
+        &self,
+        level: usize,
+        nx: usize,
+        ny: usize,
+        viewer: &Viewer,
+        morph_ranges: &[f32],
+        out: &mut Vec<SelectedNode>,
+    ) -> Visit {
+        let Some(bounds) = self.node_bounds(level, nx, ny) else {
+            return Visit::Culled;
+        };
+        if !viewer.sees(&bounds) {
+            return Visit::Culled;
+        }
+        if !viewer.within(&bounds, viewer.ranges[level]) {
+            return Visit::OutOfRange;
+        }
+
+        let size = self.leaf_size << level;
+        let select = |out: &mut Vec<SelectedNode>, x: usize, y: usize, size: usize, b: [f32; 6]| {
+            let range = (morph_ranges[level * 2], morph_ranges[level * 2 + 1]);
+            let morph = morph_factor(viewer.distance(&b), range);
+            out.push(SelectedNode {
+                x,
+                y,
+                size,
+                level,
+                bounds: b,
+                morph,
+            });
+        };
+
+        if level == 0 || !viewer.within(&bounds, viewer.ranges[level - 1]) {
+            select(out, nx * size, ny * size, size, bounds);
+            return Visit::Selected;
+        }
+
+        // Children in range are drawn at their own level; this node fills
+        // in the quadrants whose child is too far for it.
+        for (cx, cy) in quadrants(nx, ny) {
+            if self.visit(level - 1, cx, cy, viewer, morph_ranges, out) == Visit::OutOfRange {
+                let child_bounds = self.node_bounds(level - 1, cx, cy).unwrap_or(bounds);
+                select(out, cx * size / 2, cy * size / 2, size / 2, child_bounds);
+            }
+        }
+        Visit::Selected
+    }
+}
+
+/// How far (0..1) a node `distance` from the camera has morphed across the
+/// `(start, end)` zone of its level; never for the unbounded top level.
+fn morph_factor(distance: f32, (start, end): (f32, f32)) -> f32 {
+    if end.is_finite() && end > start {
+        ((distance - start) / (end - start)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Children of node (x, y) one level down.
+fn quadrants(x: usize, y: usize) -> [(usize, usize); 4] {
+    [
+        (x * 2, y * 2),
+        (x * 2 + 1, y * 2),
+        (x * 2, y * 2 + 1),
+        (x * 2 + 1, y * 2 + 1),
+    ]
+}
+
+impl Viewer<'_> {
+    /// Distance from the camera to the closest point of the box.
+    fn distance(&self, b: &[f32; 6]) -> f32 {
+        let mut sq = 0.0;
+        for k in 0..3 {
+            let d = (b[k] - self.camera[k])
+                .max(self.camera[k] - b[k + 3])
+                .max(0.0);
+            sq += d * d;
+        }
+        sq.sqrt()
+    }
+
+    fn within(&self, b: &[f32; 6], range: f32) -> bool {
+        self.distance(b) <= range
+    }
+
+    /// Conservative box/frustum test: culled only when the box is entirely
+    /// outside one plane.
+    fn sees(&self, b: &[f32; 6]) -> bool {
+        self.planes.chunks_exact(4).all(|p| {
+            // The box corner farthest along the plane normal
+            let x = if p[0] >= 0.0 { b[3] } else { b[0] };
+            let y = if p[1] >= 0.0 { b[4] } else { b[1] };
+            let z = if p[2] >= 0.0 { b[5] } else { b[2] };
+            p[0] * x + p[1] * y + p[2] * z + p[3] >= 0.0
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl LodSelection {
+    #[wasm_bindgen(getter)]
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// `x, y, size` in grid cells per node.
+    #[wasm_bindgen(getter)]
+    pub fn nodes(&self) -> Vec<u32> {
+        self.nodes
+            .iter()
+            .flat_map(|n| [n.x as u32, n.y as u32, n.size as u32])
+            .collect()
+    }
+
+    /// Quadtree level per node; the patch resolution is `2^level` cells.
+    #[wasm_bindgen(getter)]
+    pub fn levels(&self) -> Vec<u32> {
+        self.nodes.iter().map(|n| n.level as u32).collect()
+    }
+
+    /// Mesh-space `[min_x, min_y, min_z, max_x, max_y, max_z]` per node.
+    #[wasm_bindgen(getter)]
+    pub fn bounds(&self) -> Vec<f32> {
+        self.nodes.iter().flat_map(|n| n.bounds).collect()
+    }
+
+    /// Per node, how far (0..1) it has morphed toward the next coarser level,
+    /// measured at its closest point to the camera. Shaders should morph
+    /// per vertex with `morph_ranges` for seamless transitions.
+    #[wasm_bindgen(getter)]
+    pub fn morph_factors(&self) -> Vec<f32> {
+        self.nodes.iter().map(|n| n.morph).collect()
+    }
+
+    /// `start, end` camera distance of the morph zone per level, finest
+    /// first; the coarsest level ends at infinity.
+    #[wasm_bindgen(getter)]
+    pub fn morph_ranges(&self) -> Vec<f32> {
+        self.morph_ranges.clone()
+    }
+}
+
+/// The six planes (left, right, bottom, top, near, far) of a column-major
+/// view-projection matrix with WebGPU's 0..1 clip depth, normalized, as
+/// `a, b, c, d` per plane for `LodQuadtree.select`.
+#[wasm_bindgen]
+pub fn frustum_planes(view_projection: &[f32]) -> Vec<f32> {
+    let m = |row: usize, col: usize| view_projection.get(col * 4 + row).copied().unwrap_or(0.0);
+    let row = |r: usize| [m(r, 0), m(r, 1), m(r, 2), m(r, 3)];
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+    let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+    let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+
+    let planes = [
+        add(r3, r0),
+        sub(r3, r0),
+        add(r3, r1),
+        sub(r3, r1),
+        r2,
+        sub(r3, r2),
+    ];
+    planes
+        .iter()
+        .flat_map(|p| {
+            let len = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt().max(1e-12);
+            p.map(|c| c / len)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Planes that keep everything.
+    const EVERYWHERE: [f32; 24] = [
+        0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, //
+        0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0,
+    ];
+
+    fn hills(width: usize, height: usize) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                0.5 + (x * 0.4).sin() * (y * 0.3).cos() * 0.2
+            })
+            .collect()
+    }
+
+    fn tree(width: usize, height: usize, leaf_size: usize) -> LodQuadtree {
+        let elevations = hills(width, height);
+        LodQuadtree::build(
+            &elevations,
+            width,
+            height,
+            leaf_size,
+            &MeshOptions::default(),
+        )
+        .unwrap()
+    }
+
+    /// Grid cells covered by the selected nodes, clipped to the grid.
+    fn covered_cells(tree: &LodQuadtree, selection: &LodSelection) -> usize {
+        selection
+            .nodes
+            .iter()
+            .map(|n| {
+                let w = (n.x + n.size).min(tree.width - 1) - n.x;
+                let h = (n.y + n.size).min(tree.height - 1) - n.y;
+                w * h
+            })
+            .sum()
+    }
+
+    #[test]
+    fn levels_double_until_one_node_covers_the_grid() {
+        assert_eq!(tree(65, 65, 16).level_count(), 3);
+        assert_eq!(tree(65, 33, 8).level_count(), 4);
+        assert_eq!(tree(66, 10, 8).level_count(), 5);
+        // Leaf sizes round up to a power of two
+        let rounded = tree(65, 65, 12);
+        assert_eq!(rounded.leaf_size(), 16);
+        assert_eq!(rounded.level_count(), 3);
+
+        let errors = tree(129, 129, 8).errors;
+        assert_eq!(errors[0], 0.0);
+        assert!(errors.windows(2).all(|e| e[0] <= e[1]));
+        assert!(errors[errors.len() - 1] > 0.0);
+    }
+
+    #[test]
+    fn distant_cameras_draw_the_root_and_near_ones_refine() {
+        let tree = tree(129, 129, 8);
+        let select = |camera| tree.select_nodes(camera, &EVERYWHERE, 1000.0, 1.0, 40.0);
+
+        let far = select([0.0, 0.0, 1e6]);
+        assert_eq!(far.nodes.len(), 1);
+        assert_eq!(far.nodes[0].level, tree.level_count() - 1);
+
+        // Over a corner: leaves there, coarser levels farther away, and the
+        // grid covered exactly once
+        let camera = [-1.0, -1.0, 0.6];
+        let near = select(camera);
+        assert_eq!(covered_cells(&tree, &near), 128 * 128);
+        let corner = near.nodes.iter().find(|n| n.x == 0 && n.y == 0).unwrap();
+        assert_eq!(corner.level, 0);
+        let viewer = Viewer {
+            camera,
+            planes: &EVERYWHERE,
+            ranges: Vec::new(),
+        };
+        let mut by_distance: Vec<(f32, usize)> = near
+            .nodes
+            .iter()
+            .map(|n| (viewer.distance(&n.bounds), n.level))
+            .collect();
+        by_distance.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert!(by_distance.windows(2).all(|w| w[0].1 <= w[1].1 + 1));
+        assert!(by_distance[by_distance.len() - 1].1 > 0);
+    }
+
+    #[test]
+    fn frustum_culls_nodes_outside_any_plane() {
+        let tree = tree(129, 129, 8);
+        // Keep x >= 0.25 only
+        let mut planes = EVERYWHERE;
+        planes[..4].copy_from_slice(&[1.0, 0.0, 0.0, -0.25]);
+        let selection = tree.select_nodes([0.5, 0.0, 0.5], &planes, 1000.0, 1.0, 1.0);
+        assert!(!selection.nodes.is_empty());
+        assert!(selection.nodes.iter().all(|n| n.bounds[3] >= 0.25));
+        // Everything right of x = 0.25 is still drawn
+        let right_nodes = selection
+            .nodes
+            .iter()
+            .filter(|n| n.bounds[0] >= 0.25)
+            .count();
+        assert!(right_nodes > 0);
+        assert!(covered_cells(&tree, &selection) < 128 * 128);
+
+        // Identity view-projection: the clip volume itself
+        let identity = [
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ];
+        let planes = frustum_planes(&identity);
+        assert_eq!(&planes[..4], &[1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(&planes[16..20], &[0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(&planes[20..], &[0.0, 0.0, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn morph_factors_span_each_levels_zone() {
+        assert_eq!(morph_factor(1.0, (2.0, 4.0)), 0.0);
+        assert_eq!(morph_factor(2.0, (2.0, 4.0)), 0.0);
+        assert_eq!(morph_factor(3.0, (2.0, 4.0)), 0.5);
+        assert_eq!(morph_factor(4.0, (2.0, 4.0)), 1.0);
+        assert_eq!(morph_factor(9.0, (2.0, 4.0)), 1.0);
+        assert_eq!(morph_factor(9.0, (2.0, f32::INFINITY)), 0.0);
+
+        let tree = tree(129, 129, 8);
+        let selection = tree.select_nodes([-1.0, -1.0, 0.6], &EVERYWHERE, 1000.0, 1.0, 40.0);
+        let ranges = &selection.morph_ranges;
+        assert_eq!(ranges.len(), tree.level_count() * 2);
+        for level in 0..tree.level_count() {
+            let (start, end) = (ranges[level * 2], ranges[level * 2 + 1]);
+            let previous = if level == 0 {
+                0.0
+            } else {
+                ranges[level * 2 - 1]
+            };
+            assert!(previous <= start && start <= end);
+        }
+        assert_eq!(ranges[ranges.len() - 1], f32::INFINITY);
+        assert!(selection
+            .nodes
+            .iter()
+            .all(|n| (0.0..=1.0).contains(&n.morph)));
+    }
+}
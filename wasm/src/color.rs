@@ -0,0 +1,458 @@
+use wasm_bindgen::prelude::*;
+
+/// How colors are blended between two neighbouring stops of a `ColorRamp`.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RampInterpolation {
+    /// Straight blend of the (sRGB encoded) channel values.
+    LinearRgb,
+    /// Blend in CIELAB (D65), perceptually more even than RGB.
+    Lab,
+    /// Blend in OKLab, avoids the hue shifts Lab shows in blues.
+    Oklab,
+    /// No blending: each stop's color holds until the next stop.
+    Stepped,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ColorStop {
+    position: f32,
+    rgba: [f32; 4],
+}
+
+/// A color gradient over [first stop, last stop], typically 0..1 normalized
+/// elevation. Colors are sRGB encoded, like everything else we hand to WebGPU.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ColorRamp {
+    stops: Vec<ColorStop>,
+    interpolation: RampInterpolation,
+}
+
+#[wasm_bindgen]
+impl ColorRamp {
+    /// Build a ramp from `positions` and a flat `colors` array holding either
+    /// 3 (RGB) or 4 (RGBA) floats in 0..1 per position.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        positions: &[f32],
+        colors: &[f32],
+        interpolation: RampInterpolation,
+    ) -> Result<ColorRamp, JsError> {
+        ColorRamp::from_parts(positions, colors, interpolation).map_err(JsError::new)
+    }
+
+    /// The classic green → brown → white terrain ramp (the default).
+    pub fn hypsometric() -> ColorRamp {
+        ColorRamp::from_rgb_stops(&[
+            (0.0, [0.6, 0.6, 0.95]),   // Light blue for lowest areas
+            (0.1, [0.4, 0.8, 0.4]),    // Light green for low lands
+            (0.3, [0.2, 0.6, 0.2]),    // Darker green
+            (0.5, [0.8, 0.7, 0.5]),    // Light brown
+            (0.7, [0.7, 0.55, 0.4]),   // Medium brown
+            (0.9, [0.75, 0.75, 0.75]), // Gray
+            (1.0, [1.0, 1.0, 1.0]),    // White for peaks!
+        ])
+    }
+
+    pub fn grayscale() -> ColorRamp {
+        ColorRamp::from_rgb_stops(&[(0.0, [0.0, 0.0, 0.0]), (1.0, [1.0, 1.0, 1.0])])
+    }
+
+    pub fn viridis() -> ColorRamp {
+        ColorRamp::from_hex_stops(&[
+            0x440154, 0x472d7b, 0x3b528b, 0x2c728e, 0x21918c, 0x28ae80, 0x5ec962, 0xaddc30,
+            0xfde725,
+        ])
+    }
+
+    /// Deep navy to pale cyan, for sea floor and lake beds.
+    pub fn bathymetry() -> ColorRamp {
+        ColorRamp::from_rgb_stops(&[
+            (0.0, [0.03, 0.11, 0.3]),
+            (0.4, [0.1, 0.3, 0.6]),
+            (0.7, [0.35, 0.6, 0.8]),
+            (0.9, [0.65, 0.85, 0.92]),
+            (1.0, [0.85, 0.95, 0.98]),
+        ])
+    }
+
+    /// Blue → yellow ramp that stays readable with color vision deficiency.
+    pub fn cividis() -> ColorRamp {
+        ColorRamp::from_hex_stops(&[
+            0x00204d, 0x00336f, 0x39486b, 0x575c6d, 0x707173, 0x8a8779, 0xa69d75, 0xc4b56c,
+            0xe4cf5b, 0xffea46,
+        ])
+    }
+
+    /// Stepped slope-angle classes in degrees (as used for avalanche terrain
+    /// maps), meant for `ColorSource::Slope`.
+    pub fn slope_angle() -> ColorRamp {
+        let mut ramp = ColorRamp::from_rgb_stops(&[
+            (0.0, [0.92, 0.92, 0.92]), // Below avalanche terrain
+            (27.0, [0.85, 0.9, 0.3]),
+            (30.0, [1.0, 0.85, 0.0]),
+            (32.0, [1.0, 0.55, 0.0]),
+            (35.0, [0.9, 0.1, 0.1]),
+            (46.0, [0.55, 0.2, 0.65]),
+            (51.0, [0.2, 0.3, 0.8]),
+            (60.0, [0.1, 0.1, 0.1]), // Cliffs
+        ]);
+        ramp.interpolation = RampInterpolation::Stepped;
+        ramp
+    }
+
+    /// Alpine ramp with stops in metres above sea level (treeline around
+    /// 1900 m, permanent snow from 2500 m), for `ElevationInput::Metres`.
+    pub fn alpine() -> ColorRamp {
+        ColorRamp::from_rgb_stops(&[
+            (0.0, [0.35, 0.6, 0.3]),      // Valley floors
+            (800.0, [0.25, 0.5, 0.22]),   // Forest
+            (1900.0, [0.55, 0.6, 0.35]),  // Alpine meadows
+            (2200.0, [0.55, 0.5, 0.45]),  // Scree and rock
+            (2500.0, [0.95, 0.95, 0.97]), // Snow line
+            (4800.0, [1.0, 1.0, 1.0]),
+        ])
+    }
+
+    /// Copy of this ramp with its stops stretched linearly from their current
+    /// range onto [`min`, `max`], e.g. to apply a 0..1 preset to metres.
+    pub fn rescaled(&self, min: f32, max: f32) -> ColorRamp {
+        let first = self.stops[0].position;
+        let span = self.stops[self.stops.len() - 1].position - first;
+        let mut ramp = self.clone();
+        for stop in &mut ramp.stops {
+            let t = if span > 0.0 {
+                (stop.position - first) / span
+            } else {
+                0.0
+            };
+            stop.position = min + t * (max - min);
+        }
+        ramp
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn interpolation(&self) -> RampInterpolation {
+        self.interpolation
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_interpolation(&mut self, interpolation: RampInterpolation) {
+        self.interpolation = interpolation;
+    }
+
+    /// RGBA color at `value`, clamped to the ramp's range.
+    pub fn sample(&self, value: f32) -> Vec<f32> {
+        self.rgba(value).to_vec()
+    }
+}
+
+impl Default for ColorRamp {
+    fn default() -> Self {
+        ColorRamp::hypsometric()
+    }
+}
+
+impl ColorRamp {
+    fn from_parts(
+        positions: &[f32],
+        colors: &[f32],
+        interpolation: RampInterpolation,
+    ) -> Result<ColorRamp, &'static str> {
+        if positions.is_empty() {
+            return Err("a color ramp needs at least one stop");
+        }
+        let channels = if colors.len() == positions.len() * 3 {
+            3
+        } else if colors.len() == positions.len() * 4 {
+            4
+        } else {
+            return Err("colors must hold 3 (RGB) or 4 (RGBA) values per stop");
+        };
+        if positions.iter().chain(colors).any(|v| !v.is_finite()) {
+            return Err("color ramp stops must be finite");
+        }
+
+        let mut stops: Vec<ColorStop> = positions
+            .iter()
+            .zip(colors.chunks_exact(channels))
+            .map(|(&position, c)| ColorStop {
+                position,
+                rgba: [c[0], c[1], c[2], if channels == 4 { c[3] } else { 1.0 }],
+            })
+            .collect();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+
+        Ok(ColorRamp {
+            stops,
+            interpolation,
+        })
+    }
+
+    fn from_rgb_stops(stops: &[(f32, [f32; 3])]) -> ColorRamp {
+        ColorRamp {
+            stops: stops
+                .iter()
+                .map(|&(position, [r, g, b])| ColorStop {
+                    position,
+                    rgba: [r, g, b, 1.0],
+                })
+                .collect(),
+            interpolation: RampInterpolation::LinearRgb,
+        }
+    }
+
+    /// Evenly spaced stops from 0xRRGGBB values.
+    fn from_hex_stops(hex: &[u32]) -> ColorRamp {
+        let last = (hex.len() - 1) as f32;
+        let stops: Vec<(f32, [f32; 3])> = hex
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                let channel = |shift: u32| ((c >> shift) & 0xff) as f32 / 255.0;
+                (i as f32 / last, [channel(16), channel(8), channel(0)])
+            })
+            .collect();
+        ColorRamp::from_rgb_stops(&stops)
+    }
+
+    pub(crate) fn rgb(&self, value: f32) -> [f32; 3] {
+        let [r, g, b, _] = self.rgba(value);
+        [r, g, b]
+    }
+
+    pub(crate) fn rgba(&self, value: f32) -> [f32; 4] {
+        let first = &self.stops[0];
+        let last = &self.stops[self.stops.len() - 1];
+        if value.is_nan() || value <= first.position {
+            return first.rgba;
+        }
+        if value >= last.position {
+            return last.rgba;
+        }
+
+        // First stop strictly above `value`; its predecessor is at or below.
+        let upper = self.stops.partition_point(|s| s.position <= value);
+        let (lo, hi) = (&self.stops[upper - 1], &self.stops[upper]);
+        // On a stop, skip the Lab round trip so the stop color comes back as given
+        if self.interpolation == RampInterpolation::Stepped || value == lo.position {
+            return lo.rgba;
+        }
+
+        let t = (value - lo.position) / (hi.position - lo.position);
+        let alpha = lerp(lo.rgba[3], hi.rgba[3], t);
+        let [r, g, b] = match self.interpolation {
+            RampInterpolation::Lab => {
+                lab_to_srgb(lerp3(srgb_to_lab(lo.rgba), srgb_to_lab(hi.rgba), t))
+            }
+            RampInterpolation::Oklab => {
+                oklab_to_srgb(lerp3(srgb_to_oklab(lo.rgba), srgb_to_oklab(hi.rgba), t))
+            }
+            _ => lerp3(
+                [lo.rgba[0], lo.rgba[1], lo.rgba[2]],
+                [hi.rgba[0], hi.rgba[1], hi.rgba[2]],
+                t,
+            ),
+        };
+        [r, g, b, alpha]
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        lerp(a[0], b[0], t),
+        lerp(a[1], b[1], t),
+        lerp(a[2], b[2], t),
+    ]
+}
+
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// D65 reference white
+const WHITE_X: f32 = 0.950_47;
+const WHITE_Z: f32 = 1.088_83;
+const LAB_DELTA: f32 = 6.0 / 29.0;
+
+fn srgb_to_lab(rgba: [f32; 4]) -> [f32; 3] {
+    let (r, g, b) = (
+        srgb_to_linear(rgba[0]),
+        srgb_to_linear(rgba[1]),
+        srgb_to_linear(rgba[2]),
+    );
+    let x = 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = 0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b;
+
+    let f = |t: f32| {
+        if t > LAB_DELTA.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x / WHITE_X), f(y), f(z / WHITE_Z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_to_srgb(lab: [f32; 3]) -> [f32; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let fx = fy + lab[1] / 500.0;
+    let fz = fy - lab[2] / 200.0;
+    let finv = |t: f32| {
+        if t > LAB_DELTA {
+            t * t * t
+        } else {
+            3.0 * LAB_DELTA * LAB_DELTA * (t - 4.0 / 29.0)
+        }
+    };
+    let (x, y, z) = (finv(fx) * WHITE_X, finv(fy), finv(fz) * WHITE_Z);
+
+    [
+        linear_to_srgb(3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z),
+        linear_to_srgb(-0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z),
+        linear_to_srgb(0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z),
+    ]
+}
+
+fn srgb_to_oklab(rgba: [f32; 4]) -> [f32; 3] {
+    let (r, g, b) = (
+        srgb_to_linear(rgba[0]),
+        srgb_to_linear(rgba[1]),
+        srgb_to_linear(rgba[2]),
+    );
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+fn oklab_to_srgb(lab: [f32; 3]) -> [f32; 3] {
+    let l = (lab[0] + 0.396_337_78 * lab[1] + 0.215_803_76 * lab[2]).powi(3);
+    let m = (lab[0] - 0.105_561_346 * lab[1] - 0.063_854_17 * lab[2]).powi(3);
+    let s = (lab[0] - 0.089_484_18 * lab[1] - 1.291_485_5 * lab[2]).powi(3);
+
+    [
+        linear_to_srgb(4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s),
+        linear_to_srgb(-1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s),
+        linear_to_srgb(-0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [RampInterpolation; 3] = [
+        RampInterpolation::LinearRgb,
+        RampInterpolation::Lab,
+        RampInterpolation::Oklab,
+    ];
+
+    #[test]
+    fn from_parts_sorts_stops_and_rejects_bad_colors() {
+        // Stops given high to low come out sorted, alpha defaulting to 1
+        let ramp = ColorRamp::from_parts(
+            &[1.0, 0.0],
+            &[1.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+            RampInterpolation::LinearRgb,
+        )
+        .unwrap();
+        assert_eq!(ramp.rgba(0.0), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(ramp.rgba(1.0), [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(ramp.rgba(0.25), [0.25, 0.25, 0.25, 1.0]);
+
+        let rgba = ColorRamp::from_parts(&[0.0], &[0.1, 0.2, 0.3, 0.4], RampInterpolation::Lab);
+        assert_eq!(rgba.unwrap().rgba(5.0), [0.1, 0.2, 0.3, 0.4]);
+
+        for (positions, colors) in [
+            (&[][..], &[][..]),
+            (&[0.0, 1.0][..], &[0.0; 5][..]),
+            (&[0.0, 1.0][..], &[0.0; 7][..]),
+            (&[0.0, f32::NAN][..], &[0.0; 6][..]),
+            (
+                &[0.0, 1.0][..],
+                &[0.0, 0.0, f32::INFINITY, 0.0, 0.0, 0.0][..],
+            ),
+        ] {
+            let result = ColorRamp::from_parts(positions, colors, RampInterpolation::LinearRgb);
+            assert!(result.is_err(), "{positions:?} {colors:?}");
+        }
+    }
+
+    #[test]
+    fn stepped_ramps_hold_each_stop_color() {
+        let ramp = ColorRamp::slope_angle();
+        assert_eq!(ramp.rgb(29.9), [0.85, 0.9, 0.3]);
+        assert_eq!(ramp.rgb(30.0), [1.0, 0.85, 0.0]);
+        assert_eq!(ramp.rgb(34.0), [1.0, 0.55, 0.0]);
+        assert_eq!(ramp.rgb(-5.0), [0.92, 0.92, 0.92]);
+        assert_eq!(ramp.rgb(90.0), [0.1, 0.1, 0.1]);
+    }
+
+    #[test]
+    fn every_blend_returns_the_stop_colors_at_the_stops() {
+        for mode in MODES {
+            let mut ramp = ColorRamp::viridis();
+            ramp.set_interpolation(mode);
+            for stop in &ramp.stops {
+                assert_eq!(ramp.rgba(stop.position), stop.rgba, "{mode:?}");
+            }
+            // Between stops the blends stay in gamut
+            for i in 0..=64 {
+                let c = ramp.rgb(i as f32 / 64.0);
+                assert!(c.iter().all(|v| (0.0..=1.0).contains(v)), "{mode:?} {c:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn lab_blends_differ_from_rgb_between_stops() {
+        let mut ramp = ColorRamp::from_rgb_stops(&[(0.0, [0.0, 0.0, 1.0]), (1.0, [1.0, 1.0, 0.0])]);
+        let rgb = ramp.rgb(0.5);
+        assert_eq!(rgb, [0.5, 0.5, 0.5]);
+        for mode in [RampInterpolation::Lab, RampInterpolation::Oklab] {
+            ramp.set_interpolation(mode);
+            assert!(ramp.rgb(0.5) != rgb, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn rescaled_stretches_the_stop_positions() {
+        let ramp = ColorRamp::hypsometric().rescaled(100.0, 1100.0);
+        let positions: Vec<f32> = ramp.stops.iter().map(|s| s.position).collect();
+        let expected = [100.0, 200.0, 400.0, 600.0, 800.0, 1000.0, 1100.0];
+        for (p, e) in positions.iter().zip(expected) {
+            assert!((p - e).abs() < 1e-3, "{positions:?}");
+        }
+        assert_eq!(ramp.rgb(600.0), [0.8, 0.7, 0.5]);
+
+        // A single stop has no range and lands on `min`
+        let single = ColorRamp::from_rgb_stops(&[(3.0, [1.0, 0.0, 0.0])]).rescaled(-1.0, 1.0);
+        assert_eq!(single.stops[0].position, -1.0);
+    }
+}
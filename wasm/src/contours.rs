@@ -0,0 +1,443 @@
+use js_sys::{Float32Array, Uint32Array, Uint8Array};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::voids::mask_nodata;
+use crate::{check_grid, MeshError, MeshFrame, MeshOptions};
+
+/// Most levels a single call may trace; a tiny interval over a large
+/// elevation range would otherwise take minutes and freeze the page.
+pub const MAX_CONTOUR_LEVELS: usize = 1000;
+
+/// One stitched contour line, in fractional grid coordinates.
+#[derive(Clone, Debug)]
+pub struct Contour {
+    pub level: f32,
+    /// Every `index_every`-th level counted from `base` (drawn heavier).
+    pub is_index: bool,
+    /// Closed rings repeat their first point at the end.
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Contour {
+    pub fn is_closed(&self) -> bool {
+        self.points.len() > 2 && self.points.first() == self.points.last()
+    }
+}
+
+/// Contour lines packed for upload: `vertices` are xyz in mesh space, on the
+/// `mesh_compute` surface for the same options, `indices` are line-list
+/// pairs, and polyline `i` spans
+/// `vertices[polyline_offsets[i]..polyline_offsets[i + 1]]`.
+#[wasm_bindgen]
+pub struct ContourData {
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    polyline_offsets: Vec<u32>,
+    levels: Vec<f32>,
+    index_flags: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl ContourData {
+    #[wasm_bindgen(getter)]
+    pub fn vertices(&self) -> Float32Array {
+        Float32Array::from(self.vertices.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> Uint32Array {
+        Uint32Array::from(self.indices.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn polyline_offsets(&self) -> Uint32Array {
+        Uint32Array::from(self.polyline_offsets.as_slice())
+    }
+
+    /// Elevation of each polyline.
+    #[wasm_bindgen(getter)]
+    pub fn levels(&self) -> Float32Array {
+        Float32Array::from(self.levels.as_slice())
+    }
+
+    /// 1 for index contours, 0 otherwise, one entry per polyline.
+    #[wasm_bindgen(getter)]
+    pub fn index_flags(&self) -> Uint8Array {
+        Uint8Array::from(self.index_flags.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn polyline_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / 3
+    }
+}
+
+/// Marching-squares contours every `interval` units, offset by `base`.
+/// Every `index_every`-th level (counting from `base`) is flagged as an index
+/// contour; pass 0 to flag none. Levels are in the elevation unit and
+/// `options.nodata` samples are left out. Fails if `elevations` is not a
+/// `width` × `height` grid or the interval gives more than
+/// `MAX_CONTOUR_LEVELS` levels.
+#[wasm_bindgen]
+pub fn contours(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    interval: f32,
+    base: f32,
+    index_every: usize,
+    options: &MeshOptions,
+) -> Result<ContourData, JsError> {
+    check_grid(elevations, width, height)?;
+    if !(interval.is_finite() && interval > 0.0 && base.is_finite()) {
+        return Err(JsError::new(
+            "contours: interval must be positive and base finite",
+        ));
+    }
+
+    let heights = mask_nodata(elevations, options.nodata);
+    let lines = extract_contours(&heights, width, height, interval, base, index_every)?;
+    let range = options.elevation_range(&heights);
+    let frame = MeshFrame::new(width, height, range.0, options);
+
+    let mut data = ContourData {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        polyline_offsets: vec![0],
+        levels: Vec::with_capacity(lines.len()),
+        index_flags: Vec::with_capacity(lines.len()),
+    };
+    for line in &lines {
+        let start = (data.vertices.len() / 3) as u32;
+        for &(gx, gy) in &line.points {
+            data.vertices
+                .extend_from_slice(&frame.point(gx, gy, width, height, line.level));
+        }
+        let end = (data.vertices.len() / 3) as u32;
+        for i in start..end.saturating_sub(1) {
+            data.indices.extend_from_slice(&[i, i + 1]);
+        }
+        data.polyline_offsets.push(end);
+        data.levels.push(line.level);
+        data.index_flags.push(line.is_index as u8);
+    }
+
+    Ok(data)
+}
+
+pub fn extract_contours(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    interval: f32,
+    base: f32,
+    index_every: usize,
+) -> Result<Vec<Contour>, MeshError> {
+    check_grid(elevations, width, height)?;
+    let (min, max) = elevations
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    if min > max {
+        return Ok(Vec::new());
+    }
+
+    // Count in f64 first: the ratio can exceed any integer type
+    let first = ((min - base) as f64 / interval as f64).ceil();
+    let last = ((max - base) as f64 / interval as f64).floor();
+    let levels = (last - first + 1.0).max(0.0);
+    if levels > MAX_CONTOUR_LEVELS as f64 {
+        return Err(MeshError::TooManyLevels {
+            levels: levels.min(usize::MAX as f64) as usize,
+            limit: MAX_CONTOUR_LEVELS,
+        });
+    }
+    let (first, last) = (first as i64, last as i64);
+
+    let mut lines = Vec::new();
+    for k in first..=last {
+        let level = base + k as f32 * interval;
+        let is_index = index_every > 0 && k.rem_euclid(index_every as i64) == 0;
+        let segments = level_segments(elevations, width, height, level);
+        for edges in stitch(&segments) {
+            let mut points: Vec<(f32, f32)> = edges
+                .iter()
+                .map(|&edge| edge_point(edge, elevations, width, level))
+                .collect();
+            // A level that only touches samples (e.g. exactly the maximum)
+            // yields zero-length pieces; drop the repeats and what's left.
+            points.dedup();
+            if points.len() < 2 {
+                continue;
+            }
+            lines.push(Contour {
+                level,
+                is_index,
+                points,
+            });
+        }
+    }
+    Ok(lines)
+}
+
+// Grid edges are identified by their top/left sample: horizontal edge
+// (x, y)-(x + 1, y) is `2 * (y * width + x)`, vertical edge (x, y)-(x, y + 1)
+// is that plus one. Neighbouring cells share the id of their common edge,
+// which is what lets segments be stitched without comparing floats.
+fn horizontal_edge(x: usize, y: usize, width: usize) -> usize {
+    2 * (y * width + x)
+}
+
+fn vertical_edge(x: usize, y: usize, width: usize) -> usize {
+    2 * (y * width + x) + 1
+}
+
+/// Where the contour crosses `edge`, in fractional grid coordinates.
+fn edge_point(edge: usize, elevations: &[f32], width: usize, level: f32) -> (f32, f32) {
+    let sample = edge / 2;
+    let (x, y) = (sample % width, sample / width);
+    let a = elevations[sample];
+    let (b, dx, dy) = if edge.is_multiple_of(2) {
+        (elevations[sample + 1], 1.0, 0.0)
+    } else {
+        (elevations[sample + width], 0.0, 1.0)
+    };
+    let t = ((level - a) / (b - a)).clamp(0.0, 1.0);
+    (x as f32 + dx * t, y as f32 + dy * t)
+}
+
+/// Marching squares over every cell for a single level; each segment joins
+/// the two cell edges the line crosses.
+fn level_segments(elevations: &[f32], width: usize, height: usize, level: f32) -> Vec<[usize; 2]> {
+    let mut segments = Vec::new();
+
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let tl = elevations[y * width + x];
+            let tr = elevations[y * width + x + 1];
+            let bl = elevations[(y + 1) * width + x];
+            let br = elevations[(y + 1) * width + x + 1];
+            if !(tl.is_finite() && tr.is_finite() && bl.is_finite() && br.is_finite()) {
+                continue;
+            }
+
+            let case = ((tl >= level) as u8) << 3
+                | ((tr >= level) as u8) << 2
+                | ((br >= level) as u8) << 1
+                | (bl >= level) as u8;
+
+            let top = horizontal_edge(x, y, width);
+            let bottom = horizontal_edge(x, y + 1, width);
+            let left = vertical_edge(x, y, width);
+            let right = vertical_edge(x + 1, y, width);
+
+            // Saddles (5, 10) are resolved with the cell-centre average: if the
+            // centre is high the two high corners are joined through it.
+            let centre_high = (tl + tr + bl + br) * 0.25 >= level;
+
+            match case {
+                0 | 15 => {}
+                1 | 14 => segments.push([left, bottom]),
+                2 | 13 => segments.push([bottom, right]),
+                3 | 12 => segments.push([left, right]),
+                4 | 11 => segments.push([top, right]),
+                6 | 9 => segments.push([top, bottom]),
+                7 | 8 => segments.push([left, top]),
+                5 => {
+                    if centre_high {
+                        segments.push([left, top]);
+                        segments.push([bottom, right]);
+                    } else {
+                        segments.push([top, right]);
+                        segments.push([left, bottom]);
+                    }
+                }
+                10 => {
+                    if centre_high {
+                        segments.push([top, right]);
+                        segments.push([left, bottom]);
+                    } else {
+                        segments.push([left, top]);
+                        segments.push([bottom, right]);
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    segments
+}
+
+/// Join segments that share an edge into polylines. Every edge crossing
+/// belongs to at most two segments, so the chains are unambiguous: open
+/// chains end on the grid border (or a void), the rest are closed rings.
+fn stitch(segments: &[[usize; 2]]) -> Vec<Vec<usize>> {
+    let mut by_edge: HashMap<usize, Vec<usize>> = HashMap::with_capacity(segments.len() * 2);
+    for (i, seg) in segments.iter().enumerate() {
+        by_edge.entry(seg[0]).or_default().push(i);
+        by_edge.entry(seg[1]).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut polylines = Vec::new();
+
+    let mut walk = |start_edge: usize, first: usize, used: &mut Vec<bool>| {
+        let mut points = vec![start_edge];
+        let (mut edge, mut seg) = (start_edge, first);
+        loop {
+            used[seg] = true;
+            let [a, b] = segments[seg];
+            edge = if a == edge { b } else { a };
+            points.push(edge);
+            match by_edge[&edge].iter().find(|&&s| !used[s]) {
+                Some(&next) => seg = next,
+                None => break,
+            }
+        }
+        polylines.push(points);
+    };
+
+    // Open polylines first, starting from their dangling ends
+    for i in 0..segments.len() {
+        for &end in &segments[i] {
+            if !used[i] && by_edge[&end].len() == 1 {
+                walk(end, i, &mut used);
+            }
+        }
+    }
+    // Whatever is left forms closed rings
+    for i in 0..segments.len() {
+        if !used[i] {
+            walk(segments[i][0], i, &mut used);
+        }
+    }
+
+    polylines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines of a 2 × 2 grid at `level`, with their end points sorted so the
+    /// order of the walk does not matter.
+    fn cell_lines(tl: f32, tr: f32, bl: f32, br: f32, level: f32) -> Vec<[(f32, f32); 2]> {
+        let lines = extract_contours(&[tl, tr, bl, br], 2, 2, 1.0, level, 0).unwrap();
+        let mut ends: Vec<[(f32, f32); 2]> = lines
+            .iter()
+            .filter(|line| line.level == level)
+            .map(|line| {
+                assert_eq!(line.points.len(), 2);
+                let mut ends = [line.points[0], line.points[1]];
+                ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                ends
+            })
+            .collect();
+        ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        ends
+    }
+
+    #[test]
+    fn saddle_with_a_high_centre_joins_the_high_corners() {
+        // Case 10: top-left and bottom-right high
+        assert_eq!(
+            cell_lines(1.0, 0.0, 0.0, 1.0, 0.5),
+            vec![[(0.0, 0.5), (0.5, 1.0)], [(0.5, 0.0), (1.0, 0.5)]]
+        );
+        // Case 5: top-right and bottom-left high
+        assert_eq!(
+            cell_lines(0.0, 1.0, 1.0, 0.0, 0.5),
+            vec![[(0.0, 0.5), (0.5, 0.0)], [(0.5, 1.0), (1.0, 0.5)]]
+        );
+    }
+
+    #[test]
+    fn saddle_with_a_low_centre_cuts_off_the_high_corners() {
+        // Centre average 0.5 is below the level, so the high corners are
+        // separated from each other
+        let lines = cell_lines(1.0, 0.0, 0.0, 1.0, 0.75);
+        assert_eq!(
+            lines,
+            vec![[(0.0, 0.25), (0.25, 0.0)], [(0.75, 1.0), (1.0, 0.75)]]
+        );
+        let lines = cell_lines(0.0, 1.0, 1.0, 0.0, 0.75);
+        assert_eq!(
+            lines,
+            vec![[(0.0, 0.75), (0.25, 1.0)], [(0.75, 0.0), (1.0, 0.25)]]
+        );
+    }
+
+    #[test]
+    fn peak_gives_one_closed_ring_per_level() {
+        let mut grid = vec![0.0; 25];
+        grid[12] = 2.0;
+        let lines = extract_contours(&grid, 5, 5, 1.0, 0.5, 2).unwrap();
+        assert_eq!(lines.len(), 2);
+        for line in &lines {
+            assert!(line.is_closed());
+            assert_eq!(line.points.len(), 5);
+        }
+        assert_eq!((lines[0].level, lines[1].level), (0.5, 1.5));
+        assert!(lines[0].is_index && !lines[1].is_index);
+    }
+
+    #[test]
+    fn grid_must_match_its_dimensions() {
+        // One sample too many is as wrong as one too few
+        let result = extract_contours(&[0.0, 1.0, 2.0, 3.0, 4.0], 2, 2, 1.0, 0.0, 0);
+        assert_eq!(
+            result.err(),
+            Some(MeshError::DimensionMismatch {
+                expected: 4,
+                actual: 5
+            })
+        );
+        let result = extract_contours(&[0.0, 1.0, 2.0], 3, 1, 1.0, 0.0, 0);
+        assert!(matches!(result, Err(MeshError::GridTooSmall { .. })));
+    }
+
+    #[test]
+    fn rejects_intervals_with_too_many_levels() {
+        let grid = [0.0, 0.0, 630.0, 630.0];
+        let result = extract_contours(&grid, 2, 2, 0.01, 0.0, 0);
+        assert!(matches!(
+            result,
+            Err(MeshError::TooManyLevels { levels: 63_001, .. })
+        ));
+        let result = extract_contours(&grid, 2, 2, 1e-30, 0.0, 0);
+        assert!(matches!(result, Err(MeshError::TooManyLevels { .. })));
+        assert!(extract_contours(&grid, 2, 2, 1.0, 0.0, 0).is_ok());
+    }
+
+    #[test]
+    fn contours_lie_on_the_mesh_surface() {
+        // 5 × 3 metre grid rising 10 m per column, 30 m × 15 m cells
+        let elevations: Vec<f32> = (0..15).map(|i| (i % 5) as f32 * 10.0 + 100.0).collect();
+        let options = MeshOptions {
+            elevation_input: crate::ElevationInput::Metres,
+            cell_size_x: 30.0,
+            cell_size_y: 15.0,
+            ..MeshOptions::default()
+        };
+        let mesh = crate::mesh_compute_indexed(&elevations, 5, 3, 1, &options).unwrap();
+        let data = contours(&elevations, 5, 3, 10.0, 105.0, 0, &options).unwrap();
+        assert_eq!(data.levels, vec![105.0, 115.0, 125.0, 135.0]);
+
+        // The 105 m line runs halfway between the first two columns, on the
+        // segment between their vertices
+        let vertex = |i: usize| &mesh.vertices[i * 3..i * 3 + 3];
+        for (row, point) in data.vertices[..9].chunks_exact(3).enumerate() {
+            let (a, b) = (vertex(row * 5), vertex(row * 5 + 1));
+            for k in 0..3 {
+                assert!((point[k] - (a[k] + b[k]) * 0.5).abs() < 1e-6);
+            }
+        }
+    }
+}
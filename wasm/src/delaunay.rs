@@ -0,0 +1,590 @@
+use wasm_bindgen::prelude::*;
+
+use crate::voids::{fill_voids_in_place, mask_nodata};
+use crate::{
+    check_grid, mesh_from_samples, ElevationInput, MeshComputeData, MeshError, MeshOptions,
+};
+
+const NONE: usize = usize::MAX;
+
+/// Greedy Delaunay simplification of a height grid (Garland & Heckbert 1995,
+/// as in Mapbox's Delatin).
+///
+/// Starting from the two triangles spanning the grid, the sample with the
+/// largest vertical error is inserted and the triangulation kept Delaunay,
+/// until no sample is more than `max_error` metres off or the mesh has
+/// `max_triangles` triangles (0 for no budget). Unlike `RtinTerrain` the
+/// vertices are not tied to a hierarchy, so flat areas take very few
+/// triangles. Voids are filled or masked as for `mesh_compute`; masked voids
+/// are left out of the error, and a triangle with a void corner is refined
+/// until it covers no valid sample, so the mesh reaches the valid samples
+/// around a void and the triangles touching it are dropped.
+#[wasm_bindgen]
+pub fn mesh_compute_delaunay(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    max_error: f32,
+    max_triangles: usize,
+    options: &MeshOptions,
+) -> Result<MeshComputeData, JsError> {
+    Ok(delaunay_mesh(
+        elevations,
+        width,
+        height,
+        max_error,
+        max_triangles,
+        options,
+    )?)
+}
+
+/// Native counterpart of `mesh_compute_delaunay`.
+pub fn delaunay_mesh(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    max_error: f32,
+    max_triangles: usize,
+    options: &MeshOptions,
+) -> Result<MeshComputeData, MeshError> {
+    check_grid(elevations, width, height)?;
+    // Below zero (or NaN) no triangle is ever good enough, and refining
+    // would insert existing samples again until the vertex budget runs out
+    if !(max_error.is_finite() && max_error >= 0.0) {
+        return Err(MeshError::InvalidMaxError(max_error));
+    }
+
+    let mut heights = mask_nodata(elevations, options.nodata);
+    fill_voids_in_place(&mut heights, width, height, options.void_fill);
+
+    let metres_per_unit = match options.elevation_input {
+        ElevationInput::Normalized => options.metres_per_unit,
+        ElevationInput::Metres => 1.0,
+    };
+    let mut tin = Delatin::new(&heights, width, height, metres_per_unit);
+    tin.run(max_error, max_triangles, options.max_vertices);
+
+    let samples: Vec<usize> = tin.coords.iter().map(|&(x, y)| y * width + x).collect();
+    // Delatin winds its triangles opposite to the grid triangles of
+    // `mesh_compute`; swap two corners so faces point up the same way.
+    let indices: Vec<u32> = tin
+        .triangles
+        .chunks_exact(3)
+        .flat_map(|t| [t[0] as u32, t[2] as u32, t[1] as u32])
+        .collect();
+
+    Ok(mesh_from_samples(
+        &heights,
+        width,
+        height,
+        (width, height),
+        &samples,
+        indices,
+        options,
+    ))
+}
+
+/// Incremental Delaunay triangulation of grid samples. Triangles are stored
+/// as vertex triples with a half-edge twin table; every triangle has a
+/// candidate (its worst sample) kept in a max-heap on the error.
+struct Delatin<'a> {
+    heights: &'a [f32],
+    width: usize,
+    metres_per_unit: f32,
+    coords: Vec<(usize, usize)>,
+    triangles: Vec<usize>,
+    halfedges: Vec<usize>,
+    candidates: Vec<(usize, usize)>,
+    /// Heap slot of each triangle, `NONE` when not queued.
+    queue_indices: Vec<usize>,
+    queue: Vec<usize>,
+    errors: Vec<f32>,
+    /// Triangles whose candidate must be (re)computed.
+    pending: Vec<usize>,
+}
+
+impl<'a> Delatin<'a> {
+    fn new(heights: &'a [f32], width: usize, height: usize, metres_per_unit: f32) -> Self {
+        let mut tin = Delatin {
+            heights,
+            width,
+            metres_per_unit,
+            coords: Vec::new(),
+            triangles: Vec::new(),
+            halfedges: Vec::new(),
+            candidates: Vec::new(),
+            queue_indices: Vec::new(),
+            queue: Vec::new(),
+            errors: Vec::new(),
+            pending: Vec::new(),
+        };
+        let (x1, y1) = (width - 1, height - 1);
+        let p0 = tin.add_point(0, 0);
+        let p1 = tin.add_point(x1, 0);
+        let p2 = tin.add_point(0, y1);
+        let p3 = tin.add_point(x1, y1);
+        let t0 = tin.add_triangle(p3, p0, p2, NONE, NONE, NONE, None);
+        tin.add_triangle(p0, p3, p1, t0, NONE, NONE, None);
+        tin.flush();
+        tin
+    }
+
+    fn run(&mut self, max_error: f32, max_triangles: usize, max_vertices: usize) {
+        while self.max_error() > max_error
+            && (max_triangles == 0 || self.triangles.len() / 3 < max_triangles)
+            && self.coords.len() < max_vertices
+        {
+            self.step();
+            self.flush();
+        }
+    }
+
+    fn max_error(&self) -> f32 {
+        self.errors.first().copied().unwrap_or(0.0)
+    }
+
+    fn height_at(&self, x: usize, y: usize) -> f32 {
+        self.heights[y * self.width + x] * self.metres_per_unit
+    }
+
+    fn flush(&mut self) {
+        for t in std::mem::take(&mut self.pending) {
+            self.find_candidate(t);
+        }
+    }
+
+    /// Rasterize triangle `t` over the grid and queue the sample farthest
+    /// from its plane. Voids are skipped; if a corner is a void the plane is
+    /// undefined and the first valid sample inside is queued with an
+    /// infinite error instead.
+    fn find_candidate(&mut self, t: usize) {
+        let point = |i: usize| {
+            let (x, y) = self.coords[self.triangles[t * 3 + i]];
+            (x as i64, y as i64)
+        };
+        let (p0, p1, p2) = (point(0), point(1), point(2));
+
+        let min_x = p0.0.min(p1.0).min(p2.0);
+        let min_y = p0.1.min(p1.1).min(p2.1);
+        let max_x = p0.0.max(p1.0).max(p2.0);
+        let max_y = p0.1.max(p1.1).max(p2.1);
+
+        // Edge functions at the bounding box corner and their steps
+        let mut w00 = orient(p1, p2, (min_x, min_y));
+        let mut w01 = orient(p2, p0, (min_x, min_y));
+        let mut w02 = orient(p0, p1, (min_x, min_y));
+        let (a01, b01) = (p1.1 - p0.1, p0.0 - p1.0);
+        let (a12, b12) = (p2.1 - p1.1, p1.0 - p2.0);
+        let (a20, b20) = (p0.1 - p2.1, p2.0 - p0.0);
+
+        let area = orient(p0, p1, p2) as f32;
+        let z = |(x, y): (i64, i64)| self.height_at(x as usize, y as usize) / area;
+        let (z0, z1, z2) = (z(p0), z(p1), z(p2));
+        let void_corner = z0.is_nan() || z1.is_nan() || z2.is_nan();
+        let corner = |p: (i64, i64)| p == p0 || p == p1 || p == p2;
+
+        let mut max_error = 0.0f32;
+        let mut best = (p0.0, p0.1);
+        for y in min_y..=max_y {
+            // Skip to the first column that can be inside
+            let mut dx = 0;
+            if w00 < 0 && a12 != 0 {
+                dx = dx.max(-w00 / a12);
+            }
+            if w01 < 0 && a20 != 0 {
+                dx = dx.max(-w01 / a20);
+            }
+            if w02 < 0 && a01 != 0 {
+                dx = dx.max(-w02 / a01);
+            }
+            let mut w0 = w00 + a12 * dx;
+            let mut w1 = w01 + a20 * dx;
+            let mut w2 = w02 + a01 * dx;
+
+            let mut was_inside = false;
+            for x in min_x + dx..=max_x {
+                if w0 >= 0 && w1 >= 0 && w2 >= 0 {
+                    was_inside = true;
+                    let z = self.height_at(x as usize, y as usize);
+                    if !z.is_nan() && !corner((x, y)) {
+                        let error = if void_corner {
+                            f32::INFINITY
+                        } else {
+                            let plane = z0 * w0 as f32 + z1 * w1 as f32 + z2 * w2 as f32;
+                            (plane - z).abs()
+                        };
+                        if error > max_error {
+                            max_error = error;
+                            best = (x, y);
+                        }
+                    }
+                } else if was_inside {
+                    break;
+                }
+                w0 += a12;
+                w1 += a20;
+                w2 += a01;
+            }
+
+            w00 += b12;
+            w01 += b20;
+            w02 += b01;
+        }
+
+        self.candidates[t] = (best.0 as usize, best.1 as usize);
+        self.queue_push(t, max_error);
+    }
+
+    /// Insert the candidate of the worst triangle.
+    fn step(&mut self) {
+        let t = self.queue_pop();
+        let (e0, e1, e2) = (t * 3, t * 3 + 1, t * 3 + 2);
+        let (p0, p1, p2) = (self.triangles[e0], self.triangles[e1], self.triangles[e2]);
+        let at = |p: usize| (self.coords[p].0 as i64, self.coords[p].1 as i64);
+        let (a, b, c) = (at(p0), at(p1), at(p2));
+        let (px, py) = self.candidates[t];
+        let p = (px as i64, py as i64);
+        let pn = self.add_point(px, py);
+
+        if orient(a, b, p) == 0 {
+            self.handle_collinear(pn, e0);
+        } else if orient(b, c, p) == 0 {
+            self.handle_collinear(pn, e1);
+        } else if orient(c, a, p) == 0 {
+            self.handle_collinear(pn, e2);
+        } else {
+            let (h0, h1, h2) = (self.halfedges[e0], self.halfedges[e1], self.halfedges[e2]);
+            let t0 = self.add_triangle(p0, p1, pn, h0, NONE, NONE, Some(e0));
+            let t1 = self.add_triangle(p1, p2, pn, h1, NONE, t0 + 1, None);
+            let t2 = self.add_triangle(p2, p0, pn, h2, t0 + 2, t1 + 1, None);
+            self.legalize(t0);
+            self.legalize(t1);
+            self.legalize(t2);
+        }
+    }
+
+    fn add_point(&mut self, x: usize, y: usize) -> usize {
+        self.coords.push((x, y));
+        self.coords.len() - 1
+    }
+
+    /// Write a triangle at edge slot `e` (appending when `None`), linking its
+    /// half-edges to the given twins. Returns its first edge.
+    #[allow(clippy::too_many_arguments)]
+    fn add_triangle(
+        &mut self,
+        a: usize,
+        b: usize,
+        c: usize,
+        ab: usize,
+        bc: usize,
+        ca: usize,
+        e: Option<usize>,
+    ) -> usize {
+        let e = e.unwrap_or(self.triangles.len());
+        let t = e / 3;
+        if e == self.triangles.len() {
+            self.triangles.extend_from_slice(&[a, b, c]);
+            self.halfedges.extend_from_slice(&[ab, bc, ca]);
+            self.candidates.push((0, 0));
+            self.queue_indices.push(NONE);
+        } else {
+            self.triangles[e..e + 3].copy_from_slice(&[a, b, c]);
+            self.halfedges[e..e + 3].copy_from_slice(&[ab, bc, ca]);
+            self.candidates[t] = (0, 0);
+            self.queue_indices[t] = NONE;
+        }
+        for (edge, twin) in [(e, ab), (e + 1, bc), (e + 2, ca)] {
+            if twin != NONE {
+                self.halfedges[twin] = edge;
+            }
+        }
+        self.pending.push(t);
+        e
+    }
+
+    /// Flip edge `a` if it violates the Delaunay condition, recursively.
+    fn legalize(&mut self, a: usize) {
+        let b = self.halfedges[a];
+        if b == NONE {
+            return;
+        }
+        let (a0, b0) = (a - a % 3, b - b % 3);
+        let al = a0 + (a + 1) % 3;
+        let ar = a0 + (a + 2) % 3;
+        let bl = b0 + (b + 2) % 3;
+        let br = b0 + (b + 1) % 3;
+        let p0 = self.triangles[ar];
+        let pr = self.triangles[a];
+        let pl = self.triangles[al];
+        let p1 = self.triangles[bl];
+
+        let at = |p: usize| (self.coords[p].0 as f64, self.coords[p].1 as f64);
+        if !in_circle(at(p0), at(pr), at(pl), at(p1)) {
+            return;
+        }
+
+        let (hal, har) = (self.halfedges[al], self.halfedges[ar]);
+        let (hbl, hbr) = (self.halfedges[bl], self.halfedges[br]);
+        self.queue_remove(a0 / 3);
+        self.queue_remove(b0 / 3);
+
+        let t0 = self.add_triangle(p0, p1, pl, NONE, hbl, hal, Some(a0));
+        let t1 = self.add_triangle(p1, p0, pr, t0, har, hbr, Some(b0));
+        self.legalize(t0 + 1);
+        self.legalize(t1 + 2);
+    }
+
+    /// Split along edge `a` when the new point `pn` lies on it.
+    fn handle_collinear(&mut self, pn: usize, a: usize) {
+        let a0 = a - a % 3;
+        let al = a0 + (a + 1) % 3;
+        let ar = a0 + (a + 2) % 3;
+        let p0 = self.triangles[ar];
+        let pr = self.triangles[a];
+        let pl = self.triangles[al];
+        let (hal, har) = (self.halfedges[al], self.halfedges[ar]);
+
+        let b = self.halfedges[a];
+        if b == NONE {
+            let t0 = self.add_triangle(pn, p0, pr, NONE, har, NONE, Some(a0));
+            let t1 = self.add_triangle(p0, pn, pl, t0, NONE, hal, None);
+            self.legalize(t0 + 1);
+            self.legalize(t1 + 2);
+            return;
+        }
+
+        let b0 = b - b % 3;
+        let bl = b0 + (b + 2) % 3;
+        let br = b0 + (b + 1) % 3;
+        let p1 = self.triangles[bl];
+        let (hbl, hbr) = (self.halfedges[bl], self.halfedges[br]);
+        self.queue_remove(b0 / 3);
+
+        let t0 = self.add_triangle(p0, pr, pn, har, NONE, NONE, Some(a0));
+        let t1 = self.add_triangle(pr, p1, pn, hbr, NONE, t0 + 1, Some(b0));
+        let t2 = self.add_triangle(p1, pl, pn, hbl, NONE, t1 + 1, None);
+        let t3 = self.add_triangle(pl, p0, pn, hal, t0 + 2, t2 + 1, None);
+        self.legalize(t0);
+        self.legalize(t1);
+        self.legalize(t2);
+        self.legalize(t3);
+    }
+
+    fn queue_push(&mut self, t: usize, error: f32) {
+        let i = self.queue.len();
+        self.queue_indices[t] = i;
+        self.queue.push(t);
+        self.errors.push(error);
+        self.queue_up(i);
+    }
+
+    fn queue_pop(&mut self) -> usize {
+        let n = self.queue.len() - 1;
+        self.queue_swap(0, n);
+        self.queue_down(0, n);
+        self.queue_pop_back()
+    }
+
+    fn queue_pop_back(&mut self) -> usize {
+        let t = self.queue.pop().expect("queue is not empty");
+        self.errors.pop();
+        self.queue_indices[t] = NONE;
+        t
+    }
+
+    /// Drop triangle `t` from the heap, or from the pending list if its
+    /// candidate has not been computed yet.
+    fn queue_remove(&mut self, t: usize) {
+        let i = self.queue_indices[t];
+        if i == NONE {
+            if let Some(pos) = self.pending.iter().position(|&p| p == t) {
+                self.pending.swap_remove(pos);
+            }
+            return;
+        }
+        let n = self.queue.len() - 1;
+        if n != i {
+            self.queue_swap(i, n);
+            if !self.queue_down(i, n) {
+                self.queue_up(i);
+            }
+        }
+        self.queue_pop_back();
+    }
+
+    /// Whether slot `i` belongs above slot `j`; a NaN error (only possible
+    /// from a NaN `metres_per_unit`) ranks above any other.
+    fn queue_less(&self, i: usize, j: usize) -> bool {
+        self.errors[i].total_cmp(&self.errors[j]).is_gt()
+    }
+
+    fn queue_swap(&mut self, i: usize, j: usize) {
+        let (pi, pj) = (self.queue[i], self.queue[j]);
+        self.queue.swap(i, j);
+        self.errors.swap(i, j);
+        self.queue_indices[pi] = j;
+        self.queue_indices[pj] = i;
+    }
+
+    fn queue_up(&mut self, mut j: usize) {
+        while j > 0 {
+            let i = (j - 1) / 2;
+            if !self.queue_less(j, i) {
+                break;
+            }
+            self.queue_swap(i, j);
+            j = i;
+        }
+    }
+
+    /// Sift down within the first `n` slots; true if the entry moved.
+    fn queue_down(&mut self, i0: usize, n: usize) -> bool {
+        let mut i = i0;
+        loop {
+            let j1 = 2 * i + 1;
+            if j1 >= n {
+                break;
+            }
+            let j2 = j1 + 1;
+            let j = if j2 < n && self.queue_less(j2, j1) {
+                j2
+            } else {
+                j1
+            };
+            if !self.queue_less(j, i) {
+                break;
+            }
+            self.queue_swap(i, j);
+            i = j;
+        }
+        i > i0
+    }
+}
+
+/// Twice the signed area of (a, b, c), positive when counter-clockwise in a
+/// y-down grid.
+fn orient(a: (i64, i64), b: (i64, i64), c: (i64, i64)) -> i64 {
+    (b.0 - c.0) * (a.1 - c.1) - (b.1 - c.1) * (a.0 - c.0)
+}
+
+/// Whether `p` lies inside the circumcircle of (a, b, c).
+fn in_circle(a: (f64, f64), b: (f64, f64), c: (f64, f64), p: (f64, f64)) -> bool {
+    let (dx, dy) = (a.0 - p.0, a.1 - p.1);
+    let (ex, ey) = (b.0 - p.0, b.1 - p.1);
+    let (fx, fy) = (c.0 - p.0, c.1 - p.1);
+    let ap = dx * dx + dy * dy;
+    let bp = ex * ex + ey * ey;
+    let cp = fx * fx + fy * fy;
+    dx * (ey * cp - bp * fy) - dy * (ex * cp - bp * fx) + ap * (ex * fy - ey * fx) < 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Triangles of `tin` with every corner valid, as grid coordinates.
+    fn valid_triangles(tin: &Delatin) -> Vec<[(f32, f32); 3]> {
+        tin.triangles
+            .chunks_exact(3)
+            .filter(|t| {
+                t.iter()
+                    .all(|&p| !tin.height_at(tin.coords[p].0, tin.coords[p].1).is_nan())
+            })
+            .map(|t| {
+                let at = |p: usize| (tin.coords[p].0 as f32, tin.coords[p].1 as f32);
+                [at(t[0]), at(t[1]), at(t[2])]
+            })
+            .collect()
+    }
+
+    fn area([a, b, c]: [(f32, f32); 3]) -> f32 {
+        ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() * 0.5
+    }
+
+    #[test]
+    fn a_plane_needs_only_the_seed_triangles() {
+        let heights: Vec<f32> = (0..25 * 17).map(|i| (i % 25) as f32 * 0.3).collect();
+        let mut tin = Delatin::new(&heights, 25, 17, 1.0);
+        tin.run(1e-3, 0, usize::MAX);
+        assert_eq!(tin.coords.len(), 4);
+        assert_eq!(tin.triangles.len(), 6);
+    }
+
+    #[test]
+    fn every_sample_is_within_the_error() {
+        let (width, height) = (21, 14);
+        let heights: Vec<f32> = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                (x * 0.9).sin() + (y * 0.6).cos() * 0.5
+            })
+            .collect();
+        let mut tin = Delatin::new(&heights, width, height, 1.0);
+        tin.run(0.1, 0, usize::MAX);
+        assert!(tin.max_error() <= 0.1);
+
+        // Every sample lies in some triangle, within the error of its plane
+        let z = |(x, y): (f32, f32)| heights[y as usize * width + x as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let p = (x as f32, y as f32);
+                let inside = valid_triangles(&tin).into_iter().find_map(|[a, b, c]| {
+                    let total = area([a, b, c]);
+                    let wa = area([p, b, c]) / total;
+                    let wb = area([a, p, c]) / total;
+                    let wc = area([a, b, p]) / total;
+                    ((wa + wb + wc - 1.0).abs() < 1e-4).then(|| wa * z(a) + wb * z(b) + wc * z(c))
+                });
+                let plane = inside.expect("sample outside the mesh");
+                assert!((plane - z(p)).abs() <= 0.1 + 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn void_corners_are_meshed_around() {
+        // A void at the first corner used to make every error NaN and stop
+        // at the two seed triangles, both dropped
+        let mut heights: Vec<f32> = (0..33 * 33)
+            .map(|i| ((i % 33) as f32 * 0.2).sin() * 0.1)
+            .collect();
+        heights[0] = f32::NAN;
+        let mut tin = Delatin::new(&heights, 33, 33, 1.0);
+        tin.run(0.01, 0, usize::MAX);
+        assert!(tin.max_error() <= 0.01);
+        let covered: f32 = valid_triangles(&tin).into_iter().map(area).sum();
+        assert!(covered >= 32.0 * 32.0 - 1.0, "covered {covered}");
+
+        let mesh = delaunay_mesh(&heights, 33, 33, 0.01, 0, &MeshOptions::default()).unwrap();
+        assert!(!mesh.indices.is_empty());
+        assert!(mesh
+            .indices
+            .iter()
+            .all(|&i| mesh.vertices[i as usize * 3 + 2].is_finite()));
+    }
+
+    #[test]
+    fn nan_errors_rank_first_in_the_queue() {
+        let heights = [0.0; 4];
+        let mut tin = Delatin::new(&heights, 2, 2, 1.0);
+        while !tin.queue.is_empty() {
+            tin.queue_pop();
+        }
+        for (t, error) in [(0, 1.0), (1, f32::NAN)] {
+            tin.queue_push(t, error);
+        }
+        assert_eq!(tin.queue_pop(), 1);
+        assert_eq!(tin.queue_pop(), 0);
+    }
+
+    #[test]
+    fn rejects_undefined_error_bounds() {
+        let heights = [0.0, 1.0, 2.0, 3.0];
+        for max_error in [-0.1, f32::NAN, f32::INFINITY] {
+            let result = delaunay_mesh(&heights, 2, 2, max_error, 0, &MeshOptions::default());
+            assert!(matches!(result, Err(MeshError::InvalidMaxError(_))));
+        }
+        assert!(delaunay_mesh(&heights, 2, 2, 0.0, 0, &MeshOptions::default()).is_ok());
+    }
+}
@@ -0,0 +1,516 @@
+use wasm_bindgen::prelude::*;
+
+use crate::check_grid;
+use crate::hydrology::{neighbours, D8_OFFSETS};
+
+/// Largest `ErosionOptions::erosion_radius`; the brush covers
+/// (2r + 1)² cells and every droplet step walks it.
+pub const MAX_EROSION_RADIUS: f32 = 16.0;
+
+/// Parameters for `Erosion`. The droplet defaults follow Beyer (2015) and
+/// suit heights of the order of a cell, e.g. normalized 0..1 heights on a
+/// few hundred cells; scale `capacity` with the height range otherwise.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ErosionOptions {
+    /// Seed for the droplet start positions. The same seed, grid and number
+    /// of droplets always give the same terrain, however the droplets are
+    /// split across calls.
+    pub seed: u32,
+    /// How much of its previous direction a droplet keeps (0..1); higher
+    /// values carve straighter channels.
+    pub inertia: f32,
+    /// Sediment a droplet can carry per unit of speed, water and drop.
+    pub capacity: f32,
+    /// Floor on the carrying capacity, so droplets still erode on gentle
+    /// slopes.
+    pub min_capacity: f32,
+    /// Share of the excess sediment dropped per step (0..1).
+    pub deposition: f32,
+    /// Share of the spare capacity eroded per step (0..1).
+    pub erosion: f32,
+    /// Share of the water evaporating per step (0..1).
+    pub evaporation: f32,
+    pub gravity: f32,
+    /// Steps before a droplet is dropped.
+    pub max_lifetime: usize,
+    /// Radius in cells over which a droplet erodes, for smoother channels
+    /// (0..`MAX_EROSION_RADIUS`).
+    pub erosion_radius: f32,
+    /// Steepest slope loose material rests at, in degrees, for `thermal`.
+    pub talus_angle: f32,
+    /// Share of the material above the talus slope moved per `thermal`
+    /// iteration (0..1).
+    pub thermal_rate: f32,
+    /// Horizontal distance between samples in the elevation unit, for the
+    /// talus slope: the cell size in metres for metre heights, or e.g.
+    /// 1 / (width - 1) for heights normalized to a unit-wide terrain.
+    pub cell_size_x: f32,
+    pub cell_size_y: f32,
+    /// Elevation marking missing samples. Voids are never changed; droplets
+    /// stop at them and material does not slide into them.
+    pub nodata: Option<f32>,
+}
+
+#[wasm_bindgen]
+impl ErosionOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ErosionOptions {
+        ErosionOptions::default()
+    }
+}
+
+impl ErosionOptions {
+    /// Why these options cannot run the simulation, if they cannot.
+    fn invalid(&self) -> Option<&'static str> {
+        if !(0.0..=MAX_EROSION_RADIUS).contains(&self.erosion_radius) {
+            // The brush grows with the square of the radius
+            return Some("erosion: erosion_radius must be between 0 and MAX_EROSION_RADIUS");
+        }
+        // Outside 0..1 these overshoot: droplets gain water or sediment
+        // from nowhere and thermal slides pile up instead of settling
+        let shares = [
+            (self.inertia, "erosion: inertia must be between 0 and 1"),
+            (
+                self.deposition,
+                "erosion: deposition must be between 0 and 1",
+            ),
+            (self.erosion, "erosion: erosion must be between 0 and 1"),
+            (
+                self.evaporation,
+                "erosion: evaporation must be between 0 and 1",
+            ),
+            (
+                self.thermal_rate,
+                "erosion: thermal_rate must be between 0 and 1",
+            ),
+        ];
+        shares
+            .into_iter()
+            .find(|(share, _)| !(0.0..=1.0).contains(share))
+            .map(|(_, why)| why)
+    }
+}
+
+impl Default for ErosionOptions {
+    fn default() -> Self {
+        ErosionOptions {
+            seed: 1,
+            inertia: 0.05,
+            capacity: 4.0,
+            min_capacity: 0.01,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.01,
+            gravity: 4.0,
+            max_lifetime: 30,
+            erosion_radius: 3.0,
+            talus_angle: 35.0,
+            thermal_rate: 0.5,
+            cell_size_x: 1.0,
+            cell_size_y: 1.0,
+            nodata: None,
+        }
+    }
+}
+
+/// Incremental hydraulic and thermal erosion of a `width` × `height`
+/// elevation grid, changed in place so every call can be followed by a new
+/// `mesh_compute` to show the terrain evolving. The simulation keeps count
+/// of the droplets run so far: droplet `n` always starts at the same spot
+/// for a given seed, and 100 calls of 10 droplets equal one call of 1000.
+#[wasm_bindgen]
+pub struct Erosion {
+    width: usize,
+    height: usize,
+    options: ErosionOptions,
+    droplet_count: u64,
+    /// Offsets and weights of the cells a droplet erodes from, around the
+    /// cell it is in.
+    brush: Vec<(isize, isize, f32)>,
+}
+
+/// Height and gradient of the bilinear surface at a point.
+struct Surface {
+    height: f32,
+    gradient_x: f32,
+    gradient_y: f32,
+}
+
+#[wasm_bindgen]
+impl Erosion {
+    /// Fails if a rate or the erosion radius is outside its range.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize, options: &ErosionOptions) -> Result<Erosion, JsError> {
+        if let Some(why) = options.invalid() {
+            return Err(JsError::new(why));
+        }
+        let radius = options.erosion_radius.max(0.5);
+        let reach = radius.ceil() as isize;
+        let mut brush = Vec::new();
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let weight = radius - (dx as f32).hypot(dy as f32);
+                if weight > 0.0 {
+                    brush.push((dx, dy, weight));
+                }
+            }
+        }
+        Ok(Erosion {
+            width,
+            height,
+            options: options.clone(),
+            droplet_count: 0,
+            brush,
+        })
+    }
+
+    /// Droplets simulated so far.
+    #[wasm_bindgen(getter)]
+    pub fn droplet_count(&self) -> f64 {
+        self.droplet_count as f64
+    }
+
+    /// Run `droplets` more droplets of particle-based hydraulic erosion
+    /// (Beyer 2015) over `elevations`: each starts at a random spot, runs
+    /// downhill picking up sediment where it speeds up and dropping it where
+    /// it slows, and evaporates after `max_lifetime` steps.
+    pub fn hydraulic(&mut self, elevations: &mut [f32], droplets: usize) -> Result<(), JsError> {
+        check_grid(elevations, self.width, self.height)?;
+        for _ in 0..droplets {
+            let mut rng = SplitMix64::new(self.options.seed, self.droplet_count);
+            self.droplet_count += 1;
+            let x = rng.next_f32() * (self.width - 1) as f32;
+            let y = rng.next_f32() * (self.height - 1) as f32;
+            self.run_droplet(elevations, x, y);
+        }
+        Ok(())
+    }
+
+    /// Run `iterations` passes of thermal erosion over `elevations`: wherever
+    /// the slope to a neighbour is steeper than `talus_angle`, part of the
+    /// excess slides down to it, spread over all such neighbours by their
+    /// excess (Musgrave et al. 1989). Each pass reads the grid as it was at
+    /// its start, so the result does not depend on the scan order.
+    pub fn thermal(&self, elevations: &mut [f32], iterations: usize) -> Result<(), JsError> {
+        check_grid(elevations, self.width, self.height)?;
+        let (width, height) = (self.width, self.height);
+        let options = &self.options;
+        let talus = options.talus_angle.to_radians().tan();
+        let thresholds = D8_OFFSETS.map(|(dx, dy)| {
+            talus * (dx as f32 * options.cell_size_x).hypot(dy as f32 * options.cell_size_y)
+        });
+
+        let mut change = vec![0.0f32; elevations.len()];
+        let mut excess = Vec::with_capacity(8);
+        for _ in 0..iterations {
+            change.fill(0.0);
+            for i in 0..elevations.len() {
+                let z = elevations[i];
+                if !self.is_valid(z) {
+                    continue;
+                }
+                excess.clear();
+                for (k, n) in neighbours(i % width, i / width, width, height) {
+                    let over = z - elevations[n] - thresholds[k];
+                    if self.is_valid(elevations[n]) && over > 0.0 {
+                        excess.push((n, over));
+                    }
+                }
+                let total: f32 = excess.iter().map(|&(_, over)| over).sum();
+                let Some(largest) = excess.iter().map(|&(_, over)| over).reduce(f32::max) else {
+                    continue;
+                };
+                // Half the largest excess levels that pair out
+                let moved = options.thermal_rate * largest * 0.5;
+                for &(n, over) in &excess {
+                    let share = moved * over / total;
+                    change[i] -= share;
+                    change[n] += share;
+                }
+            }
+            for (z, dz) in elevations.iter_mut().zip(&change) {
+                *z += dz;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Erosion {
+    fn is_valid(&self, z: f32) -> bool {
+        z.is_finite() && Some(z) != self.options.nodata
+    }
+
+    /// Bilinear height and gradient at (`x`, `y`), which must lie inside the
+    /// grid with room for the next sample right and below.
+    fn surface(&self, elevations: &[f32], x: f32, y: f32) -> Option<Surface> {
+        let (cx, cy) = (x as usize, y as usize);
+        let (u, v) = (x - cx as f32, y - cy as f32);
+        let i = cy * self.width + cx;
+        let corners = [
+            elevations[i],
+            elevations[i + 1],
+            elevations[i + self.width],
+            elevations[i + self.width + 1],
+        ];
+        if !corners.iter().all(|&z| self.is_valid(z)) {
+            return None;
+        }
+        let [nw, ne, sw, se] = corners;
+        Some(Surface {
+            height: nw * (1.0 - u) * (1.0 - v)
+                + ne * u * (1.0 - v)
+                + sw * (1.0 - u) * v
+                + se * u * v,
+            gradient_x: (ne - nw) * (1.0 - v) + (se - sw) * v,
+            gradient_y: (sw - nw) * (1.0 - u) + (se - ne) * u,
+        })
+    }
+
+    fn run_droplet(&self, elevations: &mut [f32], mut x: f32, mut y: f32) {
+        let options = &self.options;
+        let (max_x, max_y) = ((self.width - 1) as f32, (self.height - 1) as f32);
+        let (mut dir_x, mut dir_y) = (0.0f32, 0.0f32);
+        let (mut speed, mut water, mut sediment) = (1.0f32, 1.0f32, 0.0f32);
+
+        for _ in 0..options.max_lifetime {
+            let Some(here) = self.surface(elevations, x, y) else {
+                return;
+            };
+            let (cx, cy) = (x as usize, y as usize);
+            let (u, v) = (x - cx as f32, y - cy as f32);
+
+            dir_x = dir_x * options.inertia - here.gradient_x * (1.0 - options.inertia);
+            dir_y = dir_y * options.inertia - here.gradient_y * (1.0 - options.inertia);
+            let length = dir_x.hypot(dir_y);
+            if length == 0.0 {
+                // Stuck on a flat
+                return;
+            }
+            dir_x /= length;
+            dir_y /= length;
+            x += dir_x;
+            y += dir_y;
+            // Leaving the grid (or the last row and column, which have no
+            // cell to interpolate in) ends the droplet with its sediment
+            if !(x >= 0.0 && y >= 0.0 && x < max_x && y < max_y) {
+                return;
+            }
+            let Some(next) = self.surface(elevations, x, y) else {
+                return;
+            };
+
+            let drop = here.height - next.height;
+            let capacity = (drop * speed * water * options.capacity).max(options.min_capacity);
+            if drop < 0.0 || sediment > capacity {
+                // Uphill the droplet fills the pit it left (no higher than
+                // the rise); otherwise it sheds part of the excess
+                let deposit = if drop < 0.0 {
+                    sediment.min(-drop)
+                } else {
+                    (sediment - capacity) * options.deposition
+                };
+                sediment -= deposit;
+                let i = cy * self.width + cx;
+                elevations[i] += deposit * (1.0 - u) * (1.0 - v);
+                elevations[i + 1] += deposit * u * (1.0 - v);
+                elevations[i + self.width] += deposit * (1.0 - u) * v;
+                elevations[i + self.width + 1] += deposit * u * v;
+            } else {
+                // Never dig deeper than the drop, or the droplet would carve
+                // a pit behind it
+                let amount = ((capacity - sediment) * options.erosion).min(drop);
+                sediment += self.erode(elevations, cx, cy, amount);
+            }
+
+            speed = (speed * speed + drop * options.gravity).max(0.0).sqrt();
+            water *= 1.0 - options.evaporation;
+        }
+    }
+
+    /// Take up to `amount` from the valid cells under the brush centred on
+    /// (`cx`, `cy`), by brush weight; returns what was taken.
+    fn erode(&self, elevations: &mut [f32], cx: usize, cy: usize, amount: f32) -> f32 {
+        let cells: Vec<(usize, f32)> = self
+            .brush
+            .iter()
+            .filter_map(|&(dx, dy, weight)| {
+                let x = cx.checked_add_signed(dx).filter(|&x| x < self.width)?;
+                let y = cy.checked_add_signed(dy).filter(|&y| y < self.height)?;
+                let i = y * self.width + x;
+                self.is_valid(elevations[i]).then_some((i, weight))
+            })
+            .collect();
+        let total: f32 = cells.iter().map(|&(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        for (i, weight) in cells {
+            elevations[i] -= amount * weight / total;
+        }
+        amount
+    }
+}
+
+/// SplitMix64 (Steele et al. 2014), seeded per droplet so droplet `n` does
+/// not depend on how many were run before it.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u32, stream: u64) -> SplitMix64 {
+        let mut rng = SplitMix64(((seed as u64) << 32) ^ stream);
+        rng.next_u64();
+        rng
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(elevations: &[f32]) -> f64 {
+        elevations.iter().map(|&z| z as f64).sum()
+    }
+
+    /// `size` × `size` cone, 1 at the centre falling to 0 at the edges.
+    fn cone(size: usize) -> Vec<f32> {
+        let c = (size - 1) as f32 / 2.0;
+        (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as f32 - c, (i / size) as f32 - c);
+                (1.0 - x.hypot(y) / c).max(0.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn thermal_erosion_moves_material_without_losing_any() {
+        // A spike on a flat, with a void next to it that must stay put
+        let mut z = vec![0.0f32; 49];
+        z[24] = 10.0;
+        z[25] = -9999.0;
+        let options = ErosionOptions {
+            nodata: Some(-9999.0),
+            ..ErosionOptions::default()
+        };
+        let valid = |z: &[f32]| total(&z[..25]) + total(&z[26..]);
+        let before = valid(&z);
+        Erosion::new(7, 7, &options)
+            .unwrap()
+            .thermal(&mut z, 50)
+            .unwrap();
+
+        assert_eq!(z[25], -9999.0);
+        assert!((valid(&z) - before).abs() < 1e-4);
+        assert!(z[24] < 10.0);
+        // Every slope between valid neighbours ends near the talus angle
+        let talus = 35f32.to_radians().tan();
+        for (i, &a) in z.iter().enumerate().filter(|&(i, _)| i != 25) {
+            for (k, n) in neighbours(i % 7, i / 7, 7, 7) {
+                let (dx, dy) = D8_OFFSETS[k];
+                let run = (dx as f32).hypot(dy as f32);
+                if n != 25 {
+                    assert!(a - z[n] < talus * run + 0.05, "slope {i} → {n}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hydraulic_erosion_never_adds_material() {
+        let mut z = cone(64);
+        let before = total(&z);
+        let mut erosion = Erosion::new(64, 64, &ErosionOptions::default()).unwrap();
+        erosion.hydraulic(&mut z, 500).unwrap();
+        assert_eq!(erosion.droplet_count, 500);
+        assert!(z != cone(64));
+        // Sediment only leaves the grid with droplets running off the edge
+        assert!(total(&z) <= before + 1e-3);
+        assert!(z.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn droplets_split_across_calls_give_the_same_terrain() {
+        let options = ErosionOptions {
+            seed: 7,
+            ..ErosionOptions::default()
+        };
+        let mut once = cone(48);
+        Erosion::new(48, 48, &options)
+            .unwrap()
+            .hydraulic(&mut once, 300)
+            .unwrap();
+
+        let mut split = cone(48);
+        let mut erosion = Erosion::new(48, 48, &options).unwrap();
+        for droplets in [1, 99, 200] {
+            erosion.hydraulic(&mut split, droplets).unwrap();
+        }
+        assert_eq!(once, split);
+
+        let mut other = cone(48);
+        let seed = ErosionOptions { seed: 8, ..options };
+        Erosion::new(48, 48, &seed)
+            .unwrap()
+            .hydraulic(&mut other, 300)
+            .unwrap();
+        assert!(other != once);
+    }
+
+    #[test]
+    fn rates_and_radius_must_be_in_range() {
+        assert_eq!(ErosionOptions::default().invalid(), None);
+        let cases = [
+            ErosionOptions {
+                erosion_radius: 1e6,
+                ..ErosionOptions::default()
+            },
+            ErosionOptions {
+                erosion_radius: f32::NAN,
+                ..ErosionOptions::default()
+            },
+            ErosionOptions {
+                inertia: 1.5,
+                ..ErosionOptions::default()
+            },
+            ErosionOptions {
+                evaporation: -0.1,
+                ..ErosionOptions::default()
+            },
+            ErosionOptions {
+                thermal_rate: 2.0,
+                ..ErosionOptions::default()
+            },
+            ErosionOptions {
+                thermal_rate: f32::NAN,
+                ..ErosionOptions::default()
+            },
+        ];
+        for options in cases {
+            assert!(options.invalid().is_some(), "{options:?}");
+        }
+        // The bounds themselves are fine
+        let edges = ErosionOptions {
+            erosion_radius: MAX_EROSION_RADIUS,
+            inertia: 1.0,
+            evaporation: 0.0,
+            thermal_rate: 1.0,
+            ..ErosionOptions::default()
+        };
+        assert_eq!(edges.invalid(), None);
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::BinaryHeap;
+
+use js_sys::Float32Array;
+use wasm_bindgen::prelude::*;
+
+use crate::color::ColorRamp;
+use crate::hydrology::{neighbours, Queued};
+use crate::voids::mask_nodata;
+use crate::{check_grid, MeshComputeData, MeshFrame, MeshOptions};
+
+/// Where water enters a `FloodModel`.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct FloodOptions {
+    /// Elevation marking missing samples. Voids carry water at any level
+    /// (coastal DEMs often store the sea as NoData) but have no depth.
+    pub nodata: Option<f32>,
+    /// Let water in along the whole grid border, e.g. for sea-level rise.
+    pub from_edges: bool,
+    /// Sample positions water starts from, as flat `column, row` pairs
+    /// (see `set_seeds`).
+    #[wasm_bindgen(skip)]
+    pub seeds: Vec<f32>,
+    /// Ramp sampled with the water depth at each vertex of
+    /// `FloodModel.water_surface`; pale at the shore to deep blue at the
+    /// deepest point when unset.
+    #[wasm_bindgen(skip)]
+    pub color_ramp: Option<ColorRamp>,
+}
+
+#[wasm_bindgen]
+impl FloodOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> FloodOptions {
+        FloodOptions::default()
+    }
+
+    /// Seed positions as flat `column, row` pairs in sample coordinates,
+    /// e.g. from `ElevationGrid.lon_lat_to_grid`; rounded to the nearest
+    /// sample.
+    #[wasm_bindgen(setter)]
+    pub fn set_seeds(&mut self, seeds: &[f32]) {
+        self.seeds = seeds.to_vec();
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_color_ramp(&mut self, ramp: &ColorRamp) {
+        self.color_ramp = Some(ramp.clone());
+    }
+}
+
+impl Default for FloodOptions {
+    fn default() -> Self {
+        FloodOptions {
+            nodata: None,
+            from_edges: true,
+            seeds: Vec::new(),
+            color_ramp: None,
+        }
+    }
+}
+
+/// Connected inundation of a heightfield from its seeds, for any water
+/// level.
+///
+/// Building the model runs one priority flood that records, per sample, the
+/// lowest level at which water from a seed reaches it: the minimum over all
+/// paths of the highest ground crossed. A sample is then flooded at `level`
+/// exactly when that spill level is below it, so stepping through levels
+/// (an animation, a slider) is a single pass per level with no new flood.
+/// Water spreads to all eight neighbours; levels are in the elevation unit.
+#[wasm_bindgen]
+pub struct FloodModel {
+    heights: Vec<f32>,
+    width: usize,
+    height: usize,
+    /// Per sample: lowest level that floods it, -∞ for void seeds.
+    spill: Vec<f32>,
+    /// Spill levels of the valid samples, ascending.
+    sorted: Vec<f32>,
+    color_ramp: Option<ColorRamp>,
+}
+
+#[wasm_bindgen]
+impl FloodModel {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        elevations: &[f32],
+        width: usize,
+        height: usize,
+        options: &FloodOptions,
+    ) -> Result<FloodModel, JsError> {
+        check_grid(elevations, width, height)?;
+        if !options.seeds.len().is_multiple_of(2) {
+            return Err(JsError::new("flood seeds must be column, row pairs"));
+        }
+        if options.seeds.is_empty() && !options.from_edges {
+            return Err(JsError::new("flood needs seeds or from_edges"));
+        }
+        let heights = mask_nodata(elevations, options.nodata);
+
+        let mut seeds = Vec::with_capacity(options.seeds.len() / 2);
+        for seed in options.seeds.chunks_exact(2) {
+            let (x, y) = (seed[0].round(), seed[1].round());
+            if !(x >= 0.0 && y >= 0.0 && x < width as f32 && y < height as f32) {
+                return Err(JsError::new(&format!(
+                    "flood seed ({}, {}) is outside the {} × {} grid",
+                    seed[0], seed[1], width, height
+                )));
+            }
+            seeds.push(y as usize * width + x as usize);
+        }
+        if options.from_edges {
+            seeds.extend((0..width).flat_map(|x| [x, (height - 1) * width + x]));
+            seeds.extend((1..height - 1).flat_map(|y| [y * width, y * width + width - 1]));
+        }
+
+        let mut spill = vec![f32::INFINITY; heights.len()];
+        let mut closed = vec![false; heights.len()];
+        let mut queue = BinaryHeap::new();
+        let mut order = 0;
+        for i in seeds {
+            if closed[i] {
+                continue;
+            }
+            closed[i] = true;
+            spill[i] = if heights[i].is_nan() {
+                f32::NEG_INFINITY
+            } else {
+                heights[i]
+            };
+            queue.push(Queued {
+                z: spill[i],
+                order,
+                index: i,
+            });
+            order += 1;
+        }
+
+        while let Some(cell) = queue.pop() {
+            let (x, y) = (cell.index % width, cell.index / width);
+            for (_, n) in neighbours(x, y, width, height) {
+                if closed[n] {
+                    continue;
+                }
+                closed[n] = true;
+                // NaN voids lose to the level water arrives at
+                spill[n] = heights[n].max(cell.z);
+                queue.push(Queued {
+                    z: spill[n],
+                    order,
+                    index: n,
+                });
+                order += 1;
+            }
+        }
+
+        let mut sorted: Vec<f32> = spill
+            .iter()
+            .zip(&heights)
+            .filter(|(_, z)| !z.is_nan())
+            .map(|(&s, _)| s)
+            .collect();
+        sorted.sort_unstable_by(f32::total_cmp);
+
+        Ok(FloodModel {
+            heights,
+            width,
+            height,
+            spill,
+            sorted,
+            color_ramp: options.color_ramp.clone(),
+        })
+    }
+
+    /// Per sample, the lowest water level that floods it (−∞ for voids
+    /// reached straight from a seed or the border).
+    #[wasm_bindgen(getter)]
+    pub fn spill_levels(&self) -> Float32Array {
+        Float32Array::from(self.spill.as_slice())
+    }
+
+    /// Water depth per sample at `level`: above the ground where flooded, 0
+    /// where dry, NaN for voids.
+    pub fn depth(&self, level: f32) -> Vec<f32> {
+        self.spill
+            .iter()
+            .zip(&self.heights)
+            .map(|(&s, &z)| {
+                if z.is_nan() {
+                    f32::NAN
+                } else if s < level {
+                    level - z
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Number of (non-void) samples flooded at `level`.
+    pub fn flooded_count(&self, level: f32) -> usize {
+        self.sorted.partition_point(|&s| s < level)
+    }
+
+    /// Flat water surface at `level` over the flooded area, in the mesh
+    /// space `mesh_compute` uses for the same elevations and `options`, so
+    /// the two can be drawn together. Shorelines are interpolated between
+    /// samples (marching squares), so the water meets the terrain where it
+    /// crosses `level`; next to voids the water reaches the dry sample.
+    pub fn water_surface(&self, level: f32, options: &MeshOptions) -> MeshComputeData {
+        let (width, height) = (self.width, self.height);
+        let range = options.elevation_range(&self.heights);
+        let frame = MeshFrame::new(width, height, range.0, options);
+        let wet = |i: usize| self.spill[i] < level;
+
+        let max_depth = (0..self.heights.len())
+            .filter(|&i| wet(i))
+            .map(|i| level - self.heights[i])
+            .filter(|d| !d.is_nan())
+            .fold(0.0f32, f32::max);
+        let ramp = match &self.color_ramp {
+            Some(ramp) => ramp.clone(),
+            None => ColorRamp::bathymetry().rescaled(-max_depth, 0.0),
+        };
+        let color = |depth: f32| match &self.color_ramp {
+            Some(ramp) => ramp.rgb(depth),
+            None => ramp.rgb(-depth),
+        };
+
+        let mut mesh = MeshComputeData {
+            vertices: Vec::new(),
+            colors: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+            occlusion: Vec::new(),
+            vertex_count: 0,
+            half_extent: frame.half_extent(),
+        };
+        let mut push_vertex = |position: [f32; 3], depth: f32| {
+            mesh.vertices.extend_from_slice(&position);
+            mesh.colors.extend_from_slice(&color(depth));
+            mesh.normals.extend_from_slice(&[0.0, 0.0, 1.0]);
+            mesh.vertex_count += 1;
+            (mesh.vertex_count - 1) as u32
+        };
+
+        // Vertices are shared: one per wet sample, one per shore crossing on
+        // the horizontal edge right of and the vertical edge below a sample
+        const NONE: u32 = u32::MAX;
+        let mut corner_vertex = vec![NONE; width * height];
+        let mut right_vertex = vec![NONE; width * height];
+        let mut down_vertex = vec![NONE; width * height];
+        let mut indices = Vec::new();
+        let mut polygon = Vec::with_capacity(8);
+
+        for y in 0..height - 1 {
+            for x in 0..width - 1 {
+                // Corners clockwise on screen, as the terrain triangles wind
+                let corners = [
+                    y * width + x,
+                    y * width + x + 1,
+                    (y + 1) * width + x + 1,
+                    (y + 1) * width + x,
+                ];
+                if !corners.iter().any(|&i| wet(i)) {
+                    continue;
+                }
+
+                polygon.clear();
+                for k in 0..4 {
+                    let (a, b) = (corners[k], corners[(k + 1) % 4]);
+                    if wet(a) {
+                        if corner_vertex[a] == NONE {
+                            let z = self.heights[a];
+                            let depth = if z.is_nan() { max_depth } else { level - z };
+                            corner_vertex[a] = push_vertex(
+                                frame.position(a % width, a / width, width, height, level),
+                                depth,
+                            );
+                        }
+                        polygon.push(corner_vertex[a]);
+                    }
+                    if wet(a) == wet(b) {
+                        continue;
+                    }
+
+                    // Crossing on the edge from its top-left sample `p` to `q`
+                    let (p, q) = (a.min(b), a.max(b));
+                    let edges = if q == p + 1 {
+                        &mut right_vertex
+                    } else {
+                        &mut down_vertex
+                    };
+                    if edges[p] == NONE {
+                        let t = if self.heights[p].is_nan() || self.heights[q].is_nan() {
+                            if wet(p) {
+                                1.0
+                            } else {
+                                0.0
+                            }
+                        } else {
+                            let (fp, fq) = (level - self.spill[p], level - self.spill[q]);
+                            fp / (fp - fq)
+                        };
+                        let pp = frame.position(p % width, p / width, width, height, level);
+                        let pq = frame.position(q % width, q / width, width, height, level);
+                        edges[p] = push_vertex(
+                            [
+                                pp[0] + (pq[0] - pp[0]) * t,
+                                pp[1] + (pq[1] - pp[1]) * t,
+                                pp[2],
+                            ],
+                            0.0,
+                        );
+                    }
+                    polygon.push(edges[p]);
+                }
+
+                // The polygon's points lie in order on the cell's border, so
+                // it is convex and fans from its first point
+                for k in 1..polygon.len().saturating_sub(1) {
+                    indices.extend_from_slice(&[polygon[0], polygon[k], polygon[k + 1]]);
+                }
+            }
+        }
+
+        mesh.indices = indices;
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 5 × 5 bowl: a floor at 1 inside a rim at 5, notched to 3 at the top.
+    fn bowl() -> Vec<f32> {
+        let mut z = vec![5.0; 25];
+        for y in 1..4 {
+            z[y * 5 + 1..y * 5 + 4].fill(1.0);
+        }
+        z[2] = 3.0;
+        z
+    }
+
+    #[test]
+    fn basin_fills_to_its_notch() {
+        let model = FloodModel::new(&bowl(), 5, 5, &FloodOptions::default()).unwrap();
+        for (i, (&s, &z)) in model.spill.iter().zip(&bowl()).enumerate() {
+            let (x, y) = (i % 5, i / 5);
+            let rim = x == 0 || y == 0 || x == 4 || y == 4;
+            // Border seeds flood at their own height, the floor over the notch
+            assert_eq!(s, if rim { z } else { 3.0 }, "sample {i}");
+        }
+        assert_eq!(model.flooded_count(3.0), 0);
+        assert_eq!(model.flooded_count(3.5), 10);
+        assert_eq!(model.flooded_count(5.5), 25);
+        let depth = model.depth(3.5);
+        assert_eq!(depth[12], 2.5);
+        assert_eq!(depth[2], 0.5);
+        assert_eq!(depth[0], 0.0);
+    }
+
+    #[test]
+    fn seeds_flood_from_inside_and_voids_carry_water() {
+        let mut z = bowl();
+        z[12] = -9999.0;
+        let options = FloodOptions {
+            nodata: Some(-9999.0),
+            from_edges: false,
+            seeds: vec![2.0, 2.0],
+            ..FloodOptions::default()
+        };
+        let model = FloodModel::new(&z, 5, 5, &options).unwrap();
+        assert_eq!(model.spill[12], f32::NEG_INFINITY);
+        for (i, &s) in model.spill.iter().enumerate() {
+            // Water from the centre reaches every sample at its own height
+            if i != 12 {
+                assert_eq!(s, z[i], "sample {i}");
+            }
+        }
+        // The void itself is never counted or given a depth
+        assert_eq!(model.flooded_count(f32::INFINITY), 24);
+        assert_eq!(model.flooded_count(2.0), 8);
+        assert!(model.depth(2.0)[12].is_nan());
+        assert_eq!(model.depth(2.0)[6], 1.0);
+    }
+
+    /// Triangles of `mesh` as corner positions.
+    fn triangles(mesh: &MeshComputeData) -> Vec<[[f32; 3]; 3]> {
+        let at = |i: u32| {
+            let v = &mesh.vertices[i as usize * 3..i as usize * 3 + 3];
+            [v[0], v[1], v[2]]
+        };
+        mesh.indices
+            .chunks_exact(3)
+            .map(|t| [at(t[0]), at(t[1]), at(t[2])])
+            .collect()
+    }
+
+    /// Twice the signed area in the mesh xy plane.
+    fn signed_area([a, b, c]: [[f32; 3]; 3]) -> f32 {
+        (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])
+    }
+
+    /// 5 × 2 ramp rising by 1 per column.
+    fn ramp() -> Vec<f32> {
+        (0..10).map(|i| (i % 5) as f32).collect()
+    }
+
+    #[test]
+    fn surface_spans_nothing_below_the_spill_and_everything_above() {
+        let model = FloodModel::new(&bowl(), 5, 5, &FloodOptions::default()).unwrap();
+        let options = MeshOptions::default();
+        let dry = model.water_surface(3.0, &options);
+        assert_eq!((dry.vertex_count, dry.indices.len()), (0, 0));
+
+        let full = model.water_surface(6.0, &options);
+        assert_eq!(full.indices.len() / 3, 2 * 4 * 4);
+        // One shared vertex per sample, all at the water level
+        assert_eq!(full.vertex_count, 25);
+        assert!(full.vertices.chunks_exact(3).all(|v| v[2] == 6.0));
+        let covered: f32 = triangles(&full).into_iter().map(signed_area).sum();
+        assert!((covered / 2.0 - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn shoreline_is_interpolated_between_samples() {
+        let model = FloodModel::new(&ramp(), 5, 2, &FloodOptions::default()).unwrap();
+        let mesh = model.water_surface(1.25, &MeshOptions::default());
+        // Wet columns 0 and 1, then one crossing per row a quarter of the
+        // way from column 1 to 2: x = 1.25 / 4 * 2 - 1
+        assert_eq!(mesh.vertex_count, 6);
+        let shore: Vec<&[f32]> = mesh
+            .vertices
+            .chunks_exact(3)
+            .filter(|v| v[0] > -0.5)
+            .collect();
+        assert_eq!(shore.len(), 2);
+        for v in shore {
+            assert!((v[0] + 0.375).abs() < 1e-6, "{v:?}");
+        }
+        // Both cells' triangles reuse the column 1 vertices
+        assert_eq!(mesh.indices.len() / 3, 4);
+        let mut used = mesh.indices.clone();
+        used.sort_unstable();
+        used.dedup();
+        assert_eq!(used.len(), 6);
+    }
+
+    #[test]
+    fn saddle_cell_fans_as_one_polygon() {
+        // Water from the top-left corner crosses to the bottom-right one
+        // diagonally, leaving the other two corners dry
+        let options = FloodOptions {
+            from_edges: false,
+            seeds: vec![0.0, 0.0],
+            ..FloodOptions::default()
+        };
+        let model = FloodModel::new(&[0.0, 5.0, 5.0, 0.0], 2, 2, &options).unwrap();
+        let mesh = model.water_surface(1.0, &MeshOptions::default());
+        // Two wet corners and four crossings a fifth of the way along
+        // each edge
+        assert_eq!(mesh.vertex_count, 6);
+        assert_eq!(mesh.indices.len() / 3, 4);
+        let tris = triangles(&mesh);
+        assert!(tris.iter().all(|&t| signed_area(t) > 0.0));
+        for v in mesh.vertices.chunks_exact(3) {
+            let off_corner = (v[0].abs() - 1.0).abs().max((v[1].abs() - 1.0).abs());
+            assert!(
+                off_corner < 1e-6 || (off_corner - 0.4).abs() < 1e-6,
+                "{v:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn triangles_wind_like_the_terrain() {
+        let model = FloodModel::new(&bowl(), 5, 5, &FloodOptions::default()).unwrap();
+        for level in [3.5, 4.0, 5.5] {
+            let mesh = model.water_surface(level, &MeshOptions::default());
+            assert!(!mesh.indices.is_empty());
+            for t in triangles(&mesh) {
+                assert!(signed_area(t) > 0.0, "level {level}: {t:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn water_next_to_a_void_reaches_the_dry_sample() {
+        let mut z = ramp();
+        z[2] = -9999.0;
+        let options = FloodOptions {
+            nodata: Some(-9999.0),
+            ..FloodOptions::default()
+        };
+        let model = FloodModel::new(&z, 5, 2, &options).unwrap();
+        let mesh = model.water_surface(1.25, &MeshOptions::default());
+        let has = |x: f32, y: f32| {
+            mesh.vertices
+                .chunks_exact(3)
+                .any(|v| (v[0] - x).abs() < 1e-6 && (v[1] - y).abs() < 1e-6)
+        };
+        // The void at column 2 is wet; its crossings sit on the dry samples
+        // right of and below it rather than between them
+        assert!(has(0.0, -1.0));
+        assert!(has(0.5, -1.0));
+        assert!(has(0.0, 1.0));
+        assert!(!mesh.vertices.chunks_exact(3).any(|v| v[0] > 0.5 + 1e-6));
+    }
+}
@@ -0,0 +1,55 @@
+use wasm_bindgen::prelude::*;
+
+// WGS84 ellipsoid
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_E2: f64 = 0.006_694_379_990_14;
+
+/// EPSG code of Web Mercator, the CRS of the 3DEP exports.
+pub const EPSG_WEB_MERCATOR: u16 = 3857;
+pub const EPSG_WGS84: u16 = 4326;
+
+/// Ground distance in metres covered by `dlon`/`dlat` degrees at latitude
+/// `lat` (all degrees), using the WGS84 radii of curvature.
+pub fn degrees_to_metres(lat: f64, dlon: f64, dlat: f64) -> (f64, f64) {
+    let phi = lat.to_radians();
+    let w = (1.0 - WGS84_E2 * phi.sin().powi(2)).sqrt();
+    let prime_vertical = WGS84_A / w;
+    let meridional = WGS84_A * (1.0 - WGS84_E2) / (w * w * w);
+    (
+        prime_vertical * phi.cos() * dlon.to_radians().abs(),
+        meridional * dlat.to_radians().abs(),
+    )
+}
+
+/// Web Mercator metres to (longitude, latitude) degrees.
+pub fn mercator_to_lon_lat(x: f64, y: f64) -> (f64, f64) {
+    let lon = (x / WGS84_A).to_degrees();
+    let lat = (y / WGS84_A).sinh().atan().to_degrees();
+    (lon, lat)
+}
+
+/// (longitude, latitude) degrees to Web Mercator metres.
+pub fn lon_lat_to_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let x = lon.to_radians() * WGS84_A;
+    let y = lat.to_radians().tan().asinh() * WGS84_A;
+    (x, y)
+}
+
+/// Size in metres of one cell of a `width` × `height` grid spanning the given
+/// longitude/latitude bounds, as `[x, y]`.
+#[wasm_bindgen]
+pub fn cell_size_metres(
+    min_lon: f64,
+    min_lat: f64,
+    max_lon: f64,
+    max_lat: f64,
+    width: usize,
+    height: usize,
+) -> Vec<f64> {
+    let (dx, dy) = degrees_to_metres(
+        (min_lat + max_lat) * 0.5,
+        (max_lon - min_lon) / width as f64,
+        (max_lat - min_lat) / height as f64,
+    );
+    vec![dx, dy]
+}
@@ -0,0 +1,1063 @@
+use js_sys::Float32Array;
+use std::collections::HashMap;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+use crate::geo::{
+    degrees_to_metres, lon_lat_to_mercator, mercator_to_lon_lat, EPSG_WEB_MERCATOR, EPSG_WGS84,
+};
+
+// Baseline TIFF tags
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PLANAR_CONFIGURATION: u16 = 284;
+const PREDICTOR: u16 = 317;
+const TILE_WIDTH: u16 = 322;
+const TILE_LENGTH: u16 = 323;
+const TILE_OFFSETS: u16 = 324;
+const TILE_BYTE_COUNTS: u16 = 325;
+const SAMPLE_FORMAT: u16 = 339;
+
+// GeoTIFF / GDAL tags
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const MODEL_TRANSFORMATION: u16 = 34264;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GDAL_NODATA: u16 = 42113;
+
+// GeoKeys
+const GT_MODEL_TYPE: u16 = 1024;
+const GT_RASTER_TYPE: u16 = 1025;
+const GEOGRAPHIC_TYPE: u16 = 2048;
+const PROJECTED_CS_TYPE: u16 = 3072;
+
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+/// Largest raster we decode (2^28 samples, 1 GiB as f32), so a corrupt or
+/// hostile header cannot make us allocate without bound.
+const MAX_SAMPLES: usize = 1 << 28;
+/// Largest decompressed strip or tile, in bytes.
+const MAX_CHUNK_BYTES: usize = 1 << 28;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoTiffError {
+    /// Not a classic TIFF (bad byte order mark or magic number).
+    NotTiff,
+    /// BigTIFF files are not supported.
+    BigTiff,
+    /// An offset or count points past the end of the buffer.
+    Truncated,
+    MissingTag(&'static str),
+    /// The raster (or one of its strips or tiles) is larger than we are
+    /// willing to allocate.
+    TooLarge {
+        width: usize,
+        height: usize,
+    },
+    Unsupported(String),
+    Decompression(String),
+}
+
+impl fmt::Display for GeoTiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoTiffError::NotTiff => write!(f, "not a TIFF file"),
+            GeoTiffError::BigTiff => write!(f, "BigTIFF files are not supported"),
+            GeoTiffError::Truncated => write!(f, "TIFF data is truncated"),
+            GeoTiffError::MissingTag(tag) => write!(f, "missing required TIFF tag {}", tag),
+            GeoTiffError::TooLarge { width, height } => {
+                write!(
+                    f,
+                    "TIFF image of {} × {} samples is too large",
+                    width, height
+                )
+            }
+            GeoTiffError::Unsupported(what) => write!(f, "unsupported TIFF feature: {}", what),
+            GeoTiffError::Decompression(why) => {
+                write!(f, "failed to decompress TIFF data: {}", why)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GeoTiffError {}
+
+/// A single-band elevation raster plus its georeferencing.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ElevationGrid {
+    width: usize,
+    height: usize,
+    elevations: Vec<f32>,
+    /// GDAL-style affine transform: origin x, pixel width, row rotation,
+    /// origin y, column rotation, pixel height (negative for north-up).
+    geotransform: [f64; 6],
+    nodata: Option<f32>,
+    epsg: Option<u16>,
+    geographic: bool,
+}
+
+#[wasm_bindgen]
+impl ElevationGrid {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Row-major samples, first row at the top (north) of the image.
+    #[wasm_bindgen(getter)]
+    pub fn elevations(&self) -> Float32Array {
+        Float32Array::from(self.elevations.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn geotransform(&self) -> Vec<f64> {
+        self.geotransform.to_vec()
+    }
+
+    /// `[min_x, min_y, max_x, max_y]` of the raster's outer edges, in CRS units.
+    #[wasm_bindgen(getter)]
+    pub fn bounds(&self) -> Vec<f64> {
+        self.bounds_array().to_vec()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn nodata(&self) -> Option<f32> {
+        self.nodata
+    }
+
+    /// EPSG code of the projected or geographic CRS, if the GeoKeys name one.
+    #[wasm_bindgen(getter)]
+    pub fn epsg(&self) -> Option<u16> {
+        self.epsg
+    }
+
+    /// True when coordinates are longitude/latitude degrees rather than a
+    /// projected CRS.
+    #[wasm_bindgen(getter)]
+    pub fn is_geographic(&self) -> bool {
+        self.geographic
+    }
+
+    /// `[min_lon, min_lat, max_lon, max_lat]` in degrees, for WGS84 and Web
+    /// Mercator rasters; undefined for other projections.
+    #[wasm_bindgen(getter)]
+    pub fn lon_lat_bounds(&self) -> Option<Vec<f64>> {
+        self.lon_lat_bounds_array().map(|b| b.to_vec())
+    }
+
+    /// Ground size of one cell in metres as `[x, y]`, measured at the centre
+    /// of the raster.
+    #[wasm_bindgen(getter)]
+    pub fn cell_size(&self) -> Vec<f64> {
+        let (x, y) = self.cell_size_metres();
+        vec![x, y]
+    }
+
+    /// Fractional sample position `[column, row]` of a longitude/latitude in
+    /// degrees, with sample centres at whole numbers (as `viewshed` takes
+    /// them). Undefined where `lon_lat_bounds` is.
+    pub fn lon_lat_to_grid(&self, lon: f64, lat: f64) -> Option<Vec<f64>> {
+        self.lon_lat_to_grid_array(lon, lat).map(|p| p.to_vec())
+    }
+
+    /// Ground area of one cell in m² for every row, top first. Varies with
+    /// latitude for geographic and Web Mercator rasters, where a single
+    /// `cell_size` over- or underestimates areas away from the centre.
+    #[wasm_bindgen(getter)]
+    pub fn row_cell_areas(&self) -> Vec<f64> {
+        (0..self.height)
+            .map(|row| {
+                let (x, y) = self.cell_size_at(self.row_latitude(row));
+                x * y
+            })
+            .collect()
+    }
+}
+
+impl ElevationGrid {
+    pub fn from_geotiff(bytes: &[u8]) -> Result<ElevationGrid, GeoTiffError> {
+        let reader = Reader::new(bytes)?;
+        let ifd = reader.first_ifd()?;
+        let image = ImageLayout::from_ifd(&reader, &ifd)?;
+        let elevations = image.decode(&reader)?;
+
+        let geo_keys = parse_geo_keys(&reader, &ifd)?;
+        let pixel_is_point = geo_keys.get(&GT_RASTER_TYPE) == Some(&RASTER_PIXEL_IS_POINT);
+        let geotransform = read_geotransform(&reader, &ifd, pixel_is_point)?;
+        let geographic = geo_keys.get(&GT_MODEL_TYPE) == Some(&MODEL_TYPE_GEOGRAPHIC);
+        let epsg = geo_keys
+            .get(&PROJECTED_CS_TYPE)
+            .or_else(|| geo_keys.get(&GEOGRAPHIC_TYPE))
+            .copied()
+            // 32767 is "user defined" in GeoTIFF, not an EPSG code
+            .filter(|&code| code != 32767);
+
+        let nodata = reader
+            .ascii(&ifd, GDAL_NODATA)?
+            .and_then(|s| s.trim().parse::<f32>().ok());
+
+        Ok(ElevationGrid {
+            width: image.width,
+            height: image.height,
+            elevations,
+            geotransform,
+            nodata,
+            epsg,
+            geographic,
+        })
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.elevations
+    }
+
+    pub fn geotransform_array(&self) -> [f64; 6] {
+        self.geotransform
+    }
+
+    pub fn lon_lat_bounds_array(&self) -> Option<[f64; 4]> {
+        let b = self.bounds_array();
+        if self.geographic || self.epsg == Some(EPSG_WGS84) {
+            Some(b)
+        } else if self.epsg == Some(EPSG_WEB_MERCATOR) {
+            let (min_lon, min_lat) = mercator_to_lon_lat(b[0], b[1]);
+            let (max_lon, max_lat) = mercator_to_lon_lat(b[2], b[3]);
+            Some([min_lon, min_lat, max_lon, max_lat])
+        } else {
+            None
+        }
+    }
+
+    pub fn lon_lat_to_grid_array(&self, lon: f64, lat: f64) -> Option<[f64; 2]> {
+        let (x, y) = if self.geographic || self.epsg == Some(EPSG_WGS84) {
+            (lon, lat)
+        } else if self.epsg == Some(EPSG_WEB_MERCATOR) {
+            lon_lat_to_mercator(lon, lat)
+        } else {
+            return None;
+        };
+
+        // Invert the affine transform; it maps pixel corners, so sample
+        // centres sit half a pixel in
+        let gt = &self.geotransform;
+        let det = gt[1] * gt[5] - gt[2] * gt[4];
+        if det == 0.0 {
+            return None;
+        }
+        let (dx, dy) = (x - gt[0], y - gt[3]);
+        let column = (dx * gt[5] - dy * gt[2]) / det;
+        let row = (dy * gt[1] - dx * gt[4]) / det;
+        Some([column - 0.5, row - 0.5])
+    }
+
+    pub fn cell_size_metres(&self) -> (f64, f64) {
+        let centre_lat = self
+            .lon_lat_bounds_array()
+            .map(|b| (b[1] + b[3]) * 0.5)
+            .unwrap_or(0.0);
+        self.cell_size_at(centre_lat)
+    }
+
+    /// Latitude of the centre of `row` in degrees; 0 for projections
+    /// without a known mapping to latitude.
+    fn row_latitude(&self, row: usize) -> f64 {
+        let gt = &self.geotransform;
+        let y = gt[3] + (row as f64 + 0.5) * gt[5];
+        if self.geographic || self.epsg == Some(EPSG_WGS84) {
+            y
+        } else if self.epsg == Some(EPSG_WEB_MERCATOR) {
+            mercator_to_lon_lat(gt[0], y).1
+        } else {
+            0.0
+        }
+    }
+
+    /// Ground size of one cell in metres at latitude `lat`.
+    fn cell_size_at(&self, lat: f64) -> (f64, f64) {
+        let (pixel_x, pixel_y) = (self.geotransform[1].abs(), self.geotransform[5].abs());
+
+        if self.geographic || self.epsg == Some(EPSG_WGS84) {
+            degrees_to_metres(lat, pixel_x, pixel_y)
+        } else if self.epsg == Some(EPSG_WEB_MERCATOR) {
+            // Mercator stretches both axes by 1 / cos(latitude)
+            let k = lat.to_radians().cos();
+            (pixel_x * k, pixel_y * k)
+        } else {
+            // Assume a projected CRS in metres
+            (pixel_x, pixel_y)
+        }
+    }
+
+    pub fn bounds_array(&self) -> [f64; 4] {
+        let gt = &self.geotransform;
+        let corner = |col: f64, row: f64| {
+            (
+                gt[0] + col * gt[1] + row * gt[2],
+                gt[3] + col * gt[4] + row * gt[5],
+            )
+        };
+        let (w, h) = (self.width as f64, self.height as f64);
+        let corners = [
+            corner(0.0, 0.0),
+            corner(w, 0.0),
+            corner(0.0, h),
+            corner(w, h),
+        ];
+
+        let mut bounds = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        for (x, y) in corners {
+            bounds[0] = bounds[0].min(x);
+            bounds[1] = bounds[1].min(y);
+            bounds[2] = bounds[2].max(x);
+            bounds[3] = bounds[3].max(y);
+        }
+        bounds
+    }
+}
+
+/// Decode the first image of a GeoTIFF into an `ElevationGrid`.
+#[wasm_bindgen]
+pub fn decode_geotiff(bytes: &[u8]) -> Result<ElevationGrid, JsError> {
+    Ok(ElevationGrid::from_geotiff(bytes)?)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+/// Raw IFD entry: field type, value count and where the value bytes live.
+#[derive(Copy, Clone)]
+struct Entry {
+    field_type: u16,
+    count: usize,
+    offset: usize,
+}
+
+type Ifd = HashMap<u16, Entry>;
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, GeoTiffError> {
+        let little_endian = match data.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(GeoTiffError::NotTiff),
+        };
+        let reader = Reader {
+            data,
+            little_endian,
+        };
+        match reader.u16_at(2)? {
+            42 => Ok(reader),
+            43 => Err(GeoTiffError::BigTiff),
+            _ => Err(GeoTiffError::NotTiff),
+        }
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], GeoTiffError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(GeoTiffError::Truncated)
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, GeoTiffError> {
+        let b: [u8; 2] = self.bytes(offset, 2)?.try_into().unwrap();
+        Ok(if self.little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, GeoTiffError> {
+        let b: [u8; 4] = self.bytes(offset, 4)?.try_into().unwrap();
+        Ok(if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn u64_at(&self, offset: usize) -> Result<u64, GeoTiffError> {
+        let b: [u8; 8] = self.bytes(offset, 8)?.try_into().unwrap();
+        Ok(if self.little_endian {
+            u64::from_le_bytes(b)
+        } else {
+            u64::from_be_bytes(b)
+        })
+    }
+
+    fn first_ifd(&self) -> Result<Ifd, GeoTiffError> {
+        let start = self.u32_at(4)? as usize;
+        let count = self.u16_at(start)? as usize;
+        let mut ifd = HashMap::with_capacity(count);
+
+        for i in 0..count {
+            let at = start + 2 + i * 12;
+            let tag = self.u16_at(at)?;
+            let field_type = self.u16_at(at + 2)?;
+            let count = self.u32_at(at + 4)? as usize;
+            let size = field_size(field_type).saturating_mul(count);
+            // Values of four bytes or less are stored inline in the entry.
+            let offset = if size <= 4 {
+                at + 8
+            } else {
+                self.u32_at(at + 8)? as usize
+            };
+            ifd.insert(
+                tag,
+                Entry {
+                    field_type,
+                    count,
+                    offset,
+                },
+            );
+        }
+
+        Ok(ifd)
+    }
+
+    /// Integer-typed values of `tag`, widened to u64.
+    fn uints(&self, ifd: &Ifd, tag: u16) -> Result<Option<Vec<u64>>, GeoTiffError> {
+        let Some(entry) = ifd.get(&tag) else {
+            return Ok(None);
+        };
+        let size = field_size(entry.field_type);
+        self.bytes(entry.offset, size.saturating_mul(entry.count))?;
+
+        let mut values = Vec::with_capacity(entry.count);
+        for i in 0..entry.count {
+            let at = entry.offset + i * size;
+            values.push(match entry.field_type {
+                1 | 7 => self.data[at] as u64,
+                3 => self.u16_at(at)? as u64,
+                4 => self.u32_at(at)? as u64,
+                16 => self.u64_at(at)?,
+                other => {
+                    return Err(GeoTiffError::Unsupported(format!(
+                        "field type {} for tag {}",
+                        other, tag
+                    )))
+                }
+            });
+        }
+        Ok(Some(values))
+    }
+
+    fn uint(&self, ifd: &Ifd, tag: u16) -> Result<Option<u64>, GeoTiffError> {
+        Ok(self.uints(ifd, tag)?.and_then(|v| v.first().copied()))
+    }
+
+    fn doubles(&self, ifd: &Ifd, tag: u16) -> Result<Option<Vec<f64>>, GeoTiffError> {
+        let Some(entry) = ifd.get(&tag) else {
+            return Ok(None);
+        };
+        if entry.field_type != 12 {
+            return Err(GeoTiffError::Unsupported(format!(
+                "field type {} for tag {}",
+                entry.field_type, tag
+            )));
+        }
+        self.bytes(entry.offset, 8usize.saturating_mul(entry.count))?;
+        (0..entry.count)
+            .map(|i| Ok(f64::from_bits(self.u64_at(entry.offset + i * 8)?)))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
+    fn ascii(&self, ifd: &Ifd, tag: u16) -> Result<Option<String>, GeoTiffError> {
+        let Some(entry) = ifd.get(&tag) else {
+            return Ok(None);
+        };
+        let raw = self.bytes(entry.offset, entry.count)?;
+        let text = raw.split(|&b| b == 0).next().unwrap_or(&[]);
+        Ok(Some(String::from_utf8_lossy(text).into_owned()))
+    }
+}
+
+fn field_size(field_type: u16) -> usize {
+    match field_type {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 | 16 | 17 => 8,
+        _ => 0,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Compression {
+    None,
+    Lzw,
+    Deflate,
+    PackBits,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum SampleType {
+    U8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleType {
+    fn bytes(self) -> usize {
+        match self {
+            SampleType::U8 => 1,
+            SampleType::U16 | SampleType::I16 => 2,
+            SampleType::U32 | SampleType::I32 | SampleType::F32 => 4,
+            SampleType::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, SampleType::F32 | SampleType::F64)
+    }
+}
+
+/// Everything needed to walk the strips or tiles of the first band.
+struct ImageLayout {
+    width: usize,
+    height: usize,
+    sample_type: SampleType,
+    compression: Compression,
+    predictor: u64,
+    /// Samples per pixel inside one decoded chunk (1 for planar images).
+    chunk_samples: usize,
+    chunk_width: usize,
+    chunk_height: usize,
+    chunks_across: usize,
+    offsets: Vec<u64>,
+    byte_counts: Vec<u64>,
+}
+
+impl ImageLayout {
+    fn from_ifd(reader: &Reader, ifd: &Ifd) -> Result<Self, GeoTiffError> {
+        let width = reader
+            .uint(ifd, IMAGE_WIDTH)?
+            .ok_or(GeoTiffError::MissingTag("ImageWidth"))? as usize;
+        let height = reader
+            .uint(ifd, IMAGE_LENGTH)?
+            .ok_or(GeoTiffError::MissingTag("ImageLength"))? as usize;
+        let samples_per_pixel = reader.uint(ifd, SAMPLES_PER_PIXEL)?.unwrap_or(1) as usize;
+        let bits = reader.uint(ifd, BITS_PER_SAMPLE)?.unwrap_or(1);
+        let format = reader.uint(ifd, SAMPLE_FORMAT)?.unwrap_or(1);
+        let planar = reader.uint(ifd, PLANAR_CONFIGURATION)?.unwrap_or(1);
+        let predictor = reader.uint(ifd, PREDICTOR)?.unwrap_or(1);
+
+        let sample_type = match (format, bits) {
+            (1, 8) => SampleType::U8,
+            (1, 16) => SampleType::U16,
+            (2, 16) => SampleType::I16,
+            (1, 32) => SampleType::U32,
+            (2, 32) => SampleType::I32,
+            (3, 32) => SampleType::F32,
+            (3, 64) => SampleType::F64,
+            _ => {
+                return Err(GeoTiffError::Unsupported(format!(
+                    "sample format {} with {} bits",
+                    format, bits
+                )))
+            }
+        };
+
+        let compression = match reader.uint(ifd, COMPRESSION)?.unwrap_or(1) {
+            1 => Compression::None,
+            5 => Compression::Lzw,
+            8 | 32946 => Compression::Deflate,
+            32773 => Compression::PackBits,
+            other => return Err(GeoTiffError::Unsupported(format!("compression {}", other))),
+        };
+
+        if !matches!(predictor, 1..=3) || (predictor == 3 && !sample_type.is_float()) {
+            return Err(GeoTiffError::Unsupported(format!(
+                "predictor {}",
+                predictor
+            )));
+        }
+
+        let (chunk_width, chunk_height, offsets, byte_counts) =
+            if let Some(tile_offsets) = reader.uints(ifd, TILE_OFFSETS)? {
+                let tile_width = reader
+                    .uint(ifd, TILE_WIDTH)?
+                    .ok_or(GeoTiffError::MissingTag("TileWidth"))?
+                    as usize;
+                let tile_height = reader
+                    .uint(ifd, TILE_LENGTH)?
+                    .ok_or(GeoTiffError::MissingTag("TileLength"))?
+                    as usize;
+                let counts = reader
+                    .uints(ifd, TILE_BYTE_COUNTS)?
+                    .ok_or(GeoTiffError::MissingTag("TileByteCounts"))?;
+                (tile_width, tile_height, tile_offsets, counts)
+            } else {
+                let strip_offsets = reader
+                    .uints(ifd, STRIP_OFFSETS)?
+                    .ok_or(GeoTiffError::MissingTag("StripOffsets"))?;
+                let rows = reader.uint(ifd, ROWS_PER_STRIP)?.unwrap_or(height as u64) as usize;
+                let counts = reader
+                    .uints(ifd, STRIP_BYTE_COUNTS)?
+                    .ok_or(GeoTiffError::MissingTag("StripByteCounts"))?;
+                (width, rows.min(height), strip_offsets, counts)
+            };
+
+        if width == 0 || height == 0 || chunk_width == 0 || chunk_height == 0 {
+            return Err(GeoTiffError::Unsupported("empty image".to_string()));
+        }
+
+        // Check the sizes the header declares before allocating for them
+        let too_large = || GeoTiffError::TooLarge { width, height };
+        width
+            .checked_mul(height)
+            .filter(|&samples| samples <= MAX_SAMPLES)
+            .ok_or_else(too_large)?;
+
+        let chunks_across = width.div_ceil(chunk_width);
+        let chunks_down = height.div_ceil(chunk_height);
+        // Planar images store each band's chunks one after another; the first
+        // band comes first, so it's enough to read that many chunks.
+        let band_chunks = chunks_across * chunks_down;
+        let chunk_samples = if planar == 2 { 1 } else { samples_per_pixel };
+        if offsets.len() < band_chunks || byte_counts.len() < band_chunks {
+            return Err(GeoTiffError::Truncated);
+        }
+        let row_bytes = chunk_width
+            .checked_mul(chunk_samples)
+            .and_then(|n| n.checked_mul(sample_type.bytes()))
+            .ok_or_else(too_large)?;
+        row_bytes
+            .checked_mul(chunk_height)
+            .filter(|&bytes| bytes <= MAX_CHUNK_BYTES)
+            .ok_or_else(too_large)?;
+        if compression == Compression::None {
+            for (chunk, &count) in byte_counts.iter().enumerate().take(band_chunks) {
+                let rows = chunk_height.min(height - chunk / chunks_across * chunk_height);
+                if (count as usize) < rows * row_bytes {
+                    return Err(GeoTiffError::Truncated);
+                }
+            }
+        }
+
+        Ok(ImageLayout {
+            width,
+            height,
+            sample_type,
+            compression,
+            predictor,
+            chunk_samples,
+            chunk_width,
+            chunk_height,
+            chunks_across,
+            offsets,
+            byte_counts,
+        })
+    }
+
+    fn decode(&self, reader: &Reader) -> Result<Vec<f32>, GeoTiffError> {
+        let mut out = vec![0.0; self.width * self.height];
+        let sample_bytes = self.sample_type.bytes();
+        let pixel_bytes = sample_bytes * self.chunk_samples;
+        let row_bytes = self.chunk_width * pixel_bytes;
+        let chunks_down = self.height.div_ceil(self.chunk_height);
+
+        for chunk_y in 0..chunks_down {
+            for chunk_x in 0..self.chunks_across {
+                let chunk = chunk_y * self.chunks_across + chunk_x;
+                let raw = reader.bytes(
+                    self.offsets[chunk] as usize,
+                    self.byte_counts[chunk] as usize,
+                )?;
+                let mut data = decompress(self.compression, raw, row_bytes * self.chunk_height)?;
+
+                // Tiles are padded to full size; only the last strip may be
+                // shorter. Missing rows are an error, not 0 m of elevation.
+                let x0 = chunk_x * self.chunk_width;
+                let y0 = chunk_y * self.chunk_height;
+                let rows = self.chunk_height.min(self.height - y0);
+                if data.len() < rows * row_bytes {
+                    return Err(GeoTiffError::Truncated);
+                }
+                for row in data.chunks_exact_mut(row_bytes).take(rows) {
+                    self.undo_predictor(row, reader.little_endian);
+                }
+
+                for row in 0..rows {
+                    let y = y0 + row;
+                    let cols = self.chunk_width.min(self.width - x0);
+                    for col in 0..cols {
+                        let at = row * row_bytes + col * pixel_bytes;
+                        out[y * self.width + x0 + col] =
+                            self.sample(&data[at..at + sample_bytes], reader.little_endian);
+                    }
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn undo_predictor(&self, row: &mut [u8], little_endian: bool) {
+        let stride = self.chunk_samples;
+        match (self.predictor, self.sample_type.bytes()) {
+            (2, 1) => {
+                for i in stride..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - stride]);
+                }
+            }
+            (2, 2) => {
+                let read = |b: &[u8]| {
+                    let b = [b[0], b[1]];
+                    if little_endian {
+                        u16::from_le_bytes(b)
+                    } else {
+                        u16::from_be_bytes(b)
+                    }
+                };
+                for i in stride..row.len() / 2 {
+                    let value = read(&row[i * 2..]).wrapping_add(read(&row[(i - stride) * 2..]));
+                    let bytes = if little_endian {
+                        value.to_le_bytes()
+                    } else {
+                        value.to_be_bytes()
+                    };
+                    row[i * 2..i * 2 + 2].copy_from_slice(&bytes);
+                }
+            }
+            (2, 4) => {
+                let read = |b: &[u8]| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    if little_endian {
+                        u32::from_le_bytes(b)
+                    } else {
+                        u32::from_be_bytes(b)
+                    }
+                };
+                for i in stride..row.len() / 4 {
+                    let value = read(&row[i * 4..]).wrapping_add(read(&row[(i - stride) * 4..]));
+                    let bytes = if little_endian {
+                        value.to_le_bytes()
+                    } else {
+                        value.to_be_bytes()
+                    };
+                    row[i * 4..i * 4 + 4].copy_from_slice(&bytes);
+                }
+            }
+            (3, sample_bytes) => {
+                // Floating point predictor: byte-wise differencing over
+                // planes of most-significant-first bytes.
+                for i in stride..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - stride]);
+                }
+                let samples = row.len() / sample_bytes;
+                let planes = row.to_vec();
+                for s in 0..samples {
+                    for b in 0..sample_bytes {
+                        let byte = planes[b * samples + s];
+                        let dst = if little_endian {
+                            sample_bytes - 1 - b
+                        } else {
+                            b
+                        };
+                        row[s * sample_bytes + dst] = byte;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sample(&self, b: &[u8], little_endian: bool) -> f32 {
+        macro_rules! read {
+            ($ty:ty) => {{
+                let bytes = b.try_into().unwrap();
+                if little_endian {
+                    <$ty>::from_le_bytes(bytes)
+                } else {
+                    <$ty>::from_be_bytes(bytes)
+                }
+            }};
+        }
+        match self.sample_type {
+            SampleType::U8 => b[0] as f32,
+            SampleType::U16 => read!(u16) as f32,
+            SampleType::I16 => read!(i16) as f32,
+            SampleType::U32 => read!(u32) as f32,
+            SampleType::I32 => read!(i32) as f32,
+            SampleType::F32 => read!(f32),
+            SampleType::F64 => read!(f64) as f32,
+        }
+    }
+}
+
+/// Decompress one strip or tile, keeping at most `limit` bytes: anything
+/// past the chunk's declared size is of no use, and the cap defuses
+/// decompression bombs.
+fn decompress(compression: Compression, raw: &[u8], limit: usize) -> Result<Vec<u8>, GeoTiffError> {
+    match compression {
+        Compression::None => Ok(raw[..raw.len().min(limit)].to_vec()),
+        Compression::Lzw => {
+            let mut decoder =
+                weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8);
+            let mut out = vec![0; limit];
+            let (mut read, mut written) = (0, 0);
+            while written < limit {
+                let result = decoder.decode_bytes(&raw[read..], &mut out[written..]);
+                read += result.consumed_in;
+                written += result.consumed_out;
+                let status = result
+                    .status
+                    .map_err(|e| GeoTiffError::Decompression(e.to_string()))?;
+                if !matches!(status, weezl::LzwStatus::Ok)
+                    || result.consumed_in + result.consumed_out == 0
+                {
+                    break;
+                }
+            }
+            out.truncate(written);
+            Ok(out)
+        }
+        Compression::Deflate => {
+            match miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(raw, limit) {
+                Ok(out) => Ok(out),
+                Err(e) if e.status == miniz_oxide::inflate::TINFLStatus::HasMoreOutput => {
+                    Ok(e.output)
+                }
+                Err(e) => Err(GeoTiffError::Decompression(format!("{:?}", e.status))),
+            }
+        }
+        Compression::PackBits => unpack_bits(raw, limit),
+    }
+}
+
+fn unpack_bits(raw: &[u8], limit: usize) -> Result<Vec<u8>, GeoTiffError> {
+    let mut out = Vec::with_capacity(limit.min(raw.len() * 2));
+    let mut i = 0;
+    while i < raw.len() && out.len() < limit {
+        let n = raw[i] as i8;
+        i += 1;
+        if n >= 0 {
+            // Copy the next n + 1 bytes literally
+            let len = n as usize + 1;
+            let literal = raw.get(i..i + len).ok_or(GeoTiffError::Truncated)?;
+            out.extend_from_slice(literal);
+            i += len;
+        } else if n != -128 {
+            // Repeat the next byte 1 - n times
+            let byte = *raw.get(i).ok_or(GeoTiffError::Truncated)?;
+            out.extend(std::iter::repeat_n(byte, (1 - n as isize) as usize));
+            i += 1;
+        }
+    }
+    out.truncate(limit);
+    Ok(out)
+}
+
+/// Flatten the GeoKeyDirectory into key → value for the SHORT-valued keys,
+/// which covers every key we care about.
+fn parse_geo_keys(reader: &Reader, ifd: &Ifd) -> Result<HashMap<u16, u16>, GeoTiffError> {
+    let mut keys = HashMap::new();
+    let Some(dir) = reader.uints(ifd, GEO_KEY_DIRECTORY)? else {
+        return Ok(keys);
+    };
+    let count = dir.get(3).copied().unwrap_or(0) as usize;
+    for key in dir.get(4..).unwrap_or(&[]).chunks_exact(4).take(count) {
+        let (id, location, value) = (key[0] as u16, key[1], key[3] as u16);
+        if location == 0 {
+            keys.insert(id, value);
+        }
+    }
+    Ok(keys)
+}
+
+fn read_geotransform(
+    reader: &Reader,
+    ifd: &Ifd,
+    pixel_is_point: bool,
+) -> Result<[f64; 6], GeoTiffError> {
+    let mut gt = if let Some(m) = reader.doubles(ifd, MODEL_TRANSFORMATION)? {
+        if m.len() < 8 {
+            return Err(GeoTiffError::Truncated);
+        }
+        [m[3], m[0], m[1], m[7], m[4], m[5]]
+    } else if let (Some(tie), Some(scale)) = (
+        reader.doubles(ifd, MODEL_TIEPOINT)?,
+        reader.doubles(ifd, MODEL_PIXEL_SCALE)?,
+    ) {
+        if tie.len() < 6 || scale.len() < 2 {
+            return Err(GeoTiffError::Truncated);
+        }
+        let (i, j, x, y) = (tie[0], tie[1], tie[3], tie[4]);
+        [
+            x - i * scale[0],
+            scale[0],
+            0.0,
+            y + j * scale[1],
+            0.0,
+            -scale[1],
+        ]
+    } else {
+        // No georeferencing: plain pixel coordinates
+        [0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+    };
+
+    // PixelIsPoint ties the coordinate to the pixel centre; move the origin
+    // to the outer corner so bounds cover whole pixels.
+    if pixel_is_point {
+        gt[0] -= 0.5 * (gt[1] + gt[2]);
+        gt[3] -= 0.5 * (gt[4] + gt[5]);
+    }
+
+    Ok(gt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little-endian, single-strip, uncompressed f32 TIFF of `width` ×
+    /// `height` whose strip holds `data`, declared as `byte_count` bytes.
+    fn tiff(width: u32, height: u32, data: &[f32], byte_count: u32) -> Vec<u8> {
+        let tags: [(u16, u16, u32); 7] = [
+            (IMAGE_WIDTH, 4, width),
+            (IMAGE_LENGTH, 4, height),
+            (BITS_PER_SAMPLE, 3, 32),
+            (STRIP_OFFSETS, 4, 0), // patched below
+            (ROWS_PER_STRIP, 4, height),
+            (STRIP_BYTE_COUNTS, 4, byte_count),
+            (SAMPLE_FORMAT, 3, 3),
+        ];
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        bytes.extend_from_slice(&(tags.len() as u16).to_le_bytes());
+        let data_offset = 8 + 2 + tags.len() as u32 * 12 + 4;
+        for (tag, kind, value) in tags {
+            let value = if tag == STRIP_OFFSETS {
+                data_offset
+            } else {
+                value
+            };
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            if kind == 3 {
+                bytes.extend_from_slice(&(value as u16).to_le_bytes());
+                bytes.extend_from_slice(&[0, 0]);
+            } else {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        for z in data {
+            bytes.extend_from_slice(&z.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_a_minimal_strip() {
+        let data = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let grid = ElevationGrid::from_geotiff(&tiff(3, 2, &data, 24)).unwrap();
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid.samples(), &data);
+    }
+
+    #[test]
+    fn rejects_short_strips() {
+        let data = [1.0, 2.0, 3.0, 4.0];
+        let result = ElevationGrid::from_geotiff(&tiff(3, 2, &data, 16));
+        assert_eq!(result.unwrap_err(), GeoTiffError::Truncated);
+    }
+
+    #[test]
+    fn rejects_oversized_headers_before_allocating() {
+        let result = ElevationGrid::from_geotiff(&tiff(u32::MAX, u32::MAX, &[0.0], 4));
+        assert!(matches!(result, Err(GeoTiffError::TooLarge { .. })));
+        let result = ElevationGrid::from_geotiff(&tiff(1 << 15, 1 << 14, &[0.0], 4));
+        assert!(matches!(result, Err(GeoTiffError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn decodes_the_sample_dem() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../public/data/elevation.tiff");
+        let grid = ElevationGrid::from_geotiff(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!((grid.width(), grid.height()), (256, 256));
+        assert_eq!(grid.epsg(), Some(EPSG_WEB_MERCATOR));
+
+        let [min_lon, min_lat, max_lon, max_lat] = grid.lon_lat_bounds_array().unwrap();
+        for (actual, expected) in [
+            (min_lon, -77.16),
+            (min_lat, 40.0),
+            (max_lon, -75.84),
+            (max_lat, 41.0),
+        ] {
+            assert!(
+                (actual - expected).abs() < 0.01,
+                "{} != {}",
+                actual,
+                expected
+            );
+        }
+
+        let (min, max) = grid
+            .samples()
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &z| (lo.min(z), hi.max(z)));
+        assert!((min - 49.9).abs() < 0.1, "min {}", min);
+        assert!((max - 655.1).abs() < 0.1, "max {}", max);
+    }
+
+    #[test]
+    fn lon_lat_maps_to_sample_centres() {
+        // 0.5° × 0.25° pixels with the top-left corner at 10° E, 50° N
+        let mut grid = ElevationGrid {
+            width: 4,
+            height: 4,
+            elevations: vec![0.0; 16],
+            geotransform: [10.0, 0.5, 0.0, 50.0, 0.0, -0.25],
+            nodata: None,
+            epsg: Some(EPSG_WGS84),
+            geographic: true,
+        };
+        assert_eq!(grid.lon_lat_to_grid_array(10.25, 49.875), Some([0.0, 0.0]));
+        assert_eq!(grid.lon_lat_to_grid_array(10.0, 50.0), Some([-0.5, -0.5]));
+        assert_eq!(grid.lon_lat_to_grid_array(11.75, 49.125), Some([3.0, 3.0]));
+
+        grid.geographic = false;
+        grid.epsg = Some(32633);
+        assert_eq!(grid.lon_lat_to_grid_array(10.25, 49.875), None);
+    }
+
+    #[test]
+    fn mercator_bounds_map_to_the_outer_pixel_corners() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../public/data/elevation.tiff");
+        let grid = ElevationGrid::from_geotiff(&std::fs::read(path).unwrap()).unwrap();
+        let [min_lon, min_lat, max_lon, max_lat] = grid.lon_lat_bounds_array().unwrap();
+        for ((lon, lat), expected) in [
+            ((min_lon, max_lat), [-0.5, -0.5]),
+            ((max_lon, min_lat), [255.5, 255.5]),
+        ] {
+            let p = grid.lon_lat_to_grid_array(lon, lat).unwrap();
+            for (actual, expected) in p.into_iter().zip(expected) {
+                assert!((actual - expected).abs() < 1e-6, "{p:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use wasm_bindgen::prelude::*;
+
+use crate::color::srgb_to_linear;
+use crate::{MeshComputeData, MeshError};
+
+const GLB_MAGIC: u32 = 0x4654_6c67; // "glTF"
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Serialize a mesh as binary glTF 2.0.
+///
+/// The mesh spans its frame's footprint on x/y (±1 along the longer side)
+/// with heights on z; the file is written Y-up (x east, height up, z toward
+/// the bottom row of the grid) and the root node scales it so one unit is one
+/// metre: `extent_x`/`extent_y` are the real-world width/depth in metres and
+/// `vertical_scale` the metres per unit of mesh height. Texcoords map the
+/// grid onto [0, 1] with v = 0 on row 0. Vertices at voids are left out.
+/// Fails when the extents, the vertical scale or the scale they give the
+/// mesh footprint are not finite.
+#[wasm_bindgen]
+pub fn export_glb(
+    mesh: &MeshComputeData,
+    extent_x: f32,
+    extent_y: f32,
+    vertical_scale: f32,
+    include_texcoords: bool,
+) -> Result<Vec<u8>, JsError> {
+    Ok(glb(
+        mesh,
+        extent_x,
+        extent_y,
+        vertical_scale,
+        include_texcoords,
+    )?)
+}
+
+/// Native counterpart of `export_glb`.
+pub fn glb(
+    mesh: &MeshComputeData,
+    extent_x: f32,
+    extent_y: f32,
+    vertical_scale: f32,
+    include_texcoords: bool,
+) -> Result<Vec<u8>, MeshError> {
+    let [half_x, half_y] = mesh.half_extent.map(|h| h.max(f32::MIN_POSITIVE));
+    // JSON has no NaN or infinity, so these would make the file unreadable
+    let scale = [
+        extent_x * 0.5 / half_x,
+        vertical_scale,
+        extent_y * 0.5 / half_y,
+    ];
+    let checks = [
+        ("mesh half extent x", half_x),
+        ("mesh half extent y", half_y),
+        ("extent x", extent_x),
+        ("extent y", extent_y),
+        ("vertical scale", vertical_scale),
+        ("x scale", scale[0]),
+        ("y scale", scale[2]),
+    ];
+    if let Some(&(what, value)) = checks.iter().find(|(_, value)| !value.is_finite()) {
+        return Err(MeshError::NonFiniteScale { what, value });
+    }
+
+    // Indexed meshes keep NaN positions for void samples; glTF requires
+    // finite ones, so drop those vertices and renumber the rest.
+    let mut renumbered = Vec::with_capacity(mesh.vertex_count);
+    let mut n = 0;
+    for v in mesh.vertices.chunks_exact(3) {
+        if v.iter().all(|c| c.is_finite()) {
+            renumbered.push(n as u32);
+            n += 1;
+        } else {
+            renumbered.push(u32::MAX);
+        }
+    }
+    let kept = |i: usize| renumbered[i] != u32::MAX;
+
+    // The mesh frame (x east, y down the rows, z up) is left-handed, so
+    // swapping y/z to get glTF's Y-up also mirrors it; reverse the winding to
+    // keep faces pointing out.
+    let positions: Vec<f32> = mesh
+        .vertices
+        .chunks_exact(3)
+        .enumerate()
+        .filter(|&(i, _)| kept(i))
+        .flat_map(|(_, v)| [v[0], v[2], v[1]])
+        .collect();
+    let normals: Vec<f32> = mesh
+        .normals
+        .chunks_exact(3)
+        .enumerate()
+        .filter(|&(i, _)| kept(i))
+        .flat_map(|(_, v)| {
+            if v.iter().all(|&c| c == 0.0) {
+                // Degenerate faces have no normal; glTF requires unit length
+                [0.0, 1.0, 0.0]
+            } else {
+                [v[0], v[2], v[1]]
+            }
+        })
+        .collect();
+    // glTF vertex colors are linear, ours are sRGB encoded
+    let colors: Vec<f32> = mesh
+        .colors
+        .chunks_exact(3)
+        .enumerate()
+        .filter(|&(i, _)| kept(i))
+        .flat_map(|(_, rgb)| rgb.iter().map(|&c| srgb_to_linear(c)))
+        .collect();
+    let texcoords: Vec<f32> = mesh
+        .vertices
+        .chunks_exact(3)
+        .enumerate()
+        .filter(|&(i, _)| kept(i))
+        .flat_map(|(_, v)| [(v[0] / half_x + 1.0) * 0.5, (v[1] / half_y + 1.0) * 0.5])
+        .collect();
+    let indices: Vec<u32> = mesh
+        .triangle_indices()
+        .chunks_exact(3)
+        .filter(|t| t.iter().all(|&i| kept(i as usize)))
+        .flat_map(|t| [t[0], t[2], t[1]].map(|i| renumbered[i as usize]))
+        .collect();
+
+    // glTF reserves the largest index value, so u16 only fits 65535 vertices
+    let short_indices = n < u16::MAX as usize;
+
+    let mut bin = Vec::new();
+    let mut views = Vec::new();
+    let mut push_view = |bytes: &[u8], target: u32| {
+        let offset = bin.len();
+        bin.extend_from_slice(bytes);
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            offset,
+            bytes.len(),
+            target
+        ));
+        views.len() - 1
+    };
+
+    let position_view = push_view(&f32_bytes(&positions), ARRAY_BUFFER);
+    let normal_view = push_view(&f32_bytes(&normals), ARRAY_BUFFER);
+    let color_view = push_view(&f32_bytes(&colors), ARRAY_BUFFER);
+    let texcoord_view = include_texcoords.then(|| push_view(&f32_bytes(&texcoords), ARRAY_BUFFER));
+    let index_view = if short_indices {
+        let bytes: Vec<u8> = indices
+            .iter()
+            .flat_map(|&i| (i as u16).to_le_bytes())
+            .collect();
+        push_view(&bytes, ELEMENT_ARRAY_BUFFER)
+    } else {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        push_view(&bytes, ELEMENT_ARRAY_BUFFER)
+    };
+
+    let (min, max) = bounds(&positions);
+    let mut accessors = vec![
+        format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+            position_view, FLOAT, n, min[0], min[1], min[2], max[0], max[1], max[2]
+        ),
+        format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3"}}"#,
+            normal_view, FLOAT, n
+        ),
+        format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3"}}"#,
+            color_view, FLOAT, n
+        ),
+        format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"SCALAR"}}"#,
+            index_view,
+            if short_indices {
+                UNSIGNED_SHORT
+            } else {
+                UNSIGNED_INT
+            },
+            indices.len()
+        ),
+    ];
+    let mut attributes = String::from(r#""POSITION":0,"NORMAL":1,"COLOR_0":2"#);
+    if let Some(view) = texcoord_view {
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC2"}}"#,
+            view, FLOAT, n
+        ));
+        attributes.push_str(r#","TEXCOORD_0":4"#);
+    }
+
+    let json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"mesh_compute"}},"#,
+            r#""scene":0,"scenes":[{{"nodes":[0]}}],"#,
+            r#""nodes":[{{"name":"terrain","mesh":0,"scale":[{},{},{}]}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{{}}},"indices":3,"material":0,"mode":4}}]}}],"#,
+            r#""materials":[{{"pbrMetallicRoughness":{{"metallicFactor":0,"roughnessFactor":1}}}}],"#,
+            r#""accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#
+        ),
+        scale[0],
+        scale[1],
+        scale[2],
+        attributes,
+        accessors.join(","),
+        views.join(","),
+        bin.len()
+    );
+
+    let mut json = json.into_bytes();
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut file = Vec::with_capacity(total);
+    for word in [GLB_MAGIC, 2, total as u32, json.len() as u32, CHUNK_JSON] {
+        file.extend_from_slice(&word.to_le_bytes());
+    }
+    file.extend_from_slice(&json);
+    file.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    file.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+    file.extend_from_slice(&bin);
+    Ok(file)
+}
+
+fn f32_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Per-component min/max of xyz triples, ignoring non-finite values.
+fn bounds(positions: &[f32]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in positions.chunks_exact(3) {
+        for k in 0..3 {
+            if p[k].is_finite() {
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+        }
+    }
+    if min[0] > max[0] {
+        return ([0.0; 3], [0.0; 3]);
+    }
+    (min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mesh_compute_indexed, MeshOptions};
+
+    /// The JSON and binary chunks of a GLB file.
+    fn chunks(glb: &[u8]) -> (String, &[u8]) {
+        let word = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap()) as usize;
+        assert_eq!(word(0), GLB_MAGIC as usize);
+        assert_eq!(word(8), glb.len());
+        let json_len = word(12);
+        let json = String::from_utf8(glb[20..20 + json_len].to_vec()).unwrap();
+        (json, &glb[28 + json_len..])
+    }
+
+    #[test]
+    fn void_vertices_are_left_out() {
+        let mut elevations = vec![0.5; 9];
+        elevations[0] = f32::NAN;
+        let mesh = mesh_compute_indexed(&elevations, 3, 3, 1, &MeshOptions::default()).unwrap();
+        assert_eq!(mesh.vertex_count, 9);
+        assert!(mesh.vertices[2].is_nan());
+
+        let glb = glb(&mesh, 100.0, 100.0, 1.0, true).unwrap();
+        let (json, bin) = chunks(&glb);
+        assert!(json.contains(r#""componentType":5126,"count":8,"type":"VEC3","min""#));
+        // Seven triangles left, as u16 indices padded to four bytes
+        assert!(json.contains(r#""componentType":5123,"count":21,"type":"SCALAR""#));
+        let positions = &bin[..8 * 12];
+        assert!(positions
+            .chunks_exact(4)
+            .all(|b| f32::from_le_bytes(b.try_into().unwrap()).is_finite()));
+    }
+
+    /// The numbers in the JSON array following `key`.
+    fn numbers(json: &str, key: &str) -> Vec<f32> {
+        let start = json.find(key).unwrap() + key.len();
+        let end = start + json[start..].find(']').unwrap();
+        json[start..end]
+            .split(',')
+            .map(|n| n.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn file_layout_is_valid_glb() {
+        let elevations = [f32::NAN, 0.25, 0.5, 0.25, 0.0, 0.75, 0.5, 0.75, 1.0];
+        let mesh = mesh_compute_indexed(&elevations, 3, 3, 1, &MeshOptions::default()).unwrap();
+        let glb = glb(&mesh, 200.0, 100.0, 50.0, false).unwrap();
+        let word = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap()) as usize;
+
+        // Header, then a JSON and a BIN chunk, each padded to four bytes
+        assert_eq!(word(4), 2);
+        let json_len = word(12);
+        assert_eq!(word(16), CHUNK_JSON as usize);
+        assert_eq!(json_len % 4, 0);
+        let bin_len = word(20 + json_len);
+        assert_eq!(word(24 + json_len), CHUNK_BIN as usize);
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(28 + json_len + bin_len, glb.len());
+
+        let (json, bin) = chunks(&glb);
+        assert!(json.trim_end_matches(' ').ends_with('}'));
+        assert!(json.contains(&format!(r#""buffers":[{{"byteLength":{}}}]"#, bin_len)));
+        // Every buffer view starts aligned and fits in the buffer
+        let offsets: Vec<usize> = json
+            .match_indices(r#""byteOffset":"#)
+            .map(|(at, key)| {
+                let rest = &json[at + key.len()..];
+                rest[..rest.find(',').unwrap()].parse().unwrap()
+            })
+            .collect();
+        assert_eq!(offsets.len(), 4);
+        assert!(offsets.iter().all(|&o| o % 4 == 0 && o < bin_len));
+        // Seven triangles' u16 indices fill 42 of the 44 bytes that end the buffer
+        assert!(json.contains(r#""componentType":5123,"count":21,"type":"SCALAR""#));
+        assert_eq!(offsets[3] + 44, bin_len);
+
+        // The position accessor bounds match the positions written
+        let positions: Vec<f32> = bin[..8 * 12]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let (min, max) = (numbers(&json, r#""min":["#), numbers(&json, r#""max":["#));
+        for k in 0..3 {
+            let axis = positions.iter().skip(k).step_by(3);
+            assert_eq!(min[k], axis.clone().copied().fold(f32::MAX, f32::min));
+            assert_eq!(max[k], axis.copied().fold(f32::MIN, f32::max));
+        }
+        assert_eq!((min[0], max[0]), (-1.0, 1.0));
+        assert_eq!((min[1], max[1]), (0.0, 1.0));
+    }
+
+    #[test]
+    fn non_finite_scales_are_rejected() {
+        let mesh = mesh_compute_indexed(&[0.0; 4], 2, 2, 1, &MeshOptions::default()).unwrap();
+        for (extent_x, vertical_scale, what) in [
+            (f32::NAN, 1.0, "extent x"),
+            (f32::INFINITY, 1.0, "extent x"),
+            (1.0, f32::NAN, "vertical scale"),
+        ] {
+            match glb(&mesh, extent_x, 1.0, vertical_scale, false) {
+                Err(MeshError::NonFiniteScale { what: got, .. }) => assert_eq!(got, what),
+                other => panic!("{extent_x}, {vertical_scale}: {other:?}"),
+            }
+        }
+        let mut broken = mesh.clone();
+        broken.half_extent = [f32::INFINITY, 1.0];
+        assert!(matches!(
+            glb(&broken, 1.0, 1.0, 1.0, false),
+            Err(MeshError::NonFiniteScale {
+                what: "mesh half extent x",
+                ..
+            })
+        ));
+        // A finite extent over a vanishing footprint overflows the scale
+        broken.half_extent = [1e-30, 1.0];
+        assert!(matches!(
+            glb(&broken, 1e10, 1.0, 1.0, false),
+            Err(MeshError::NonFiniteScale {
+                what: "x scale",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,419 @@
+use wasm_bindgen::prelude::*;
+
+use crate::check_grid;
+use crate::color::ColorRamp;
+use crate::shadows::cast_shadows;
+use crate::terrain::{gradients, DerivativeMethod};
+use crate::voids::mask_nodata;
+
+/// Light directions of the standard multidirectional hillshade (GDAL's
+/// `-multidirectional`), in degrees clockwise from north.
+const MULTIDIRECTIONAL_AZIMUTHS: [f32; 4] = [225.0, 270.0, 315.0, 360.0];
+
+/// How the shade is combined with the color ramp in `hillshade_image`.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShadeBlend {
+    /// Shade only, as gray.
+    Grayscale,
+    /// Ramp color times shade: darkens shadowed slopes, and flat ground by
+    /// the sine of the sun altitude.
+    Multiply,
+    /// Soft light: shade above 0.5 lightens and below darkens, keeping
+    /// flat areas close to the ramp color.
+    SoftLight,
+}
+
+/// Illumination settings for `hillshade` and `hillshade_image`.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct HillshadeOptions {
+    /// Sun direction in degrees clockwise from north (row 0 is north).
+    pub azimuth: f32,
+    /// Sun angle above the horizon in degrees.
+    pub altitude: f32,
+    /// Multiplier converting elevation units to the cell size unit, e.g.
+    /// the elevation range for heights normalized to 0..1 over metre cells.
+    pub z_factor: f32,
+    pub cell_size_x: f32,
+    pub cell_size_y: f32,
+    pub method: DerivativeMethod,
+    /// Elevation marking missing samples; voids get no shade and are
+    /// transparent in the image.
+    pub nodata: Option<f32>,
+    /// Azimuths to combine instead of `azimuth` (see `set_azimuths`).
+    #[wasm_bindgen(skip)]
+    pub azimuths: Vec<f32>,
+    #[wasm_bindgen(skip)]
+    pub color_ramp: Option<ColorRamp>,
+    pub blend: ShadeBlend,
+    /// Opacity of the shade over the ramp colors, 0..1.
+    pub shade_strength: f32,
+    /// Also darken samples in the cast shadow of other terrain (see
+    /// `shadow_mask`). Only applies with a single `azimuth`.
+    pub cast_shadows: bool,
+}
+
+#[wasm_bindgen]
+impl HillshadeOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> HillshadeOptions {
+        HillshadeOptions::default()
+    }
+
+    /// Options lit from the four standard multidirectional azimuths.
+    pub fn multidirectional() -> HillshadeOptions {
+        HillshadeOptions {
+            azimuths: MULTIDIRECTIONAL_AZIMUTHS.to_vec(),
+            ..HillshadeOptions::default()
+        }
+    }
+
+    /// Light from several azimuths at once. Each sample weights the lights
+    /// by how obliquely they strike it (sin² of the angle between light and
+    /// aspect), so relief facing any of them stays visible. An empty list
+    /// goes back to the single `azimuth`.
+    #[wasm_bindgen(setter)]
+    pub fn set_azimuths(&mut self, azimuths: &[f32]) {
+        self.azimuths = azimuths.to_vec();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn azimuths(&self) -> Vec<f32> {
+        self.azimuths.clone()
+    }
+
+    /// Ramp for `hillshade_image`; the hypsometric ramp stretched over the
+    /// data range when unset.
+    #[wasm_bindgen(setter)]
+    pub fn set_color_ramp(&mut self, ramp: &ColorRamp) {
+        self.color_ramp = Some(ramp.clone());
+    }
+}
+
+impl Default for HillshadeOptions {
+    fn default() -> Self {
+        HillshadeOptions {
+            azimuth: 315.0,
+            altitude: 45.0,
+            z_factor: 1.0,
+            cell_size_x: 1.0,
+            cell_size_y: 1.0,
+            method: DerivativeMethod::Horn,
+            nodata: None,
+            azimuths: Vec::new(),
+            color_ramp: None,
+            blend: ShadeBlend::Multiply,
+            shade_strength: 1.0,
+            cast_shadows: false,
+        }
+    }
+}
+
+/// Illumination per sample in 0..1 (NaN for voids): the cosine between the
+/// surface normal and the sun, clamped at 0 for slopes facing away.
+#[wasm_bindgen]
+pub fn hillshade(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &HillshadeOptions,
+) -> Result<Vec<f32>, JsError> {
+    check_grid(elevations, width, height)?;
+    check_lighting(options)?;
+    let heights = mask_nodata(elevations, options.nodata);
+    Ok(shade_values(&heights, width, height, options))
+}
+
+/// RGBA8 image of the grid, one pixel per sample with row 0 on top: the
+/// color ramp sampled by elevation and combined with the hillshade per
+/// `options.blend`. Voids are transparent.
+#[wasm_bindgen]
+pub fn hillshade_image(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &HillshadeOptions,
+) -> Result<Vec<u8>, JsError> {
+    check_grid(elevations, width, height)?;
+    check_lighting(options)?;
+    let heights = mask_nodata(elevations, options.nodata);
+    let shade = shade_values(&heights, width, height, options);
+
+    let (min, max) = heights
+        .iter()
+        .filter(|z| !z.is_nan())
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &z| (lo.min(z), hi.max(z)));
+    let ramp = match &options.color_ramp {
+        Some(ramp) => ramp.clone(),
+        None => ColorRamp::hypsometric().rescaled(min, max),
+    };
+    let strength = options.shade_strength.clamp(0.0, 1.0);
+
+    let mut image = Vec::with_capacity(heights.len() * 4);
+    for (&z, &s) in heights.iter().zip(&shade) {
+        if z.is_nan() {
+            image.extend([0, 0, 0, 0]);
+            continue;
+        }
+        let s = if s.is_nan() { 1.0 } else { s };
+        let [r, g, b, a] = ramp.rgba(z);
+        let rgb = match options.blend {
+            ShadeBlend::Grayscale => [s; 3],
+            ShadeBlend::Multiply => [r, g, b].map(|c| c * (1.0 - strength + strength * s)),
+            ShadeBlend::SoftLight => [r, g, b].map(|c| {
+                let lit = if s <= 0.5 {
+                    c - (1.0 - 2.0 * s) * c * (1.0 - c)
+                } else {
+                    c + (2.0 * s - 1.0) * (c.sqrt() - c)
+                };
+                c + (lit - c) * strength
+            }),
+        };
+        let alpha = if options.blend == ShadeBlend::Grayscale {
+            1.0
+        } else {
+            a
+        };
+        image.extend([rgb[0], rgb[1], rgb[2], alpha].map(to_u8));
+    }
+    Ok(image)
+}
+
+/// Reject lighting options that leave the sun direction or the cell
+/// spacing undefined; a zero cell size or a NaN angle would otherwise stall
+/// the cast-shadow march.
+pub(crate) fn check_lighting(options: &HillshadeOptions) -> Result<(), JsError> {
+    let angles_finite = options.azimuth.is_finite()
+        && options.altitude.is_finite()
+        && options.azimuths.iter().all(|a| a.is_finite());
+    if !angles_finite {
+        return Err(JsError::new(
+            "hillshade: azimuth and altitude must be finite",
+        ));
+    }
+    let cell_sizes = [options.cell_size_x, options.cell_size_y];
+    if !(cell_sizes.iter().all(|c| c.is_finite() && *c != 0.0) && options.z_factor.is_finite()) {
+        return Err(JsError::new(
+            "hillshade: cell sizes must be finite and non-zero, z_factor finite",
+        ));
+    }
+    Ok(())
+}
+
+/// Shade per sample for heights with NaN voids. Samples whose 3×3 window
+/// touches a void get NaN, samples in cast shadow 0.
+pub fn shade_values(
+    heights: &[f32],
+    width: usize,
+    height: usize,
+    options: &HillshadeOptions,
+) -> Vec<f32> {
+    let zenith = (90.0 - options.altitude).to_radians();
+    let (sin_zenith, cos_zenith) = zenith.sin_cos();
+    let single = [options.azimuth];
+    let azimuths = if options.azimuths.is_empty() {
+        &single[..]
+    } else {
+        &options.azimuths[..]
+    };
+    let azimuths: Vec<f32> = azimuths.iter().map(|a| a.to_radians()).collect();
+    let shadowed = if options.cast_shadows && options.azimuths.is_empty() {
+        cast_shadows(heights, width, height, options)
+    } else {
+        Vec::new()
+    };
+
+    let mut shade: Vec<f32> = gradients(
+        heights,
+        width,
+        height,
+        options.cell_size_x,
+        options.cell_size_y,
+        options.method,
+    )
+    .into_iter()
+    .map(|(dzdx, dzdy)| {
+        let (dzdx, dzdy) = (dzdx * options.z_factor, dzdy * options.z_factor);
+        if dzdx.is_nan() || dzdy.is_nan() {
+            return f32::NAN;
+        }
+        let rise = (dzdx * dzdx + dzdy * dzdy).sqrt();
+        let (sin_slope, cos_slope) = rise.atan().sin_cos();
+        // Downhill compass direction; rows grow southward
+        let aspect = (-dzdx).atan2(dzdy);
+        let lit = |azimuth: f32| {
+            (cos_zenith * cos_slope + sin_zenith * sin_slope * (azimuth - aspect).cos()).max(0.0)
+        };
+
+        let (mut sum, mut weights) = (0.0, 0.0);
+        for &azimuth in &azimuths {
+            // Flat ground has no aspect: weight all lights equally
+            let weight = if rise == 0.0 {
+                1.0
+            } else {
+                (azimuth - aspect).sin().powi(2)
+            };
+            sum += lit(azimuth) * weight;
+            weights += weight;
+        }
+        if weights > 0.0 {
+            sum / weights
+        } else {
+            // A single light straight along the aspect
+            lit(azimuths[0])
+        }
+    })
+    .collect();
+
+    for (shade, &shadowed) in shade.iter_mut().zip(&shadowed) {
+        if shadowed {
+            *shade = 0.0;
+        }
+    }
+    shade
+}
+
+fn to_u8(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::RampInterpolation;
+
+    /// 5 × 5 plane rising by `per_x` per column and `per_y` per row.
+    fn plane(per_x: f32, per_y: f32) -> Vec<f32> {
+        (0..25)
+            .map(|i| (i % 5) as f32 * per_x + (i / 5) as f32 * per_y)
+            .collect()
+    }
+
+    /// `image` for a flat grid under a constant orange ramp, as RGBA pixels.
+    fn flat_pixels(altitude: f32, blend: ShadeBlend) -> Vec<u8> {
+        let options = HillshadeOptions {
+            altitude,
+            blend,
+            color_ramp: Some(
+                ColorRamp::new(
+                    &[0.0, 1.0],
+                    &[0.8, 0.4, 0.2, 0.8, 0.4, 0.2],
+                    RampInterpolation::LinearRgb,
+                )
+                .unwrap(),
+            ),
+            ..HillshadeOptions::default()
+        };
+        hillshade_image(&[0.5; 9], 3, 3, &options).unwrap()
+    }
+
+    fn assert_pixel(actual: &[u8], expected: [u8; 4]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(a.abs_diff(e) <= 1, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn flat_ground_is_lit_by_the_sun_altitude() {
+        for altitude in [20.0f32, 45.0, 90.0] {
+            let options = HillshadeOptions {
+                altitude,
+                ..HillshadeOptions::default()
+            };
+            let shade = shade_values(&[7.0; 25], 5, 5, &options);
+            let expected = altitude.to_radians().sin();
+            assert!(
+                shade.iter().all(|s| (s - expected).abs() < 1e-6),
+                "{altitude}"
+            );
+        }
+    }
+
+    #[test]
+    fn slopes_facing_the_sun_are_fully_lit() {
+        // The default sun is in the north-west, 45° up: a 45° slope falling
+        // toward it faces it squarely, the opposite slope is edge-on
+        let rise = std::f32::consts::FRAC_1_SQRT_2;
+        let options = HillshadeOptions::default();
+        let toward = shade_values(&plane(rise, rise), 5, 5, &options);
+        let away = shade_values(&plane(-rise, -rise), 5, 5, &options);
+        assert!(toward.iter().all(|s| (s - 1.0).abs() < 1e-5), "{toward:?}");
+        assert!(away.iter().all(|s| s.abs() < 1e-5), "{away:?}");
+    }
+
+    #[test]
+    fn multidirectional_lights_share_flat_ground_equally() {
+        let options = HillshadeOptions::multidirectional();
+        let flat = shade_values(&[0.0; 25], 5, 5, &options);
+        let expected = 45f32.to_radians().cos();
+        assert!(flat.iter().all(|s| (s - expected).abs() < 1e-6));
+
+        // On a slope the blend stays between the darkest and brightest light
+        let z = plane(0.3, -0.8);
+        let single: Vec<f32> = MULTIDIRECTIONAL_AZIMUTHS
+            .iter()
+            .map(|&azimuth| {
+                let options = HillshadeOptions {
+                    azimuth,
+                    ..HillshadeOptions::default()
+                };
+                shade_values(&z, 5, 5, &options)[12]
+            })
+            .collect();
+        let blended = shade_values(&z, 5, 5, &options)[12];
+        let (lo, hi) = single
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &s| (lo.min(s), hi.max(s)));
+        assert!(
+            lo <= blended && blended <= hi,
+            "{blended} outside {single:?}"
+        );
+    }
+
+    #[test]
+    fn blends_combine_shade_and_ramp_color() {
+        // Flat ground at 30° altitude has shade 0.5, at 90° shade 1
+        assert_pixel(
+            &flat_pixels(30.0, ShadeBlend::Grayscale)[..4],
+            [128, 128, 128, 255],
+        );
+        assert_pixel(
+            &flat_pixels(30.0, ShadeBlend::Multiply)[..4],
+            [102, 51, 26, 255],
+        );
+        assert_pixel(
+            &flat_pixels(90.0, ShadeBlend::Multiply)[..4],
+            [204, 102, 51, 255],
+        );
+        // Soft light leaves the color at half shade and brightens it to the
+        // square root in full light
+        assert_pixel(
+            &flat_pixels(30.0, ShadeBlend::SoftLight)[..4],
+            [204, 102, 51, 255],
+        );
+        assert_pixel(
+            &flat_pixels(90.0, ShadeBlend::SoftLight)[..4],
+            [228, 161, 114, 255],
+        );
+    }
+
+    #[test]
+    fn voids_are_transparent() {
+        let mut z = plane(0.1, 0.2);
+        z[12] = -9999.0;
+        let options = HillshadeOptions {
+            nodata: Some(-9999.0),
+            ..HillshadeOptions::default()
+        };
+        let image = hillshade_image(&z, 5, 5, &options).unwrap();
+        assert_eq!(image[12 * 4..13 * 4], [0, 0, 0, 0]);
+        // Neighbours lose their shade but keep their color
+        assert_eq!(image[11 * 4 + 3], 255);
+        assert!(shade_values(&mask_nodata(&z, options.nodata), 5, 5, &options)[11].is_nan());
+        assert_eq!(
+            image.iter().skip(3).step_by(4).filter(|&&a| a == 0).count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,650 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::FRAC_PI_2;
+
+use wasm_bindgen::prelude::*;
+
+use crate::check_grid;
+use crate::voids::mask_nodata;
+
+/// D8 neighbour offsets, index k flowing to direction code `1 << k`: east,
+/// south-east, south, south-west, west, north-west, north, north-east (ESRI
+/// encoding, with row 0 as north).
+pub(crate) const D8_OFFSETS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// D8 direction code of cells without a downslope neighbour (pits, flats,
+/// outlets on the grid edge) and voids.
+pub const NO_FLOW: u8 = 0;
+
+/// How flow leaving a cell is routed to its neighbours.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlowRouting {
+    /// All flow to the steepest of the eight neighbours (O'Callaghan & Mark
+    /// 1984): simple, but channels follow the 45° grid.
+    D8,
+    /// Flow along the steepest downslope angle, split between the two
+    /// neighbours bracketing it (Tarboton 1997): realistic dispersion on
+    /// hillslopes.
+    DInfinity,
+}
+
+/// Settings shared by the hydrology functions.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct HydrologyOptions {
+    /// Ground distance between samples, in the elevation unit.
+    pub cell_size_x: f32,
+    pub cell_size_y: f32,
+    /// Elevation marking missing samples. Voids take no part in routing;
+    /// flow reaching their edge leaves the grid, as at its border.
+    pub nodata: Option<f32>,
+    /// Rise added per cell when filling depressions, so filled areas keep a
+    /// gradient toward their outlet. 0 leaves them flat (no D8 direction).
+    pub fill_increment: f32,
+    pub routing: FlowRouting,
+    /// Ground area of one cell per row in m², for rasters whose cell area
+    /// varies with latitude (see `set_row_cell_areas`).
+    #[wasm_bindgen(skip)]
+    pub row_cell_areas: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl HydrologyOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> HydrologyOptions {
+        HydrologyOptions::default()
+    }
+
+    /// Cell area per row, one per grid row (e.g.
+    /// `ElevationGrid.row_cell_areas`), used for areas instead of
+    /// `cell_size_x` × `cell_size_y`.
+    #[wasm_bindgen(setter)]
+    pub fn set_row_cell_areas(&mut self, areas: &[f64]) {
+        self.row_cell_areas = areas.to_vec();
+    }
+}
+
+impl Default for HydrologyOptions {
+    fn default() -> Self {
+        HydrologyOptions {
+            cell_size_x: 1.0,
+            cell_size_y: 1.0,
+            nodata: None,
+            fill_increment: 1e-3,
+            routing: FlowRouting::D8,
+            row_cell_areas: Vec::new(),
+        }
+    }
+}
+
+impl HydrologyOptions {
+    /// Ground area in m² of a cell in `row`.
+    pub(crate) fn cell_area(&self, row: usize) -> f64 {
+        self.row_cell_areas
+            .get(row)
+            .copied()
+            .unwrap_or((self.cell_size_x as f64 * self.cell_size_y as f64).abs())
+    }
+
+    /// Why these options cannot route flow over a grid of `height` rows, if
+    /// they cannot.
+    fn invalid(&self, height: usize) -> Option<&'static str> {
+        let cell_sizes = [self.cell_size_x, self.cell_size_y];
+        if !cell_sizes.iter().all(|c| c.is_finite() && *c != 0.0) {
+            // Every D8 distance would be 0 and the steepest neighbour lost
+            return Some("hydrology: cell sizes must be finite and non-zero");
+        }
+        if !(self.fill_increment.is_finite() && self.fill_increment >= 0.0) {
+            // NaN turns filled depressions into voids, a negative rise
+            // digs new pits
+            return Some("hydrology: fill_increment must be finite and non-negative");
+        }
+        if !self.row_cell_areas.is_empty() && self.row_cell_areas.len() != height {
+            return Some("hydrology: row_cell_areas needs one area per grid row");
+        }
+        None
+    }
+}
+
+/// Reject options that leave distances, filled heights or cell areas
+/// undefined.
+pub(crate) fn check_hydrology(options: &HydrologyOptions, height: usize) -> Result<(), JsError> {
+    match options.invalid(height) {
+        Some(why) => Err(JsError::new(why)),
+        None => Ok(()),
+    }
+}
+
+/// Copy of the grid with every depression raised to its spill level, so
+/// that all cells drain to the border or a void. Voids come back as NaN.
+#[wasm_bindgen]
+pub fn fill_depressions(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &HydrologyOptions,
+) -> Result<Vec<f32>, JsError> {
+    check_grid(elevations, width, height)?;
+    check_hydrology(options, height)?;
+    let mut heights = mask_nodata(elevations, options.nodata);
+    priority_flood(&mut heights, width, height, options.fill_increment);
+    Ok(heights)
+}
+
+/// D8 flow direction per cell as ESRI codes: 1 east, 2 south-east, 4 south,
+/// 8 south-west, 16 west, 32 north-west, 64 north, 128 north-east (row 0 is
+/// north), `NO_FLOW` (0) where no neighbour is lower. Fill depressions first
+/// for continuous drainage.
+#[wasm_bindgen]
+pub fn flow_direction_d8(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &HydrologyOptions,
+) -> Result<Vec<u8>, JsError> {
+    check_grid(elevations, width, height)?;
+    check_hydrology(options, height)?;
+    let heights = mask_nodata(elevations, options.nodata);
+    Ok(d8_directions(&heights, width, height, options))
+}
+
+/// D-infinity flow direction per cell in degrees clockwise from north (row 0
+/// is north), like `aspect`; -1 where there is no downslope direction.
+#[wasm_bindgen]
+pub fn flow_direction_dinf(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &HydrologyOptions,
+) -> Result<Vec<f32>, JsError> {
+    check_grid(elevations, width, height)?;
+    check_hydrology(options, height)?;
+    let heights = mask_nodata(elevations, options.nodata);
+    Ok(dinf_flows(&heights, width, height, options)
+        .into_iter()
+        .map(|flow| flow.map_or(-1.0, |f| f.azimuth))
+        .collect())
+}
+
+/// Number of cells draining through each cell, itself included, routed per
+/// `options.routing` (fractional with D-infinity). Multiply by the cell area
+/// for the contributing area. Voids are NaN. Fill depressions first, or flow
+/// stops in every pit.
+#[wasm_bindgen]
+pub fn flow_accumulation(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &HydrologyOptions,
+) -> Result<Vec<f32>, JsError> {
+    check_grid(elevations, width, height)?;
+    check_hydrology(options, height)?;
+    let heights = mask_nodata(elevations, options.nodata);
+    Ok(accumulate(&heights, width, height, options))
+}
+
+/// A cell waiting in the priority flood, ordered lowest first and then in
+/// insertion order.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Queued {
+    pub(crate) z: f32,
+    pub(crate) order: usize,
+    pub(crate) index: usize,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    // Reversed: `BinaryHeap` is a max-heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .z
+            .total_cmp(&self.z)
+            .then(other.order.cmp(&self.order))
+    }
+}
+
+/// Priority-Flood depression filling (Barnes et al. 2014) in place, for
+/// heights with NaN voids. The flood grows inward from the border and the
+/// void edges, lowest cell first; any neighbour at or below the cell it is
+/// reached from lies in a depression and is raised to that level plus
+/// `increment`.
+pub(crate) fn priority_flood(heights: &mut [f32], width: usize, height: usize, increment: f32) {
+    let mut closed: Vec<bool> = heights.iter().map(|z| z.is_nan()).collect();
+    let mut queue = BinaryHeap::new();
+    let mut order = 0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if closed[i] {
+                continue;
+            }
+            let on_edge = x == 0
+                || y == 0
+                || x == width - 1
+                || y == height - 1
+                || neighbours(x, y, width, height).any(|(_, n)| heights[n].is_nan());
+            if on_edge {
+                closed[i] = true;
+                queue.push(Queued {
+                    z: heights[i],
+                    order,
+                    index: i,
+                });
+                order += 1;
+            }
+        }
+    }
+
+    while let Some(cell) = queue.pop() {
+        let (x, y) = (cell.index % width, cell.index / width);
+        for (_, n) in neighbours(x, y, width, height) {
+            if closed[n] {
+                continue;
+            }
+            closed[n] = true;
+            if heights[n] <= cell.z {
+                heights[n] = cell.z + increment;
+            }
+            queue.push(Queued {
+                z: heights[n],
+                order,
+                index: n,
+            });
+            order += 1;
+        }
+    }
+}
+
+/// In-grid neighbours of (x, y) as (D8 index, sample index).
+pub(crate) fn neighbours(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    D8_OFFSETS
+        .iter()
+        .enumerate()
+        .filter_map(move |(k, &(dx, dy))| {
+            let nx = x.checked_add_signed(dx).filter(|&nx| nx < width)?;
+            let ny = y.checked_add_signed(dy).filter(|&ny| ny < height)?;
+            Some((k, ny * width + nx))
+        })
+}
+
+/// Ground distance to the D8 neighbour in direction `k`.
+fn d8_distance(k: usize, options: &HydrologyOptions) -> f32 {
+    let (dx, dy) = D8_OFFSETS[k];
+    (dx as f32 * options.cell_size_x).hypot(dy as f32 * options.cell_size_y)
+}
+
+/// D8 code per cell for heights with NaN voids.
+pub(crate) fn d8_directions(
+    heights: &[f32],
+    width: usize,
+    height: usize,
+    options: &HydrologyOptions,
+) -> Vec<u8> {
+    let distances: [f32; 8] = std::array::from_fn(|k| d8_distance(k, options));
+    let mut directions = vec![NO_FLOW; heights.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let z = heights[y * width + x];
+            let mut steepest = 0.0;
+            for (k, n) in neighbours(x, y, width, height) {
+                // Voids compare false and are skipped
+                let drop = (z - heights[n]) / distances[k];
+                if drop > steepest {
+                    steepest = drop;
+                    directions[y * width + x] = 1 << k;
+                }
+            }
+        }
+    }
+    directions
+}
+
+/// Sample index the D8 code at (x, y) points to.
+pub(crate) fn d8_target(code: u8, x: usize, y: usize, width: usize) -> Option<usize> {
+    if code == NO_FLOW {
+        return None;
+    }
+    let (dx, dy) = D8_OFFSETS[code.trailing_zeros() as usize];
+    Some(y.wrapping_add_signed(dy) * width + x.wrapping_add_signed(dx))
+}
+
+/// D-infinity flow out of one cell.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct DinfFlow {
+    /// Degrees clockwise from north.
+    pub azimuth: f32,
+    /// The two neighbours bracketing the direction and their share of the
+    /// flow (summing to 1).
+    pub receivers: [(usize, f32); 2],
+}
+
+/// D-infinity flow per cell (Tarboton 1997) for heights with NaN voids.
+/// Each cell is split into eight triangular facets spanned by a cardinal
+/// and a diagonal neighbour; the steepest downslope plane direction over all
+/// facets wins, clamped to the facet edges.
+pub(crate) fn dinf_flows(
+    heights: &[f32],
+    width: usize,
+    height: usize,
+    options: &HydrologyOptions,
+) -> Vec<Option<DinfFlow>> {
+    // (cardinal, diagonal) D8 indices per facet, counter-clockwise from east
+    const FACETS: [(usize, usize); 8] = [
+        (0, 7),
+        (6, 7),
+        (6, 5),
+        (4, 5),
+        (4, 3),
+        (2, 3),
+        (2, 1),
+        (0, 1),
+    ];
+    let (cell_x, cell_y) = (options.cell_size_x.abs(), options.cell_size_y.abs());
+
+    let mut flows = vec![None; heights.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let e0 = heights[y * width + x];
+            if e0.is_nan() {
+                continue;
+            }
+            let at = |k: usize| {
+                let (dx, dy) = D8_OFFSETS[k];
+                let nx = x.checked_add_signed(dx).filter(|&nx| nx < width)?;
+                let ny = y.checked_add_signed(dy).filter(|&ny| ny < height)?;
+                let z = heights[ny * width + nx];
+                (!z.is_nan()).then_some((ny * width + nx, z))
+            };
+
+            let mut best: Option<(f32, DinfFlow)> = None;
+            for (facet, &(cardinal, diagonal)) in FACETS.iter().enumerate() {
+                let (Some((i1, e1)), Some((i2, e2))) = (at(cardinal), at(diagonal)) else {
+                    continue;
+                };
+                // Along the cardinal direction and across it
+                let (d1, d2) = if D8_OFFSETS[cardinal].0 != 0 {
+                    (cell_x, cell_y)
+                } else {
+                    (cell_y, cell_x)
+                };
+                let max_angle = (d2 / d1).atan();
+                let (s1, s2) = ((e0 - e1) / d1, (e1 - e2) / d2);
+                let (mut r, mut slope) = (s2.atan2(s1), s1.hypot(s2));
+                if r < 0.0 {
+                    (r, slope) = (0.0, s1);
+                } else if r > max_angle {
+                    (r, slope) = (max_angle, (e0 - e2) / d1.hypot(d2));
+                }
+                if slope <= 0.0 || best.is_some_and(|(s, _)| slope <= s) {
+                    continue;
+                }
+
+                // Facets alternate between turning counter-clockwise (even)
+                // and clockwise (odd) from their cardinal direction
+                let base = facet.div_ceil(2);
+                let sign = if facet % 2 == 0 { 1.0 } else { -1.0 };
+                let angle = base as f32 * FRAC_PI_2 + sign * r;
+                let azimuth = (90.0 - angle.to_degrees()).rem_euclid(360.0);
+                let to_diagonal = r / max_angle;
+                best = Some((
+                    slope,
+                    DinfFlow {
+                        azimuth,
+                        receivers: [(i1, 1.0 - to_diagonal), (i2, to_diagonal)],
+                    },
+                ));
+            }
+            flows[y * width + x] = best.map(|(_, flow)| flow);
+        }
+    }
+    flows
+}
+
+/// Valid cells from the highest down. Every receiver is strictly lower than
+/// its donor, so this order visits donors before their receivers.
+pub(crate) fn downhill_order(heights: &[f32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..heights.len())
+        .filter(|&i| !heights[i].is_nan())
+        .collect();
+    order.sort_unstable_by(|&a, &b| heights[b].total_cmp(&heights[a]));
+    order
+}
+
+/// Flow accumulation for heights with NaN voids.
+pub(crate) fn accumulate(
+    heights: &[f32],
+    width: usize,
+    height: usize,
+    options: &HydrologyOptions,
+) -> Vec<f32> {
+    match options.routing {
+        FlowRouting::D8 => {
+            let directions = d8_directions(heights, width, height, options);
+            d8_accumulation(heights, &directions, width)
+        }
+        FlowRouting::DInfinity => {
+            let flows = dinf_flows(heights, width, height, options);
+            let mut accumulation = unit_accumulation(heights);
+            for i in downhill_order(heights) {
+                if let Some(flow) = flows[i] {
+                    for (n, share) in flow.receivers {
+                        if share > 0.0 {
+                            accumulation[n] += accumulation[i] * share;
+                        }
+                    }
+                }
+            }
+            accumulation
+        }
+    }
+}
+
+/// D8 flow accumulation along precomputed `directions`.
+pub(crate) fn d8_accumulation(heights: &[f32], directions: &[u8], width: usize) -> Vec<f32> {
+    let mut accumulation = unit_accumulation(heights);
+    for i in downhill_order(heights) {
+        if let Some(n) = d8_target(directions[i], i % width, i / width, width) {
+            accumulation[n] += accumulation[i];
+        }
+    }
+    accumulation
+}
+
+/// One cell of flow per valid cell, NaN for voids.
+fn unit_accumulation(heights: &[f32]) -> Vec<f32> {
+    heights
+        .iter()
+        .map(|z| if z.is_nan() { f32::NAN } else { 1.0 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `width` × `height` plane falling by `fall_x` per column and `fall_y`
+    /// per row.
+    fn plane(width: usize, height: usize, fall_x: f32, fall_y: f32) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| 10.0 - (i % width) as f32 * fall_x - (i / width) as f32 * fall_y)
+            .collect()
+    }
+
+    #[test]
+    fn priority_flood_raises_pits_to_their_spill_level() {
+        // 5 × 5 basin: rim at 10 with a notch at 7, floor at 5
+        let mut heights: Vec<f32> = (0..25)
+            .map(|i| {
+                let (x, y) = (i % 5, i / 5);
+                if x == 0 || y == 0 || x == 4 || y == 4 {
+                    10.0
+                } else {
+                    5.0
+                }
+            })
+            .collect();
+        heights[2] = 7.0;
+        heights[12] = 8.0; // an island above the spill level stays
+
+        let mut flat = heights.clone();
+        priority_flood(&mut flat, 5, 5, 0.0);
+        for i in [6, 7, 8, 11, 13, 16, 17, 18] {
+            assert_eq!(flat[i], 7.0);
+        }
+        assert_eq!(flat[12], 8.0);
+
+        // With an increment every filled cell still drains toward the notch
+        let mut sloped = heights.clone();
+        priority_flood(&mut sloped, 5, 5, 0.01);
+        let directions = d8_directions(&sloped, 5, 5, &HydrologyOptions::default());
+        let mut reaches_notch = 0;
+        for start in [16, 17, 18] {
+            let mut cell = start;
+            while let Some(next) = d8_target(directions[cell], cell % 5, cell / 5, 5) {
+                cell = next;
+            }
+            reaches_notch += usize::from(cell == 2);
+        }
+        assert_eq!(reaches_notch, 3);
+
+        // A void drains the basin instead
+        let mut drained = heights;
+        drained[16] = f32::NAN;
+        priority_flood(&mut drained, 5, 5, 0.0);
+        assert_eq!(drained[17], 5.0);
+    }
+
+    #[test]
+    fn d8_takes_the_steepest_neighbour() {
+        let options = HydrologyOptions::default();
+        let east = d8_directions(&plane(4, 3, 1.0, 0.0), 4, 3, &options);
+        assert_eq!(east, [1, 1, 1, 0].repeat(3));
+
+        // Falling 1 east and 1 south: the diagonal drops 2 over √2
+        let south_east = d8_directions(&plane(3, 3, 1.0, 1.0), 3, 3, &options);
+        assert_eq!(south_east[4], 2);
+        assert_eq!(south_east[8], NO_FLOW);
+
+        // Wider cells make the same fall gentler along x
+        let wide = HydrologyOptions {
+            cell_size_x: 4.0,
+            ..HydrologyOptions::default()
+        };
+        assert_eq!(d8_directions(&plane(3, 3, 1.0, 1.0), 3, 3, &wide)[4], 4);
+    }
+
+    #[test]
+    fn dinf_splits_flow_between_the_bracketing_neighbours() {
+        let options = HydrologyOptions::default();
+        let due_east = dinf_flows(&plane(3, 3, 1.0, 0.0), 3, 3, &options)[4].unwrap();
+        assert!((due_east.azimuth - 90.0).abs() < 1e-4);
+        assert_eq!(due_east.receivers[0], (5, 1.0));
+
+        // Gradient (1, 0.5): between east and south-east
+        let flow = dinf_flows(&plane(3, 3, 1.0, 0.5), 3, 3, &options)[4].unwrap();
+        let expected = 90.0 + 0.5f32.atan().to_degrees();
+        assert!((flow.azimuth - expected).abs() < 1e-3);
+        let to_diagonal = 0.5f32.atan() / std::f32::consts::FRAC_PI_4;
+        assert_eq!(flow.receivers[0].0, 5);
+        assert_eq!(flow.receivers[1].0, 8);
+        assert!((flow.receivers[1].1 - to_diagonal).abs() < 1e-5);
+        assert!((flow.receivers[0].1 + flow.receivers[1].1 - 1.0).abs() < 1e-6);
+
+        // Pits have no direction
+        let mut pit = vec![1.0; 9];
+        pit[4] = 0.0;
+        assert!(dinf_flows(&pit, 3, 3, &options)[4].is_none());
+    }
+
+    #[test]
+    fn accumulation_conserves_every_cell() {
+        let heights = plane(4, 3, 1.0, 0.0);
+        let d8 = accumulate(&heights, 4, 3, &HydrologyOptions::default());
+        assert_eq!(d8, [1.0, 2.0, 3.0, 4.0].repeat(3));
+
+        // D-infinity: whatever leaves through the outlets is the whole grid
+        let (width, height) = (6, 5);
+        let heights: Vec<f32> = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                20.0 - x * 1.3 - y * 0.4 + (x * y * 0.7).sin() * 0.2
+            })
+            .collect();
+        let options = HydrologyOptions {
+            routing: FlowRouting::DInfinity,
+            ..HydrologyOptions::default()
+        };
+        let accumulation = accumulate(&heights, width, height, &options);
+        let flows = dinf_flows(&heights, width, height, &options);
+        let outflow: f32 = (0..width * height)
+            .filter(|&i| flows[i].is_none())
+            .map(|i| accumulation[i])
+            .sum();
+        assert!((outflow - (width * height) as f32).abs() < 1e-3);
+    }
+
+    #[test]
+    fn options_that_leave_routing_undefined_are_rejected() {
+        assert_eq!(HydrologyOptions::default().invalid(5), None);
+        let broken = [
+            HydrologyOptions {
+                cell_size_x: 0.0,
+                ..HydrologyOptions::default()
+            },
+            HydrologyOptions {
+                cell_size_y: f32::NAN,
+                ..HydrologyOptions::default()
+            },
+            HydrologyOptions {
+                fill_increment: f32::NAN,
+                ..HydrologyOptions::default()
+            },
+            HydrologyOptions {
+                fill_increment: -1e-3,
+                ..HydrologyOptions::default()
+            },
+            HydrologyOptions {
+                row_cell_areas: vec![1.0; 4],
+                ..HydrologyOptions::default()
+            },
+        ];
+        for options in broken {
+            assert!(options.invalid(5).is_some(), "{options:?}");
+        }
+        let per_row = HydrologyOptions {
+            row_cell_areas: vec![1.0; 5],
+            fill_increment: 0.0,
+            ..HydrologyOptions::default()
+        };
+        assert_eq!(per_row.invalid(5), None);
+    }
+}
@@ -1,12 +1,448 @@
-use js_sys::Float32Array;
+use js_sys::{Float32Array, Uint16Array, Uint32Array};
+use std::borrow::Cow;
+use std::fmt;
 use wasm_bindgen::prelude::*;
 
+mod cdlod;
+mod color;
+mod contours;
+mod delaunay;
+mod erosion;
+mod flood;
+mod geo;
+mod geotiff;
+mod gltf;
+mod hillshade;
+mod hydrology;
+mod occlusion;
+mod resample;
+mod rtin;
+mod shadows;
+mod stl;
+mod streams;
+mod terrain;
+mod tiles;
+mod viewshed;
+mod voids;
+mod watershed;
+
+pub use cdlod::{frustum_planes, LodQuadtree, LodSelection};
+pub use color::{ColorRamp, RampInterpolation};
+pub use contours::{contours, extract_contours, Contour, ContourData, MAX_CONTOUR_LEVELS};
+pub use delaunay::mesh_compute_delaunay;
+pub use erosion::{Erosion, ErosionOptions};
+pub use flood::{FloodModel, FloodOptions};
+pub use geo::cell_size_metres;
+pub use geotiff::{decode_geotiff, ElevationGrid, GeoTiffError};
+pub use gltf::export_glb;
+pub use hillshade::{hillshade, hillshade_image, HillshadeOptions, ShadeBlend};
+pub use hydrology::{
+    fill_depressions, flow_accumulation, flow_direction_d8, flow_direction_dinf, FlowRouting,
+    HydrologyOptions, NO_FLOW,
+};
+pub use resample::Interpolation;
+pub use rtin::RtinTerrain;
+pub use shadows::{shadow_mask, SunPosition};
+pub use stl::export_stl;
+pub use streams::{extract_streams, StreamNetwork};
+pub use terrain::{aspect, slope, DerivativeMethod, SlopeUnits};
+pub use tiles::{TerrainTile, TerrainTiles};
+pub use viewshed::{line_of_sight, viewshed, LineOfSight, ViewshedOptions};
+pub use voids::{fill_voids, VoidFill};
+pub use watershed::{delineate_watershed, Watershed};
+
+use occlusion::HorizonScan;
+use resample::interpolate_elevations;
+use voids::{fill_voids_in_place, mask_nodata};
+
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct MeshComputeData {
     vertices: Vec<f32>,
     colors: Vec<f32>,
     normals: Vec<f32>,
+    indices: Vec<u32>,
+    /// Sky-view factor per vertex, empty unless
+    /// `MeshOptions.occlusion_directions` is set.
+    occlusion: Vec<f32>,
     vertex_count: usize,
+    /// Half the footprint of the full grid in mesh units (`MeshFrame`
+    /// `half_x`, `half_y`), for exporters that scale it to real size.
+    half_extent: [f32; 2],
+}
+
+impl MeshComputeData {
+    /// Whether every index fits in a 16-bit index buffer.
+    fn uses_u16_indices(&self) -> bool {
+        self.vertex_count <= u16::MAX as usize + 1
+    }
+
+    /// Triangle vertex indices, synthesized for triangle-soup meshes.
+    fn triangle_indices(&self) -> Cow<'_, [u32]> {
+        if self.indices.is_empty() {
+            Cow::Owned((0..self.vertex_count as u32).collect())
+        } else {
+            Cow::Borrowed(&self.indices)
+        }
+    }
+}
+
+/// How vertex normals are derived for the generated mesh.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NormalMode {
+    /// One normal per triangle (faceted look). Indexed meshes share vertices
+    /// between faces, so they fall back to `AreaWeighted`.
+    Flat,
+    /// Central differences of the height grid at each sample.
+    Smooth,
+    /// Average of the adjacent face normals, weighted by triangle area.
+    AreaWeighted,
+}
+
+/// Which per-vertex value is looked up in the color ramp.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSource {
+    /// The vertex height, as passed in.
+    Elevation,
+    /// Terrain slope in degrees (Horn's method), e.g. with
+    /// `ColorRamp.slope_angle()`.
+    Slope,
+}
+
+/// Default `MeshOptions::max_vertices`: 16M vertices, about 200 MB of
+/// position, color and normal data.
+pub const DEFAULT_MAX_VERTICES: usize = 1 << 24;
+
+/// Invalid input to the mesh entry points.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshError {
+    /// `elevations` does not hold `width * height` samples.
+    DimensionMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// A mesh needs at least 2 × 2 samples.
+    GridTooSmall {
+        width: usize,
+        height: usize,
+    },
+    ZeroTessellation,
+    /// The mesh would exceed `MeshOptions::max_vertices`.
+    OutputTooLarge {
+        vertices: usize,
+        limit: usize,
+    },
+    /// The grid cannot be split into this many tiles (at least one cell
+    /// per tile is needed).
+    InvalidTiling {
+        tiles_x: usize,
+        tiles_y: usize,
+    },
+    TileOutOfRange {
+        x: usize,
+        y: usize,
+    },
+    /// The contour interval would produce more levels than
+    /// `MAX_CONTOUR_LEVELS`.
+    TooManyLevels {
+        levels: usize,
+        limit: usize,
+    },
+    /// A simplification error bound that is negative or not finite.
+    InvalidMaxError(f32),
+    /// A mesh extent or export scale that is not finite.
+    NonFiniteScale {
+        what: &'static str,
+        value: f32,
+    },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::DimensionMismatch { expected, actual } => write!(
+                f,
+                "expected {} elevations for the grid, got {}",
+                expected, actual
+            ),
+            MeshError::GridTooSmall { width, height } => write!(
+                f,
+                "grid of {} × {} samples is too small, need at least 2 × 2",
+                width, height
+            ),
+            MeshError::ZeroTessellation => write!(f, "tessellation factor must be at least 1"),
+            MeshError::OutputTooLarge { vertices, limit } => {
+                if *vertices == usize::MAX {
+                    write!(f, "mesh would exceed the limit of {} vertices", limit)
+                } else {
+                    write!(
+                        f,
+                        "mesh would have {} vertices, more than the limit of {}",
+                        vertices, limit
+                    )
+                }
+            }
+            MeshError::InvalidTiling { tiles_x, tiles_y } => write!(
+                f,
+                "cannot split the grid into {} × {} tiles",
+                tiles_x, tiles_y
+            ),
+            MeshError::TileOutOfRange { x, y } => write!(f, "no tile at ({}, {})", x, y),
+            MeshError::TooManyLevels { levels, limit } => write!(
+                f,
+                "contour interval gives {} levels, more than the limit of {}",
+                levels, limit
+            ),
+            MeshError::InvalidMaxError(max_error) => write!(
+                f,
+                "max error must be finite and non-negative, got {}",
+                max_error
+            ),
+            MeshError::NonFiniteScale { what, value } => {
+                write!(f, "{} must be finite, got {}", what, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+/// How the elevations passed to the mesh entry points are interpreted.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ElevationInput {
+    /// Heights already normalized (typically 0..1) and used as mesh z
+    /// directly; the footprint is always the [-1, 1] square.
+    Normalized,
+    /// Raw heights in metres. The mesh keeps the real proportions given by
+    /// the cell size, with the longer side spanning [-1, 1], and colors are
+    /// looked up by absolute elevation.
+    Metres,
+}
+
+/// Options shared by the mesh generation entry points.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct MeshOptions {
+    pub normal_mode: NormalMode,
+    /// Ramp to sample; `None` picks the default for the color source (see
+    /// `set_color_ramp`).
+    #[wasm_bindgen(skip)]
+    pub color_ramp: Option<ColorRamp>,
+    pub color_source: ColorSource,
+    /// Ground distance between input samples in metres, used for slope and
+    /// for the mesh proportions with `ElevationInput::Metres`.
+    pub cell_size_x: f32,
+    pub cell_size_y: f32,
+    /// Metres per unit of input elevation, used for slope (the elevation
+    /// range when the input is normalized to 0..1). Ignored for metre input.
+    pub metres_per_unit: f32,
+    pub elevation_input: ElevationInput,
+    /// Elevation placed at z = 0 for metre input; the data minimum when unset.
+    pub elevation_min: Option<f32>,
+    /// Top of the default color ramp for metre input; the data maximum when
+    /// unset.
+    pub elevation_max: Option<f32>,
+    /// Multiplier applied to mesh heights (not to slope or colors).
+    pub vertical_exaggeration: f32,
+    /// Largest number of output vertices a call may produce before it fails
+    /// with `MeshError::OutputTooLarge`.
+    pub max_vertices: usize,
+    /// Elevation marking missing samples (e.g. `ElevationGrid.nodata`).
+    /// These and NaN samples are voids: they are skipped by interpolation
+    /// and triangles touching them are left out of the mesh.
+    pub nodata: Option<f32>,
+    /// Optional fill of the voids before meshing.
+    pub void_fill: VoidFill,
+    /// How heights between input samples are reconstructed when
+    /// `tessellation_factor` > 1.
+    pub interpolation: Interpolation,
+    /// Azimuths scanned per vertex for baked ambient occlusion (see
+    /// `MeshComputeData.occlusion`); 0 disables it. 8–16 is usually enough.
+    pub occlusion_directions: usize,
+    /// How far the occlusion scan looks for horizons, in input cells.
+    pub occlusion_radius: f32,
+    /// Also multiply the vertex colors by the occlusion.
+    pub occlusion_in_colors: bool,
+}
+
+#[wasm_bindgen]
+impl MeshOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> MeshOptions {
+        MeshOptions::default()
+    }
+
+    /// Color ramp sampled with each vertex's color value. For elevation this
+    /// is the normalized height, or metres above sea level with
+    /// `ElevationInput::Metres` (so stops can sit at absolute thresholds, see
+    /// `ColorRamp.alpine()`). Without one, elevation uses the hypsometric
+    /// ramp stretched over the elevation range and slope uses
+    /// `ColorRamp.slope_angle()`.
+    #[wasm_bindgen(setter)]
+    pub fn set_color_ramp(&mut self, ramp: &ColorRamp) {
+        self.color_ramp = Some(ramp.clone());
+    }
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        MeshOptions {
+            normal_mode: NormalMode::Flat,
+            color_ramp: None,
+            color_source: ColorSource::Elevation,
+            cell_size_x: 1.0,
+            cell_size_y: 1.0,
+            metres_per_unit: 1.0,
+            elevation_input: ElevationInput::Normalized,
+            elevation_min: None,
+            elevation_max: None,
+            vertical_exaggeration: 1.0,
+            max_vertices: DEFAULT_MAX_VERTICES,
+            nodata: None,
+            void_fill: VoidFill::None,
+            interpolation: Interpolation::Bilinear,
+            occlusion_directions: 0,
+            occlusion_radius: 16.0,
+            occlusion_in_colors: false,
+        }
+    }
+}
+
+impl MeshOptions {
+    /// The ramp to sample, resolving the default for the color source.
+    /// `range` is the elevation range the default hypsometric ramp spans.
+    fn resolved_ramp(&self, range: (f32, f32)) -> Cow<'_, ColorRamp> {
+        match (&self.color_ramp, self.color_source) {
+            (Some(ramp), _) => Cow::Borrowed(ramp),
+            (None, ColorSource::Slope) => Cow::Owned(ColorRamp::slope_angle()),
+            (None, ColorSource::Elevation) => {
+                Cow::Owned(ColorRamp::hypsometric().rescaled(range.0, range.1))
+            }
+        }
+    }
+
+    /// Elevation range of the input: the explicit bounds for metre input,
+    /// falling back to the finite data extremes; 0..1 for normalized input.
+    fn elevation_range(&self, elevations: &[f32]) -> (f32, f32) {
+        if self.elevation_input == ElevationInput::Normalized {
+            return (0.0, 1.0);
+        }
+        let (data_min, data_max) = elevations
+            .iter()
+            .filter(|z| z.is_finite())
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &z| (lo.min(z), hi.max(z)));
+        let (data_min, data_max) = if data_min > data_max {
+            (0.0, 0.0)
+        } else {
+            (data_min, data_max)
+        };
+        (
+            self.elevation_min.unwrap_or(data_min),
+            self.elevation_max.unwrap_or(data_max),
+        )
+    }
+}
+
+/// Maps grid samples to mesh space: x/y in [-1, 1] along the longer side,
+/// z = (elevation - `z_offset`) * `z_scale`.
+#[derive(Copy, Clone, Debug)]
+struct MeshFrame {
+    half_x: f32,
+    half_y: f32,
+    z_offset: f32,
+    z_scale: f32,
+}
+
+impl MeshFrame {
+    fn new(width: usize, height: usize, min_elevation: f32, options: &MeshOptions) -> MeshFrame {
+        match options.elevation_input {
+            ElevationInput::Normalized => MeshFrame {
+                half_x: 1.0,
+                half_y: 1.0,
+                z_offset: 0.0,
+                z_scale: options.vertical_exaggeration,
+            },
+            ElevationInput::Metres => {
+                let extent_x = options.cell_size_x.abs() * (width - 1) as f32;
+                let extent_y = options.cell_size_y.abs() * (height - 1) as f32;
+                let longest = extent_x.max(extent_y).max(f32::MIN_POSITIVE);
+                MeshFrame {
+                    half_x: extent_x / longest,
+                    half_y: extent_y / longest,
+                    z_offset: min_elevation,
+                    z_scale: options.vertical_exaggeration * 2.0 / longest,
+                }
+            }
+        }
+    }
+
+    fn half_extent(&self) -> [f32; 2] {
+        [self.half_x, self.half_y]
+    }
+
+    /// Position of grid sample (x, y) with elevation `z`.
+    fn position(&self, x: usize, y: usize, grid_w: usize, grid_h: usize, z: f32) -> [f32; 3] {
+        self.point(x as f32, y as f32, grid_w, grid_h, z)
+    }
+
+    /// Position of the fractional grid coordinate (x, y) with elevation `z`.
+    fn point(&self, x: f32, y: f32, grid_w: usize, grid_h: usize, z: f32) -> [f32; 3] {
+        let px = (x / (grid_w - 1) as f32) * 2.0 - 1.0;
+        let py = (y / (grid_h - 1) as f32) * 2.0 - 1.0;
+        [
+            px * self.half_x,
+            py * self.half_y,
+            (z - self.z_offset) * self.z_scale,
+        ]
+    }
+
+    /// Occlusion scan set up by `options` over a `grid_w` × `grid_h` grid of
+    /// mesh-space heights resampled from `source_width` input columns, or
+    /// `None` when occlusion is off. Working in mesh space shades the relief
+    /// as displayed, including vertical exaggeration.
+    fn occlusion_scan<'a>(
+        &self,
+        mesh_heights: &'a [f32],
+        grid_w: usize,
+        grid_h: usize,
+        source_width: usize,
+        options: &MeshOptions,
+    ) -> Option<HorizonScan<'a>> {
+        if options.occlusion_directions == 0 {
+            return None;
+        }
+        let spacing_x = 2.0 * self.half_x / (grid_w - 1) as f32;
+        let spacing_y = 2.0 * self.half_y / (grid_h - 1) as f32;
+        let samples_per_cell = (grid_w - 1) as f32 / (source_width - 1) as f32;
+        Some(HorizonScan::new(
+            mesh_heights,
+            grid_w,
+            grid_h,
+            (spacing_x, spacing_y),
+            options.occlusion_directions,
+            options.occlusion_radius * samples_per_cell,
+        ))
+    }
+
+    /// Positions of every sample of a `grid_w` × `grid_h` elevation grid.
+    fn grid_positions(&self, elev: &[f32], grid_w: usize, grid_h: usize) -> Vec<f32> {
+        let mut positions = Vec::with_capacity(grid_w * grid_h * 3);
+        for y in 0..grid_h {
+            for x in 0..grid_w {
+                positions.extend_from_slice(&self.position(
+                    x,
+                    y,
+                    grid_w,
+                    grid_h,
+                    elev[y * grid_w + x],
+                ));
+            }
+        }
+        positions
+    }
 }
 
 #[wasm_bindgen]
@@ -26,47 +462,45 @@ impl MeshComputeData {
         Float32Array::from(self.normals.as_slice())
     }
 
+    /// Triangle indices into `vertices`. A `Uint16Array` when the vertex count
+    /// allows it, a `Uint32Array` otherwise. Empty for triangle-soup meshes.
     #[wasm_bindgen(getter)]
-    pub fn vertex_count(&self) -> usize {
-        self.vertex_count
+    pub fn indices(&self) -> JsValue {
+        if self.uses_u16_indices() {
+            let narrowed: Vec<u16> = self.indices.iter().map(|&i| i as u16).collect();
+            Uint16Array::from(narrowed.as_slice()).into()
+        } else {
+            Uint32Array::from(self.indices.as_slice()).into()
+        }
     }
-}
 
-fn interpolate_elevations(
-    elevations: &[f32],
-    original_width: usize,
-    original_height: usize,
-    new_width: usize,
-    new_height: usize,
-) -> Vec<f32> {
-    let mut interpolated = vec![0.0; new_width * new_height];
-
-    for y in 0..new_height {
-        for x in 0..new_width {
-            let orig_x = (x as f32 * (original_width as f32 - 1.0)) / (new_width as f32 - 1.0);
-            let orig_y = (y as f32 * (original_height as f32 - 1.0)) / (new_height as f32 - 1.0);
-
-            let x1 = orig_x.floor() as usize;
-            let x2 = (x1 + 1).min(original_width - 1);
-            let y1 = orig_y.floor() as usize;
-            let y2 = (y1 + 1).min(original_height - 1);
-
-            let dx = orig_x - x1 as f32;
-            let dy = orig_y - y1 as f32;
-
-            let z1 = elevations[y1 * original_width + x1];
-            let z2 = elevations[y1 * original_width + x2];
-            let z3 = elevations[y2 * original_width + x1];
-            let z4 = elevations[y2 * original_width + x2];
-
-            interpolated[y * new_width + x] = z1 * (1.0 - dx) * (1.0 - dy)
-                + z2 * dx * (1.0 - dy)
-                + z3 * (1.0 - dx) * dy
-                + z4 * dx * dy;
+    /// The `GPUIndexFormat` matching the array returned by `indices`.
+    #[wasm_bindgen(getter)]
+    pub fn index_format(&self) -> String {
+        if self.uses_u16_indices() {
+            "uint16".to_string()
+        } else {
+            "uint32".to_string()
         }
     }
 
-    interpolated
+    /// Baked ambient occlusion per vertex: the share of sky visible above
+    /// the local horizon, 1 on open ground and lower in valleys. Empty when
+    /// `MeshOptions.occlusion_directions` is 0.
+    #[wasm_bindgen(getter)]
+    pub fn occlusion(&self) -> js_sys::Float32Array {
+        Float32Array::from(self.occlusion.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn index_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
 }
 
 fn calculate_face_normal(
@@ -94,35 +528,52 @@ fn calculate_face_normal(
 }
 
 /// Compute a smooth normal for the grid vertex (gx, gy) using central
-/// differences.  Works on borders by clamping to the nearest valid cell.
+/// differences.  Works on borders and next to voids (NaN heights) by falling
+/// back to a one-sided difference.
+/// `spacing_x`/`spacing_y` are the distances between neighbouring samples in
+/// the same units as the heights.
 fn vertex_normal(
     gx: usize,
     gy: usize,
     grid_w: usize,
     grid_h: usize,
     elev: &[f32], // the interpolated height array
+    spacing_x: f32,
+    spacing_y: f32,
 ) -> (f32, f32, f32) {
     // helper to index 1-D slice
     let idx = |x: usize, y: usize| -> f32 { elev[y * grid_w + x] };
 
-    // height samples of immediate neighbours (with edge clamping)
-    let left = if gx > 0 { idx(gx - 1, gy) } else { idx(gx, gy) };
-    let right = if gx + 1 < grid_w {
-        idx(gx + 1, gy)
+    if idx(gx, gy).is_nan() {
+        return (0.0, 0.0, 1.0);
+    }
+
+    // neighbour columns/rows, clamped to the grid and to valid samples
+    let x0 = Some(gx.saturating_sub(1))
+        .filter(|&x| !idx(x, gy).is_nan())
+        .unwrap_or(gx);
+    let x1 = Some((gx + 1).min(grid_w - 1))
+        .filter(|&x| !idx(x, gy).is_nan())
+        .unwrap_or(gx);
+    let y0 = Some(gy.saturating_sub(1))
+        .filter(|&y| !idx(gx, y).is_nan())
+        .unwrap_or(gy);
+    let y1 = Some((gy + 1).min(grid_h - 1))
+        .filter(|&y| !idx(gx, y).is_nan())
+        .unwrap_or(gy);
+
+    // derivatives (central difference, one-sided on borders and voids)
+    let dx = if x1 > x0 {
+        (idx(x1, gy) - idx(x0, gy)) / ((x1 - x0) as f32 * spacing_x)
     } else {
-        idx(gx, gy)
+        0.0
     };
-    let top = if gy > 0 { idx(gx, gy - 1) } else { idx(gx, gy) };
-    let bottom = if gy + 1 < grid_h {
-        idx(gx, gy + 1)
+    let dy = if y1 > y0 {
+        (idx(gx, y1) - idx(gx, y0)) / ((y1 - y0) as f32 * spacing_y)
     } else {
-        idx(gx, gy)
+        0.0
     };
 
-    // derivatives (central difference)
-    let dx = (right - left) * 0.5;
-    let dy = (bottom - top) * 0.5;
-
     // normal = (-dx, -dy, 1)  → then normalize
     let mut nx = -dx;
     let mut ny = -dy;
@@ -134,107 +585,308 @@ fn vertex_normal(
     (nx, ny, nz)
 }
 
-#[derive(Copy, Clone)]
-struct RGB {
-    r: f32,
-    g: f32,
-    b: f32,
+/// Triangle indices for a `grid_w` × `grid_h` vertex grid, two triangles per
+/// quad split along the same diagonal as the triangle-soup mesh.
+fn grid_indices(grid_w: usize, grid_h: usize) -> Vec<u32> {
+    let mut indices = Vec::with_capacity((grid_w - 1) * (grid_h - 1) * 6);
+    for y in 0..grid_h - 1 {
+        for x in 0..grid_w - 1 {
+            let i0 = (y * grid_w + x) as u32;
+            let i1 = i0 + 1;
+            let i2 = i0 + grid_w as u32;
+            let i3 = i2 + 1;
+            indices.extend_from_slice(&[i0, i1, i2, i1, i3, i2]);
+        }
+    }
+    indices
+}
+
+/// `indices` without the triangles that touch a void (a NaN height).
+fn drop_void_triangles(indices: Vec<u32>, vertices: &[f32]) -> Vec<u32> {
+    let is_void = |i: u32| vertices[i as usize * 3 + 2].is_nan();
+    if !vertices.chunks_exact(3).any(|p| p[2].is_nan()) {
+        return indices;
+    }
+    indices
+        .chunks_exact(3)
+        .filter(|t| !t.iter().any(|&i| is_void(i)))
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Per-vertex normals for an indexed mesh, accumulating the unnormalized face
+/// normals (whose length is twice the triangle area) of every adjacent face.
+fn area_weighted_normals(vertices: &[f32], indices: &[u32]) -> Vec<f32> {
+    let mut normals = vec![0.0; vertices.len()];
+    let position = |i: usize| (vertices[i * 3], vertices[i * 3 + 1], vertices[i * 3 + 2]);
+
+    for tri in indices.chunks_exact(3) {
+        let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+        let (v1, v2, v3) = (position(a), position(b), position(c));
+        let edge1 = (v2.0 - v1.0, v2.1 - v1.1, v2.2 - v1.2);
+        let edge2 = (v3.0 - v1.0, v3.1 - v1.1, v3.2 - v1.2);
+        let face = [
+            edge1.1 * edge2.2 - edge1.2 * edge2.1,
+            edge1.2 * edge2.0 - edge1.0 * edge2.2,
+            edge1.0 * edge2.1 - edge1.1 * edge2.0,
+        ];
+        for &v in &[a, b, c] {
+            for k in 0..3 {
+                normals[v * 3 + k] += face[k];
+            }
+        }
+    }
+
+    for n in normals.chunks_exact_mut(3) {
+        let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        if len > 0.0 {
+            n.iter_mut().for_each(|c| *c /= len);
+        } else {
+            n.copy_from_slice(&[0.0, 0.0, 1.0]);
+        }
+    }
+
+    normals
 }
 
-const TERRAIN_COLORS: [(f32, RGB); 7] = [
-    (
-        0.0,
-        RGB {
-            r: 0.6,
-            g: 0.6,
-            b: 0.95,
-        },
-    ), // Light blue for lowest areas
-    (
-        0.1,
-        RGB {
-            r: 0.4,
-            g: 0.8,
-            b: 0.4,
-        },
-    ), // Light green for low lands
-    (
-        0.3,
-        RGB {
-            r: 0.2,
-            g: 0.6,
-            b: 0.2,
-        },
-    ), // Darker green
-    (
-        0.5,
-        RGB {
-            r: 0.8,
-            g: 0.7,
-            b: 0.5,
-        },
-    ), // Light brown
-    (
-        0.7,
-        RGB {
-            r: 0.7,
-            g: 0.55,
-            b: 0.4,
-        },
-    ), // Medium brown
-    (
-        0.9,
-        RGB {
-            r: 0.75,
-            g: 0.75,
-            b: 0.75,
-        },
-    ), // Gray
-    (
-        1.0,
-        RGB {
-            r: 1.0,
-            g: 1.0,
-            b: 1.0,
-        },
-    ), // White for peaks!
-];
-
-fn get_color_for_elevation(normalized_elevation: f32) -> RGB {
-    for i in 0..TERRAIN_COLORS.len() - 1 {
-        let (stop1, ref color1) = TERRAIN_COLORS[i];
-        let (stop2, ref color2) = TERRAIN_COLORS[i + 1];
-
-        if normalized_elevation >= stop1 && normalized_elevation <= stop2 {
-            let terped_color = (normalized_elevation - stop1) / (stop2 - stop1);
-
-            return RGB {
-                r: color1.r + (color2.r - color1.r) * terped_color,
-                g: color1.g + (color2.g - color1.g) * terped_color,
-                b: color1.b + (color2.b - color1.b) * terped_color,
+/// Shared per-sample normals for a grid of mesh-space `positions` (see
+/// `MeshFrame::grid_positions`). `Flat` has no per-sample equivalent and is
+/// treated as `AreaWeighted`.
+fn grid_vertex_normals(
+    positions: &[f32],
+    grid_w: usize,
+    grid_h: usize,
+    mode: NormalMode,
+) -> Vec<f32> {
+    match mode {
+        NormalMode::Smooth => {
+            let heights: Vec<f32> = positions.chunks_exact(3).map(|p| p[2]).collect();
+            let spacing_x = positions[3] - positions[0];
+            let spacing_y = positions[grid_w * 3 + 1] - positions[1];
+            let mut normals = Vec::with_capacity(grid_w * grid_h * 3);
+            for y in 0..grid_h {
+                for x in 0..grid_w {
+                    let n = vertex_normal(x, y, grid_w, grid_h, &heights, spacing_x, spacing_y);
+                    normals.extend_from_slice(&[n.0, n.1, n.2]);
+                }
+            }
+            normals
+        }
+        NormalMode::Flat | NormalMode::AreaWeighted => {
+            let indices = drop_void_triangles(grid_indices(grid_w, grid_h), positions);
+            area_weighted_normals(positions, &indices)
+        }
+    }
+}
+
+/// Per-sample values to look up in the color ramp, for a (resampled) grid
+/// whose samples are `cell_x` × `cell_y` metres apart.
+fn grid_color_values<'a>(
+    elev: &'a [f32],
+    grid_w: usize,
+    grid_h: usize,
+    cell_x: f32,
+    cell_y: f32,
+    options: &MeshOptions,
+) -> Cow<'a, [f32]> {
+    match options.color_source {
+        ColorSource::Elevation => Cow::Borrowed(elev),
+        ColorSource::Slope => {
+            let metres: Cow<[f32]> = match options.elevation_input {
+                ElevationInput::Normalized => {
+                    Cow::Owned(elev.iter().map(|z| z * options.metres_per_unit).collect())
+                }
+                ElevationInput::Metres => Cow::Borrowed(elev),
             };
+            Cow::Owned(terrain::slope_values(
+                &metres,
+                grid_w,
+                grid_h,
+                cell_x,
+                cell_y,
+                DerivativeMethod::Horn,
+                SlopeUnits::Degrees,
+            ))
+        }
+    }
+}
+
+/// Indexed mesh over a subset of the samples of a `grid_w` × `grid_h` grid
+/// (`heights`, NaN for voids) covering an input of `source_size` samples.
+/// `indices` refer to positions in `samples`; triangles touching voids are
+/// dropped. Shared by the adaptive meshers.
+fn mesh_from_samples(
+    heights: &[f32],
+    grid_w: usize,
+    grid_h: usize,
+    source_size: (usize, usize),
+    samples: &[usize],
+    indices: Vec<u32>,
+    options: &MeshOptions,
+) -> MeshComputeData {
+    let (width, height) = source_size;
+    let range = options.elevation_range(heights);
+    let frame = MeshFrame::new(width, height, range.0, options);
+    let ramp = options.resolved_ramp(range);
+
+    let mut vertices = Vec::with_capacity(samples.len() * 3);
+    for &i in samples {
+        let (x, y) = (i % grid_w, i / grid_w);
+        vertices.extend_from_slice(&frame.position(x, y, grid_w, grid_h, heights[i]));
+    }
+    let indices = drop_void_triangles(indices, &vertices);
+
+    let cell_x = options.cell_size_x * (width - 1) as f32 / (grid_w - 1) as f32;
+    let cell_y = options.cell_size_y * (height - 1) as f32 / (grid_h - 1) as f32;
+    let color_values = grid_color_values(heights, grid_w, grid_h, cell_x, cell_y, options);
+    let mut colors: Vec<f32> = samples
+        .iter()
+        .flat_map(|&i| ramp.rgb(color_values[i]))
+        .collect();
+
+    // Mesh-space heights of the full-resolution grid, for smooth normals and
+    // occlusion
+    let grid_heights: Vec<f32> =
+        if options.normal_mode == NormalMode::Smooth || options.occlusion_directions > 0 {
+            heights
+                .iter()
+                .map(|&z| (z - frame.z_offset) * frame.z_scale)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+    let occlusion: Vec<f32> =
+        match frame.occlusion_scan(&grid_heights, grid_w, grid_h, width, options) {
+            Some(scan) => samples
+                .iter()
+                .map(|&i| scan.sky_view(i % grid_w, i / grid_w))
+                .collect(),
+            None => Vec::new(),
+        };
+    apply_occlusion(&mut colors, &occlusion, options);
+
+    let normals = match options.normal_mode {
+        NormalMode::Smooth => {
+            // Central differences on the full-resolution grid
+            let spacing_x = 2.0 * frame.half_x / (grid_w - 1) as f32;
+            let spacing_y = 2.0 * frame.half_y / (grid_h - 1) as f32;
+            samples
+                .iter()
+                .flat_map(|&i| {
+                    let (x, y) = (i % grid_w, i / grid_w);
+                    let n =
+                        vertex_normal(x, y, grid_w, grid_h, &grid_heights, spacing_x, spacing_y);
+                    [n.0, n.1, n.2]
+                })
+                .collect()
         }
+        NormalMode::Flat | NormalMode::AreaWeighted => area_weighted_normals(&vertices, &indices),
+    };
+
+    MeshComputeData {
+        vertices,
+        colors,
+        normals,
+        indices,
+        occlusion,
+        vertex_count: samples.len(),
+        half_extent: frame.half_extent(),
+    }
+}
+
+/// Multiply RGB `colors` by the per-vertex `occlusion` if the options ask for
+/// it.
+fn apply_occlusion(colors: &mut [f32], occlusion: &[f32], options: &MeshOptions) {
+    if !options.occlusion_in_colors {
+        return;
+    }
+    for (rgb, &sky) in colors.chunks_exact_mut(3).zip(occlusion) {
+        rgb.iter_mut().for_each(|c| *c *= sky);
+    }
+}
+
+/// Check that `elevations` is a `width` × `height` grid a mesh can be built
+/// from.
+fn check_grid(elevations: &[f32], width: usize, height: usize) -> Result<(), MeshError> {
+    let expected = width.saturating_mul(height);
+    if elevations.len() != expected {
+        return Err(MeshError::DimensionMismatch {
+            expected,
+            actual: elevations.len(),
+        });
+    }
+    if width < 2 || height < 2 {
+        return Err(MeshError::GridTooSmall { width, height });
+    }
+    Ok(())
+}
+
+/// Check the inputs of a mesh entry point and return the tessellated grid
+/// size. `vertices_per_quad` is 6 for triangle soup and 0 for indexed meshes
+/// (which have one vertex per sample).
+fn tessellated_size(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    tessellation_factor: usize,
+    vertices_per_quad: usize,
+    options: &MeshOptions,
+) -> Result<(usize, usize), MeshError> {
+    check_grid(elevations, width, height)?;
+    if tessellation_factor == 0 {
+        return Err(MeshError::ZeroTessellation);
     }
 
-    // If nothing matched, return the last color in the array
-    return TERRAIN_COLORS[TERRAIN_COLORS.len() - 1].1;
+    let too_large = |vertices| MeshError::OutputTooLarge {
+        vertices,
+        limit: options.max_vertices,
+    };
+    let side = |n: usize| {
+        (n - 1)
+            .checked_mul(tessellation_factor)
+            .and_then(|n| n.checked_add(1))
+    };
+    let (Some(new_width), Some(new_height)) = (side(width), side(height)) else {
+        return Err(too_large(usize::MAX));
+    };
+    let vertices = if vertices_per_quad == 0 {
+        new_width.checked_mul(new_height)
+    } else {
+        (new_width - 1)
+            .checked_mul(new_height - 1)
+            .and_then(|quads| quads.checked_mul(vertices_per_quad))
+    }
+    .unwrap_or(usize::MAX);
+    if vertices > options.max_vertices {
+        return Err(too_large(vertices));
+    }
+
+    Ok((new_width, new_height))
 }
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
 }
 
+// Native builds (exporters, tooling) have no console to log to.
+#[cfg(not(target_arch = "wasm32"))]
+fn log(_s: &str) {}
+
 #[wasm_bindgen]
 pub fn mesh_compute(
     elevations: &[f32],
     width: usize,
     height: usize,
     tessellation_factor: usize,
-) -> MeshComputeData {
-    let new_width = (width - 1) * tessellation_factor + 1;
-    let new_height = (height - 1) * tessellation_factor + 1;
+    options: &MeshOptions,
+) -> Result<MeshComputeData, JsError> {
+    let (new_width, new_height) =
+        tessellated_size(elevations, width, height, tessellation_factor, 6, options)?;
 
     log(&format!(
         "wasm mesh_compute: elevations length = {}, width = {}, height = {}",
@@ -243,7 +895,16 @@ pub fn mesh_compute(
         height
     ));
 
-    let interpolated = interpolate_elevations(elevations, width, height, new_width, new_height);
+    let mut source = mask_nodata(elevations, options.nodata);
+    fill_voids_in_place(&mut source, width, height, options.void_fill);
+    let interpolated = interpolate_elevations(
+        &source,
+        width,
+        height,
+        new_width,
+        new_height,
+        options.interpolation,
+    );
 
     log(&format!(
         "wasm interpolated length = {} ({} × {})",
@@ -252,27 +913,55 @@ pub fn mesh_compute(
         new_height
     ));
 
+    let range = options.elevation_range(&source);
+    let frame = MeshFrame::new(width, height, range.0, options);
+    let positions = frame.grid_positions(&interpolated, new_width, new_height);
+    let ramp = options.resolved_ramp(range);
+
+    // Per-sample normals, only needed when not shading per face
+    let shared_normals = match options.normal_mode {
+        NormalMode::Flat => None,
+        mode => Some(grid_vertex_normals(&positions, new_width, new_height, mode)),
+    };
+
+    let color_values = grid_color_values(
+        &interpolated,
+        new_width,
+        new_height,
+        options.cell_size_x / tessellation_factor as f32,
+        options.cell_size_y / tessellation_factor as f32,
+        options,
+    );
+
+    let mesh_heights: Vec<f32> = positions.chunks_exact(3).map(|p| p[2]).collect();
+    let grid_occlusion = frame
+        .occlusion_scan(&mesh_heights, new_width, new_height, width, options)
+        .map(|scan| scan.grid());
+
     let mut vertices = Vec::new();
     let mut colors = Vec::new();
     let mut normals = Vec::new();
+    let mut occlusion = Vec::new();
 
     for y in 0..new_height - 1 {
         for x in 0..new_width - 1 {
-            let x1 = (x as f32 / (new_width - 1) as f32) * 2.0 - 1.0;
-            let x2 = ((x + 1) as f32 / (new_width - 1) as f32) * 2.0 - 1.0;
-            let y1 = (y as f32 / (new_height - 1) as f32) * 2.0 - 1.0;
-            let y2 = ((y + 1) as f32 / (new_height - 1) as f32) * 2.0 - 1.0;
-
-            let z1 = interpolated[y * new_width + x];
-            let z2 = interpolated[y * new_width + (x + 1)];
-            let z3 = interpolated[(y + 1) * new_width + x];
-            let z4 = interpolated[(y + 1) * new_width + (x + 1)];
-
-            let quad_vertices = [(x1, y1, z1), (x2, y1, z2), (x1, y2, z3), (x2, y2, z4)];
+            let quad_samples = [
+                y * new_width + x,
+                y * new_width + (x + 1),
+                (y + 1) * new_width + x,
+                (y + 1) * new_width + (x + 1),
+            ];
+            let quad_vertices = quad_samples.map(|i| {
+                let p = &positions[i * 3..i * 3 + 3];
+                (p[0], p[1], p[2])
+            });
 
             let triangle_indices = [[0, 1, 2], [1, 3, 2]];
 
             for indices in triangle_indices {
+                if indices.iter().any(|&i| quad_vertices[i].2.is_nan()) {
+                    continue;
+                }
                 let normal = calculate_face_normal(
                     quad_vertices[indices[0]],
                     quad_vertices[indices[1]],
@@ -280,29 +969,352 @@ pub fn mesh_compute(
                 );
 
                 for &i in &indices {
-                    normals.extend_from_slice(&[normal.0, normal.1, normal.2]);
+                    match &shared_normals {
+                        Some(shared) => {
+                            let s = quad_samples[i] * 3;
+                            normals.extend_from_slice(&shared[s..s + 3]);
+                        }
+                        None => normals.extend_from_slice(&[normal.0, normal.1, normal.2]),
+                    }
                     vertices.extend_from_slice(&[
                         quad_vertices[i].0,
                         quad_vertices[i].1,
                         quad_vertices[i].2,
                     ]);
-                    let vertex_z = quad_vertices[i].2;
-
-                    let color: RGB = get_color_for_elevation(vertex_z);
-                    colors.extend_from_slice(&[color.r, color.g, color.b]);
+                    let value = color_values[quad_samples[i]];
+                    colors.extend_from_slice(&ramp.rgb(value));
+                    if let Some(grid_occlusion) = &grid_occlusion {
+                        occlusion.push(grid_occlusion[quad_samples[i]]);
+                    }
                 }
             }
         }
     }
 
     let vertex_count = vertices.len() / 3;
+    apply_occlusion(&mut colors, &occlusion, options);
 
     log(&format!("wasm vertex_count length = {}", vertex_count));
 
-    MeshComputeData {
+    Ok(MeshComputeData {
+        vertices,
+        colors,
+        normals,
+        indices: Vec::new(),
+        occlusion,
+        vertex_count,
+        half_extent: frame.half_extent(),
+    })
+}
+
+/// Indexed variant of `mesh_compute`: one vertex per (tessellated) grid sample
+/// plus a shared index buffer for `drawIndexed`.
+///
+/// Vertices are shared between faces, so `NormalMode::Flat` shades like
+/// `AreaWeighted` here; use `mesh_compute` for a faceted look.
+#[wasm_bindgen]
+pub fn mesh_compute_indexed(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    tessellation_factor: usize,
+    options: &MeshOptions,
+) -> Result<MeshComputeData, JsError> {
+    let (new_width, new_height) =
+        tessellated_size(elevations, width, height, tessellation_factor, 0, options)?;
+
+    let mut source = mask_nodata(elevations, options.nodata);
+    fill_voids_in_place(&mut source, width, height, options.void_fill);
+    let interpolated = interpolate_elevations(
+        &source,
+        width,
+        height,
+        new_width,
+        new_height,
+        options.interpolation,
+    );
+
+    let range = options.elevation_range(&source);
+    let frame = MeshFrame::new(width, height, range.0, options);
+    let vertices = frame.grid_positions(&interpolated, new_width, new_height);
+    let ramp = options.resolved_ramp(range);
+
+    let color_values = grid_color_values(
+        &interpolated,
+        new_width,
+        new_height,
+        options.cell_size_x / tessellation_factor as f32,
+        options.cell_size_y / tessellation_factor as f32,
+        options,
+    );
+
+    let vertex_count = new_width * new_height;
+    let mut colors: Vec<f32> = color_values.iter().flat_map(|&v| ramp.rgb(v)).collect();
+
+    let mesh_heights: Vec<f32> = vertices.chunks_exact(3).map(|p| p[2]).collect();
+    let occlusion = frame
+        .occlusion_scan(&mesh_heights, new_width, new_height, width, options)
+        .map_or_else(Vec::new, |scan| scan.grid());
+    apply_occlusion(&mut colors, &occlusion, options);
+
+    let normals = grid_vertex_normals(&vertices, new_width, new_height, options.normal_mode);
+    let indices = drop_void_triangles(grid_indices(new_width, new_height), &vertices);
+
+    Ok(MeshComputeData {
         vertices,
         colors,
         normals,
+        indices,
+        occlusion,
         vertex_count,
+        half_extent: frame.half_extent(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width` × `height` grid rising by `step` per column.
+    fn ramp(width: usize, height: usize, step: f32) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| (i % width) as f32 * step)
+            .collect()
+    }
+
+    #[test]
+    fn indexed_mesh_shares_one_vertex_per_sample() {
+        let elevations = ramp(4, 3, 0.1);
+        let mesh = mesh_compute_indexed(&elevations, 4, 3, 1, &MeshOptions::default()).unwrap();
+        assert_eq!(mesh.vertex_count, 12);
+        assert_eq!(mesh.vertices.len(), 12 * 3);
+        assert_eq!(mesh.indices.len(), 3 * 2 * 6);
+        assert!(mesh.indices.iter().all(|&i| i < 12));
+        assert!(mesh.uses_u16_indices());
+
+        let tessellated =
+            mesh_compute_indexed(&elevations, 4, 3, 2, &MeshOptions::default()).unwrap();
+        assert_eq!(tessellated.vertex_count, 7 * 5);
+        assert_eq!(tessellated.indices.len(), 6 * 4 * 6);
+    }
+
+    #[test]
+    fn indexed_mesh_matches_the_triangle_soup() {
+        let elevations = ramp(5, 4, 0.05);
+        let options = MeshOptions::default();
+        let soup = mesh_compute(&elevations, 5, 4, 1, &options).unwrap();
+        let indexed = mesh_compute_indexed(&elevations, 5, 4, 1, &options).unwrap();
+        assert_eq!(soup.vertex_count, 4 * 3 * 6);
+        assert!(soup.indices.is_empty());
+
+        // Same triangles, corner for corner
+        let corners: Vec<f32> = indexed
+            .indices
+            .iter()
+            .flat_map(|&i| indexed.vertices[i as usize * 3..i as usize * 3 + 3].to_vec())
+            .collect();
+        assert_eq!(corners, soup.vertices);
+    }
+
+    #[test]
+    fn indexed_mesh_drops_triangles_touching_voids() {
+        let mut elevations = ramp(3, 3, 0.1);
+        elevations[4] = f32::NAN;
+        let mesh = mesh_compute_indexed(&elevations, 3, 3, 1, &MeshOptions::default()).unwrap();
+        assert_eq!(mesh.vertex_count, 9);
+        // Of the eight triangles only the outer halves of the top-left and
+        // bottom-right quads miss the centre
+        assert_eq!(mesh.indices, vec![0, 1, 3, 5, 8, 7]);
+
+        elevations[4] = 0.1;
+        elevations[0] = f32::NAN;
+        let mesh = mesh_compute_indexed(&elevations, 3, 3, 1, &MeshOptions::default()).unwrap();
+        // Only the first triangle of the top-left quad touches sample 0
+        assert_eq!(mesh.indices.len(), (8 - 1) * 3);
+    }
+
+    #[test]
+    fn index_format_follows_the_vertex_count() {
+        let options = MeshOptions {
+            max_vertices: usize::MAX,
+            ..MeshOptions::default()
+        };
+        let small = mesh_compute_indexed(&vec![0.0; 256 * 256], 256, 256, 1, &options).unwrap();
+        assert!(small.uses_u16_indices());
+        let large = mesh_compute_indexed(&vec![0.0; 257 * 256], 257, 256, 1, &options).unwrap();
+        assert!(!large.uses_u16_indices());
+    }
+
+    /// Unit normal of the plane through the first three mesh vertices of a
+    /// ramp, facing up.
+    fn plane_normal(vertices: &[f32], width: usize) -> [f32; 3] {
+        let p = |i: usize| (vertices[i * 3], vertices[i * 3 + 1], vertices[i * 3 + 2]);
+        let n = calculate_face_normal(p(0), p(width), p(1));
+        let n = if n.2 < 0.0 { (-n.0, -n.1, -n.2) } else { n };
+        [n.0, n.1, n.2]
+    }
+
+    fn assert_all_normals(normals: &[f32], expected: [f32; 3]) {
+        for n in normals.chunks_exact(3) {
+            for k in 0..3 {
+                assert!(
+                    (n[k] - expected[k]).abs() < 1e-5,
+                    "{:?} != {:?}",
+                    n,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_normal_mode_is_exact_on_a_plane() {
+        let elevations = ramp(5, 4, 0.2);
+        let expected = {
+            let mesh = mesh_compute_indexed(&elevations, 5, 4, 1, &MeshOptions::default()).unwrap();
+            plane_normal(&mesh.vertices, 5)
+        };
+        assert!(expected[0] < 0.0 && expected[1].abs() < 1e-6);
+
+        for mode in [
+            NormalMode::Flat,
+            NormalMode::Smooth,
+            NormalMode::AreaWeighted,
+        ] {
+            let options = MeshOptions {
+                normal_mode: mode,
+                ..MeshOptions::default()
+            };
+            let soup = mesh_compute(&elevations, 5, 4, 1, &options).unwrap();
+            assert_all_normals(&soup.normals, expected);
+            let indexed = mesh_compute_indexed(&elevations, 5, 4, 1, &options).unwrap();
+            assert_all_normals(&indexed.normals, expected);
+        }
+    }
+
+    #[test]
+    fn flat_normals_are_per_face_and_shared_ones_are_blended() {
+        // A single ridge along the middle column
+        let elevations: Vec<f32> = (0..9).map(|i| if i % 3 == 1 { 0.5 } else { 0.0 }).collect();
+        let flat = mesh_compute(&elevations, 3, 3, 1, &MeshOptions::default()).unwrap();
+        for triangle in flat.normals.chunks_exact(9) {
+            assert_eq!(triangle[0..3], triangle[3..6]);
+            assert_eq!(triangle[0..3], triangle[6..9]);
+            assert!(triangle[0].abs() > 0.1);
+        }
+
+        // On the ridge the blended normals point straight up
+        for mode in [
+            NormalMode::Smooth,
+            NormalMode::AreaWeighted,
+            NormalMode::Flat,
+        ] {
+            let options = MeshOptions {
+                normal_mode: mode,
+                ..MeshOptions::default()
+            };
+            let mesh = mesh_compute_indexed(&elevations, 3, 3, 1, &options).unwrap();
+            assert_all_normals(&mesh.normals[4 * 3..5 * 3], [0.0, 0.0, 1.0]);
+            for n in mesh.normals.chunks_exact(3) {
+                let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+                assert!((length - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn grids_must_match_their_dimensions() {
+        assert_eq!(check_grid(&[0.0; 6], 2, 3), Ok(()));
+        for len in [5, 7] {
+            assert_eq!(
+                check_grid(&vec![0.0; len], 2, 3),
+                Err(MeshError::DimensionMismatch {
+                    expected: 6,
+                    actual: len
+                })
+            );
+        }
+        for (width, height) in [(1, 4), (4, 1)] {
+            assert_eq!(
+                check_grid(&[0.0; 4], width, height),
+                Err(MeshError::GridTooSmall { width, height })
+            );
+        }
+    }
+
+    #[test]
+    fn tessellation_is_bounded_by_the_vertex_limit() {
+        let elevations = ramp(4, 3, 0.1);
+        let options = MeshOptions {
+            max_vertices: 36,
+            ..MeshOptions::default()
+        };
+        assert_eq!(
+            tessellated_size(&elevations, 4, 3, 0, 0, &options),
+            Err(MeshError::ZeroTessellation)
+        );
+        // 3 × 2 quads of triangle soup take exactly the limit
+        assert_eq!(
+            tessellated_size(&elevations, 4, 3, 1, 6, &options),
+            Ok((4, 3))
+        );
+        assert_eq!(
+            tessellated_size(&elevations, 4, 3, 2, 0, &options),
+            Ok((7, 5))
+        );
+        assert_eq!(
+            tessellated_size(&elevations, 4, 3, 2, 6, &options),
+            Err(MeshError::OutputTooLarge {
+                vertices: 6 * 4 * 6,
+                limit: 36
+            })
+        );
+        let overflow = tessellated_size(&elevations, 4, 3, usize::MAX, 0, &options);
+        assert_eq!(
+            overflow,
+            Err(MeshError::OutputTooLarge {
+                vertices: usize::MAX,
+                limit: 36
+            })
+        );
+        assert_eq!(
+            overflow.unwrap_err().to_string(),
+            "mesh would exceed the limit of 36 vertices"
+        );
+    }
+
+    #[test]
+    fn occlusion_darkens_colors_when_asked() {
+        let pit: Vec<f32> = (0..81)
+            .map(|i| {
+                let (x, y) = ((i % 9) as f32 - 4.0, (i / 9) as f32 - 4.0);
+                0.02 * (x * x + y * y)
+            })
+            .collect();
+        let options = MeshOptions {
+            occlusion_directions: 8,
+            occlusion_radius: 4.0,
+            ..MeshOptions::default()
+        };
+        let plain = mesh_compute_indexed(&pit, 9, 9, 1, &options).unwrap();
+        assert_eq!(plain.occlusion.len(), 81);
+        assert!(plain.occlusion[40] < 1.0);
+
+        let baked = MeshOptions {
+            occlusion_in_colors: true,
+            ..options
+        };
+        let shaded = mesh_compute_indexed(&pit, 9, 9, 1, &baked).unwrap();
+        assert_eq!(shaded.occlusion, plain.occlusion);
+        for (v, &sky) in plain.occlusion.iter().enumerate() {
+            for c in 0..3 {
+                let expected = plain.colors[v * 3 + c] * sky;
+                assert!((shaded.colors[v * 3 + c] - expected).abs() < 1e-6);
+            }
+        }
+
+        let off = mesh_compute_indexed(&pit, 9, 9, 1, &MeshOptions::default()).unwrap();
+        assert!(off.occlusion.is_empty());
+        assert_eq!(off.colors, plain.colors);
     }
 }
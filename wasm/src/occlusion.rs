@@ -0,0 +1,161 @@
+use std::f32::consts::TAU;
+
+/// Growth of the march step along each direction: the horizon is sampled
+/// densely near the vertex and sparsely far out, where a sample changes the
+/// elevation angle less.
+const STEP_GROWTH: f32 = 1.2;
+
+/// Horizon scan over a grid of heights for baked ambient occlusion.
+pub(crate) struct HorizonScan<'a> {
+    heights: &'a [f32],
+    width: usize,
+    height: usize,
+    spacing_x: f32,
+    spacing_y: f32,
+    /// Unit step per direction, in samples.
+    directions: Vec<(f32, f32)>,
+    radius: f32,
+}
+
+impl<'a> HorizonScan<'a> {
+    /// Scan `directions` azimuths up to `radius` samples away over a
+    /// `width` × `height` grid of `heights` (NaN for voids) whose samples are
+    /// `spacing_x` × `spacing_y` apart in the height unit.
+    pub(crate) fn new(
+        heights: &'a [f32],
+        width: usize,
+        height: usize,
+        (spacing_x, spacing_y): (f32, f32),
+        directions: usize,
+        radius: f32,
+    ) -> HorizonScan<'a> {
+        // Offset by half a step so no direction runs exactly along the grid
+        let directions = (0..directions)
+            .map(|k| {
+                let angle = (k as f32 + 0.5) * TAU / directions as f32;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        HorizonScan {
+            heights,
+            width,
+            height,
+            spacing_x,
+            spacing_y,
+            directions,
+            radius,
+        }
+    }
+
+    /// Sky-view factor of the sample at (x, y) (Zakšek et al. 2011): one
+    /// minus the mean sine of the horizon elevation angle over all
+    /// directions. 1 is fully open sky (peaks, plains), lower values are
+    /// enclosed (valleys, pits). Voids and scans without directions give 1.
+    pub(crate) fn sky_view(&self, x: usize, y: usize) -> f32 {
+        let centre = self.heights[y * self.width + x];
+        if centre.is_nan() || self.directions.is_empty() {
+            return 1.0;
+        }
+
+        let mut sum = 0.0;
+        for &(dx, dy) in &self.directions {
+            let step_length = (dx * self.spacing_x).hypot(dy * self.spacing_y);
+            let mut max_tan = 0.0f32;
+            let mut t = 1.0f32;
+            while t <= self.radius {
+                let (sx, sy) = (x as f32 + dx * t, y as f32 + dy * t);
+                if sx < 0.0
+                    || sy < 0.0
+                    || sx > (self.width - 1) as f32
+                    || sy > (self.height - 1) as f32
+                {
+                    break;
+                }
+                let z = self.height_at(sx, sy);
+                if !z.is_nan() {
+                    max_tan = max_tan.max((z - centre) / (t * step_length));
+                }
+                t = (t * STEP_GROWTH).max(t + 1.0);
+            }
+            sum += max_tan / (1.0 + max_tan * max_tan).sqrt();
+        }
+        1.0 - sum / self.directions.len() as f32
+    }
+
+    /// Sky-view factor of every sample.
+    pub(crate) fn grid(&self) -> Vec<f32> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| self.sky_view(x, y)))
+            .collect()
+    }
+
+    /// Bilinear height at a fractional sample position inside the grid.
+    fn height_at(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: usize, y: usize| self.heights[y * self.width + x];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+        top + (bottom - top) * fy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 21 × 21 bowl rising with the squared distance from its centre.
+    fn bowl() -> Vec<f32> {
+        (0..21 * 21)
+            .map(|i| {
+                let (x, y) = ((i % 21) as f32 - 10.0, (i / 21) as f32 - 10.0);
+                (x * x + y * y) * 0.05
+            })
+            .collect()
+    }
+
+    #[test]
+    fn open_plain_sees_the_whole_sky() {
+        let flat = [3.0; 100];
+        let scan = HorizonScan::new(&flat, 10, 10, (1.0, 1.0), 8, 5.0);
+        assert!(scan.grid().iter().all(|&s| s == 1.0));
+    }
+
+    #[test]
+    fn pit_floor_is_darker_than_its_rim() {
+        let z = bowl();
+        let scan = HorizonScan::new(&z, 21, 21, (1.0, 1.0), 16, 10.0);
+        let (floor, rim) = (scan.sky_view(10, 10), scan.sky_view(10, 0));
+        assert!(floor < 1.0 && floor < rim, "floor {floor}, rim {rim}");
+        // Voids and scans without directions are left unshaded
+        let mut voided = z.clone();
+        voided[10 * 21 + 10] = f32::NAN;
+        assert_eq!(
+            HorizonScan::new(&voided, 21, 21, (1.0, 1.0), 16, 10.0).sky_view(10, 10),
+            1.0
+        );
+        assert_eq!(
+            HorizonScan::new(&z, 21, 21, (1.0, 1.0), 0, 10.0).sky_view(10, 10),
+            1.0
+        );
+    }
+
+    #[test]
+    fn radius_and_directions_change_the_result() {
+        // The bowl steepens outward, so looking farther finds higher horizons
+        let z = bowl();
+        let near = HorizonScan::new(&z, 21, 21, (1.0, 1.0), 16, 2.0).sky_view(10, 10);
+        let far = HorizonScan::new(&z, 21, 21, (1.0, 1.0), 16, 10.0).sky_view(10, 10);
+        assert!(far < near, "near {near}, far {far}");
+
+        // A wall on one side only: fewer directions sample it differently
+        let wall: Vec<f32> = (0..21 * 21)
+            .map(|i| if i % 21 >= 14 { 5.0 } else { 0.0 })
+            .collect();
+        let coarse = HorizonScan::new(&wall, 21, 21, (1.0, 1.0), 4, 10.0).sky_view(10, 10);
+        let fine = HorizonScan::new(&wall, 21, 21, (1.0, 1.0), 32, 10.0).sky_view(10, 10);
+        assert!(coarse < 1.0 && fine < 1.0);
+        assert!((coarse - fine).abs() > 1e-3, "{coarse} vs {fine}");
+    }
+}
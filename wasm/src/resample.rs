@@ -0,0 +1,253 @@
+use wasm_bindgen::prelude::*;
+
+/// Reconstruction filter used to densify the height grid.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Height of the closest input sample (blocky, keeps exact values).
+    Nearest,
+    /// Linear in x and y; continuous but creased along input cell borders.
+    Bilinear,
+    /// Keys cubic convolution with a = -0.75 (the common "bicubic"), a
+    /// little sharper than Catmull-Rom.
+    Bicubic,
+    /// Keys cubic convolution with a = -0.5: passes through the samples with
+    /// a continuous slope.
+    CatmullRom,
+    /// Cubic B-spline: continuous curvature, the smoothest option, but it
+    /// approximates rather than passes through the samples (peaks are
+    /// slightly flattened).
+    BSpline,
+}
+
+/// Resample a `original_width` × `original_height` grid to `new_width` ×
+/// `new_height`, with the corner samples of both grids aligned. Samples
+/// outside the grid repeat the nearest edge sample.
+///
+/// NaN samples are voids. Bilinear leaves them out and renormalizes the
+/// remaining corner weights, so a new sample is only a void when all corners
+/// it depends on are; the cubic filters fall back to that wherever their
+/// 4 × 4 footprint touches a void.
+pub(crate) fn interpolate_elevations(
+    elevations: &[f32],
+    original_width: usize,
+    original_height: usize,
+    new_width: usize,
+    new_height: usize,
+    mode: Interpolation,
+) -> Vec<f32> {
+    let mut interpolated = vec![0.0; new_width * new_height];
+
+    for y in 0..new_height {
+        for x in 0..new_width {
+            let orig_x = (x as f32 * (original_width as f32 - 1.0)) / (new_width as f32 - 1.0);
+            let orig_y = (y as f32 * (original_height as f32 - 1.0)) / (new_height as f32 - 1.0);
+            interpolated[y * new_width + x] = sample_elevation(
+                elevations,
+                original_width,
+                original_height,
+                orig_x,
+                orig_y,
+                mode,
+            );
+        }
+    }
+
+    interpolated
+}
+
+/// Height at the fractional sample position (`x`, `y`) of a `width` ×
+/// `height` grid, reconstructed with `mode`. Edges and voids are handled as
+/// in `interpolate_elevations`.
+pub(crate) fn sample_elevation(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    x: f32,
+    y: f32,
+    mode: Interpolation,
+) -> f32 {
+    let at = |x: isize, y: isize| {
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        elevations[y * width + x]
+    };
+
+    let x1 = x.floor();
+    let y1 = y.floor();
+    let dx = x - x1;
+    let dy = y - y1;
+    let (x1, y1) = (x1 as isize, y1 as isize);
+
+    let kernel: fn(f32) -> f32 = match mode {
+        Interpolation::Nearest => return at(x.round() as isize, y.round() as isize),
+        Interpolation::Bilinear => return bilinear(at, x1, y1, dx, dy),
+        Interpolation::Bicubic => |t| keys(t, -0.75),
+        Interpolation::CatmullRom => |t| keys(t, -0.5),
+        Interpolation::BSpline => b_spline,
+    };
+
+    // Separable 4 × 4 filter over samples x1-1..=x1+2, y1-1..=y1+2
+    let wx = [
+        kernel(1.0 + dx),
+        kernel(dx),
+        kernel(1.0 - dx),
+        kernel(2.0 - dx),
+    ];
+    let wy = [
+        kernel(1.0 + dy),
+        kernel(dy),
+        kernel(1.0 - dy),
+        kernel(2.0 - dy),
+    ];
+    let mut sum = 0.0;
+    for (j, wy) in wy.iter().enumerate() {
+        for (i, wx) in wx.iter().enumerate() {
+            sum += at(x1 + i as isize - 1, y1 + j as isize - 1) * wx * wy;
+        }
+    }
+
+    if sum.is_nan() {
+        bilinear(at, x1, y1, dx, dy)
+    } else {
+        sum
+    }
+}
+
+/// Void-aware bilinear blend of the cell with top-left sample (x1, y1).
+fn bilinear(at: impl Fn(isize, isize) -> f32, x1: isize, y1: isize, dx: f32, dy: f32) -> f32 {
+    let corners = [
+        (at(x1, y1), (1.0 - dx) * (1.0 - dy)),
+        (at(x1 + 1, y1), dx * (1.0 - dy)),
+        (at(x1, y1 + 1), (1.0 - dx) * dy),
+        (at(x1 + 1, y1 + 1), dx * dy),
+    ];
+
+    let (mut sum, mut weight) = (0.0, 0.0);
+    for (z, w) in corners {
+        if w > 0.0 && !z.is_nan() {
+            sum += z * w;
+            weight += w;
+        }
+    }
+
+    if weight > 0.0 {
+        sum / weight
+    } else {
+        f32::NAN
+    }
+}
+
+/// Keys (1981) cubic convolution kernel with free parameter `a`.
+fn keys(t: f32, a: f32) -> f32 {
+    let t = t.abs();
+    if t <= 1.0 {
+        ((a + 2.0) * t - (a + 3.0)) * t * t + 1.0
+    } else if t < 2.0 {
+        ((a * t - 5.0 * a) * t + 8.0 * a) * t - 4.0 * a
+    } else {
+        0.0
+    }
+}
+
+/// Uniform cubic B-spline basis.
+fn b_spline(t: f32) -> f32 {
+    let t = t.abs();
+    if t < 1.0 {
+        (4.0 - 6.0 * t * t + 3.0 * t * t * t) / 6.0
+    } else if t < 2.0 {
+        (2.0 - t).powi(3) / 6.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERPOLATING: [Interpolation; 4] = [
+        Interpolation::Nearest,
+        Interpolation::Bilinear,
+        Interpolation::Bicubic,
+        Interpolation::CatmullRom,
+    ];
+
+    /// 6 × 5 grid of uneven heights.
+    fn bumpy() -> Vec<f32> {
+        (0..30)
+            .map(|i| ((i * 7 % 11) as f32 * 0.37).sin())
+            .collect()
+    }
+
+    #[test]
+    fn interpolating_filters_pass_through_the_samples() {
+        let z = bumpy();
+        for mode in INTERPOLATING {
+            for (i, &expected) in z.iter().enumerate() {
+                let (x, y) = ((i % 6) as f32, (i / 6) as f32);
+                assert_eq!(sample_elevation(&z, 6, 5, x, y, mode), expected, "{mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn b_spline_smooths_peaks_but_keeps_planes() {
+        let mut spike = vec![0.0; 25];
+        spike[12] = 1.0;
+        let top = sample_elevation(&spike, 5, 5, 2.0, 2.0, Interpolation::BSpline);
+        assert!((top - 4.0 / 9.0).abs() < 1e-6);
+
+        let plane: Vec<f32> = (0..36)
+            .map(|i| (i % 6) as f32 + 2.0 * (i / 6) as f32)
+            .collect();
+        let z = sample_elevation(&plane, 6, 6, 2.25, 2.5, Interpolation::BSpline);
+        assert!((z - 7.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn edges_repeat_the_last_row_and_column() {
+        let z = bumpy();
+        for mode in INTERPOLATING.into_iter().chain([Interpolation::BSpline]) {
+            // Resampling keeps the corners in place
+            let dense = interpolate_elevations(&z, 6, 5, 11, 9, mode);
+            if mode != Interpolation::BSpline {
+                assert_eq!(dense[10], z[5], "{mode:?}");
+                assert_eq!(dense[98], z[29], "{mode:?}");
+            }
+            assert!(dense.iter().all(|v| v.is_finite()));
+        }
+        // Past the last column the edge sample holds
+        for x in [5.0, 5.5, 7.0] {
+            assert_eq!(
+                sample_elevation(&z, 6, 5, x, 4.0, Interpolation::Bilinear),
+                z[29]
+            );
+        }
+    }
+
+    #[test]
+    fn voids_fall_back_to_bilinear() {
+        let mut z = bumpy();
+        z[6 + 1] = f32::NAN;
+        // The cubic 4 × 4 window around cell (2, 2) reaches the void at (1, 1)
+        let bilinear = sample_elevation(&z, 6, 5, 2.5, 2.5, Interpolation::Bilinear);
+        for mode in [
+            Interpolation::Bicubic,
+            Interpolation::CatmullRom,
+            Interpolation::BSpline,
+        ] {
+            assert_eq!(
+                sample_elevation(&z, 6, 5, 2.5, 2.5, mode),
+                bilinear,
+                "{mode:?}"
+            );
+        }
+        // Next to the void the other three corners share its weight
+        let (a, b, c) = (z[2 * 6 + 1], z[6 + 2], z[2 * 6 + 2]);
+        let renormalized = sample_elevation(&z, 6, 5, 1.5, 1.5, Interpolation::Bilinear);
+        assert!((renormalized - (a + b + c) / 3.0).abs() < 1e-6);
+        // On the void itself there is nothing to blend
+        assert!(sample_elevation(&z, 6, 5, 1.0, 1.0, Interpolation::Bicubic).is_nan());
+    }
+}
@@ -0,0 +1,384 @@
+use wasm_bindgen::prelude::*;
+
+use crate::voids::{fill_voids_in_place, mask_nodata};
+use crate::{
+    check_grid, mesh_from_samples, ElevationInput, MeshComputeData, MeshError, MeshOptions,
+};
+
+/// Right-triangulated irregular network (RTIN) over a heightfield, after
+/// Evans et al. (2001) and Mapbox's Martini.
+///
+/// The grid is padded with voids to a square of 2^k + 1 samples (the
+/// smallest one holding the input), and the approximation error of every
+/// possible triangle split is computed once. `mesh` then extracts an indexed
+/// mesh for any error threshold in a single pass over the triangle
+/// hierarchy; the padding is never meshed and only forces full resolution
+/// along the right and bottom edges of non-square inputs.
+#[wasm_bindgen]
+pub struct RtinTerrain {
+    /// Side of the padded grid, 2^k + 1.
+    size: usize,
+    width: usize,
+    height: usize,
+    /// Input heights, NaN for voids.
+    heights: Vec<f32>,
+    /// Largest error (in metres) introduced by not splitting at each sample
+    /// of the padded grid.
+    errors: Vec<f32>,
+    options: MeshOptions,
+}
+
+#[wasm_bindgen]
+impl RtinTerrain {
+    /// Build the error map for a `width` × `height` grid. `options` are kept
+    /// for every `mesh` call; voids are filled or masked as for
+    /// `mesh_compute`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        elevations: &[f32],
+        width: usize,
+        height: usize,
+        options: &MeshOptions,
+    ) -> Result<RtinTerrain, JsError> {
+        Ok(RtinTerrain::build(elevations, width, height, options)?)
+    }
+
+    /// Side length of the padded grid, in samples.
+    #[wasm_bindgen(getter)]
+    pub fn grid_size(&self) -> usize {
+        self.size
+    }
+
+    /// Largest approximation error in the map, i.e. the `max_error` below
+    /// which the mesh starts to refine.
+    #[wasm_bindgen(getter)]
+    pub fn max_error(&self) -> f32 {
+        self.errors
+            .iter()
+            .copied()
+            .filter(|e| e.is_finite())
+            .fold(0.0, f32::max)
+    }
+
+    /// Indexed mesh whose vertical error against the input grid stays
+    /// within `max_error` metres. Triangles touching voids are left out.
+    pub fn mesh(&self, max_error: f32) -> MeshComputeData {
+        let (width, height) = (self.width, self.height);
+        let mut vertex_ids = vec![0u32; width * height]; // id + 1, 0 = unused
+        let mut samples = Vec::new();
+        let mut indices = Vec::new();
+
+        let last = self.size - 1;
+        let mut extract = Extract {
+            size: self.size,
+            width,
+            height,
+            errors: &self.errors,
+            max_error,
+            vertex_ids: &mut vertex_ids,
+            samples: &mut samples,
+            indices: &mut indices,
+        };
+        extract.triangle([0, 0], [last, last], [last, 0]);
+        extract.triangle([last, last], [0, 0], [0, last]);
+
+        mesh_from_samples(
+            &self.heights,
+            width,
+            height,
+            (width, height),
+            &samples,
+            indices,
+            &self.options,
+        )
+    }
+}
+
+impl RtinTerrain {
+    /// Native counterpart of the constructor.
+    pub fn build(
+        elevations: &[f32],
+        width: usize,
+        height: usize,
+        options: &MeshOptions,
+    ) -> Result<RtinTerrain, MeshError> {
+        check_grid(elevations, width, height)?;
+        let size = (width.max(height) - 1).max(2).next_power_of_two() + 1;
+        if size * size > options.max_vertices {
+            return Err(MeshError::OutputTooLarge {
+                vertices: size * size,
+                limit: options.max_vertices,
+            });
+        }
+
+        let mut heights = mask_nodata(elevations, options.nodata);
+        fill_voids_in_place(&mut heights, width, height, options.void_fill);
+        let mut padded = vec![f32::NAN; size * size];
+        for (row, line) in heights.chunks_exact(width).enumerate() {
+            padded[row * size..row * size + width].copy_from_slice(line);
+        }
+
+        let metres_per_unit = match options.elevation_input {
+            ElevationInput::Normalized => options.metres_per_unit,
+            ElevationInput::Metres => 1.0,
+        };
+        let errors = error_map(&padded, size, metres_per_unit);
+
+        Ok(RtinTerrain {
+            size,
+            width,
+            height,
+            heights,
+            errors,
+            options: options.clone(),
+        })
+    }
+}
+
+/// Per-sample error map: at each triangle's hypotenuse midpoint, the largest
+/// height difference between the triangle's plane and the input samples it
+/// covers, maxed with the errors of its children so that refinement is
+/// always conforming. Any extracted triangle is then within its threshold at
+/// every sample, not only at the split points. Voids and the padding get an
+/// infinite error, forcing full resolution around them.
+fn error_map(heights: &[f32], size: usize, metres_per_unit: f32) -> Vec<f32> {
+    let tile = size - 1;
+    let num_triangles = tile * tile * 2 - 2;
+    let num_parents = num_triangles - tile * tile;
+    let mut errors = vec![0.0f32; size * size];
+
+    // Visit triangles finest first, so children are done before parents
+    for i in (0..num_triangles).rev() {
+        let (a, b, c) = triangle_coords(i, tile);
+        let middle = ((a[1] + b[1]) / 2) * size + (a[0] + b[0]) / 2;
+
+        let error = triangle_error(heights, size, [a, b, c]) * metres_per_unit.abs();
+        let error = if error.is_nan() { f32::INFINITY } else { error };
+        errors[middle] = errors[middle].max(error);
+
+        if i < num_parents {
+            let left = ((a[1] + c[1]) / 2) * size + (a[0] + c[0]) / 2;
+            let right = ((b[1] + c[1]) / 2) * size + (b[0] + c[0]) / 2;
+            errors[middle] = errors[middle].max(errors[left]).max(errors[right]);
+        }
+    }
+
+    errors
+}
+
+/// Largest vertical distance between the plane through the corners of a
+/// triangle and the samples inside it (edges included), NaN if any of them
+/// is a void.
+fn triangle_error(heights: &[f32], size: usize, corners: [[usize; 2]; 3]) -> f32 {
+    let [a, b, c] = corners.map(|[x, y]| [x as f32, y as f32]);
+    let [za, zb, zc] = corners.map(|[x, y]| heights[y * size + x]);
+    let area = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+    let [xs, ys] = [0, 1].map(|k| corners.map(|p| p[k]));
+    let (x0, x1) = (xs.into_iter().min().unwrap(), xs.into_iter().max().unwrap());
+    let (y0, y1) = (ys.into_iter().min().unwrap(), ys.into_iter().max().unwrap());
+
+    let mut error = 0.0f32;
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let (px, py) = (x as f32, y as f32);
+            let wb = ((px - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (py - a[1])) / area;
+            let wc = ((b[0] - a[0]) * (py - a[1]) - (px - a[0]) * (b[1] - a[1])) / area;
+            let wa = 1.0 - wb - wc;
+            if wa < -1e-6 || wb < -1e-6 || wc < -1e-6 {
+                continue;
+            }
+            let z = heights[y * size + x];
+            if z.is_nan() {
+                return f32::NAN;
+            }
+            error = error.max((wa * za + wb * zb + wc * zc - z).abs());
+        }
+    }
+    error
+}
+
+/// Corners (a, b, c) of triangle `i` in the implicit binary tree, with a–b
+/// the hypotenuse and c the right-angle corner. Triangles 0 and 1 are the
+/// two halves of the tile; the children of `id` are `2 * id` and
+/// `2 * id + 1` (ids offset by 2 from `i`).
+fn triangle_coords(i: usize, tile: usize) -> ([usize; 2], [usize; 2], [usize; 2]) {
+    let mut id = i + 2;
+    let (mut a, mut b, mut c) = if id & 1 == 1 {
+        ([0, 0], [tile, tile], [tile, 0])
+    } else {
+        ([tile, tile], [0, 0], [0, tile])
+    };
+    while {
+        id >>= 1;
+        id > 1
+    } {
+        let m = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2];
+        if id & 1 == 1 {
+            b = a;
+            a = c;
+        } else {
+            a = b;
+            b = c;
+        }
+        c = m;
+    }
+    (a, b, c)
+}
+
+/// State of one mesh extraction walk over the padded grid; vertices are
+/// numbered by their sample in the `width` × `height` input.
+struct Extract<'a> {
+    size: usize,
+    width: usize,
+    height: usize,
+    errors: &'a [f32],
+    max_error: f32,
+    vertex_ids: &'a mut [u32],
+    samples: &'a mut Vec<usize>,
+    indices: &'a mut Vec<u32>,
+}
+
+impl Extract<'_> {
+    fn triangle(&mut self, a: [usize; 2], b: [usize; 2], c: [usize; 2]) {
+        let inside = |p: [usize; 2]| p[0] < self.width && p[1] < self.height;
+        let corner = [a[0].min(b[0]).min(c[0]), a[1].min(b[1]).min(c[1])];
+        if !inside(corner) {
+            // Wholly in the padding
+            return;
+        }
+        let m = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2];
+        let splittable = a[0].abs_diff(c[0]) + a[1].abs_diff(c[1]) > 1;
+        if splittable && self.errors[m[1] * self.size + m[0]] > self.max_error {
+            self.triangle(c, a, m);
+            self.triangle(b, c, m);
+        } else if [a, b, c].into_iter().all(inside) {
+            // Hierarchy triangles are wound opposite to the grid triangles
+            // of `mesh_compute`; emit a, c, b so faces point up the same way.
+            for p in [a, c, b] {
+                let id = self.vertex_id(p);
+                self.indices.push(id);
+            }
+        }
+    }
+
+    fn vertex_id(&mut self, [x, y]: [usize; 2]) -> u32 {
+        let sample = y * self.width + x;
+        if self.vertex_ids[sample] == 0 {
+            self.samples.push(sample);
+            self.vertex_ids[sample] = self.samples.len() as u32;
+        }
+        self.vertex_ids[sample] - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic bumpy `width` × `height` grid.
+    fn bumps(width: usize, height: usize) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                (x * 1.3).sin() * 0.2 + (y * 0.7).cos() * 0.15 + x * 0.01
+            })
+            .collect()
+    }
+
+    /// Source samples of each triangle of `rtin.mesh(max_error)`, as
+    /// (column, row) corners.
+    fn triangles(rtin: &RtinTerrain, max_error: f32) -> Vec<[(usize, usize); 3]> {
+        let mut vertex_ids = vec![0u32; rtin.width * rtin.height];
+        let (mut samples, mut indices) = (Vec::new(), Vec::new());
+        let last = rtin.size - 1;
+        let mut extract = Extract {
+            size: rtin.size,
+            width: rtin.width,
+            height: rtin.height,
+            errors: &rtin.errors,
+            max_error,
+            vertex_ids: &mut vertex_ids,
+            samples: &mut samples,
+            indices: &mut indices,
+        };
+        extract.triangle([0, 0], [last, last], [last, 0]);
+        extract.triangle([last, last], [0, 0], [0, last]);
+        let corner = |i: u32| {
+            let sample = samples[i as usize];
+            (sample % rtin.width, sample / rtin.width)
+        };
+        indices
+            .chunks_exact(3)
+            .map(|t| [corner(t[0]), corner(t[1]), corner(t[2])])
+            .collect()
+    }
+
+    #[test]
+    fn zero_error_keeps_every_input_sample() {
+        let (width, height) = (6, 4);
+        let elevations = bumps(width, height);
+        let options = MeshOptions::default();
+        let rtin = RtinTerrain::build(&elevations, width, height, &options).unwrap();
+        assert_eq!(rtin.size, 9);
+
+        let mesh = rtin.mesh(0.0);
+        assert_eq!(mesh.vertex_count, width * height);
+        assert_eq!(mesh.indices.len(), (width - 1) * (height - 1) * 6);
+
+        // Same positions as the full-resolution mesh, in some order
+        let full = crate::mesh_compute_indexed(&elevations, width, height, 1, &options).unwrap();
+        let sorted = |v: &[f32]| {
+            let mut points: Vec<[u32; 3]> = v
+                .chunks_exact(3)
+                .map(|p| [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()])
+                .collect();
+            points.sort_unstable();
+            points
+        };
+        assert_eq!(sorted(&mesh.vertices), sorted(&full.vertices));
+    }
+
+    #[test]
+    fn a_plane_needs_only_its_corners() {
+        let elevations: Vec<f32> = (0..81).map(|i| (i % 9) as f32 * 0.1).collect();
+        let rtin = RtinTerrain::build(&elevations, 9, 9, &MeshOptions::default()).unwrap();
+        let mesh = rtin.mesh(1e-3);
+        assert_eq!(mesh.vertex_count, 4);
+        assert_eq!(mesh.indices.len(), 6);
+    }
+
+    #[test]
+    fn error_against_the_input_stays_within_the_threshold() {
+        let (width, height) = (13, 7);
+        let elevations = bumps(width, height);
+        let options = MeshOptions {
+            metres_per_unit: 1.0,
+            ..MeshOptions::default()
+        };
+        let rtin = RtinTerrain::build(&elevations, width, height, &options).unwrap();
+        let z = |(x, y): (usize, usize)| elevations[y * width + x];
+
+        for max_error in [0.01, 0.05, 0.2] {
+            let mut covered = vec![false; width * height];
+            for [a, b, c] in triangles(&rtin, max_error) {
+                let [ax, ay, bx, by, cx, cy] = [a.0, a.1, b.0, b.1, c.0, c.1].map(|v| v as f32);
+                let area = (bx - ax) * (cy - ay) - (cx - ax) * (by - ay);
+                for y in a.1.min(b.1).min(c.1)..=a.1.max(b.1).max(c.1) {
+                    for x in a.0.min(b.0).min(c.0)..=a.0.max(b.0).max(c.0) {
+                        let (px, py) = (x as f32, y as f32);
+                        let wb = ((px - ax) * (cy - ay) - (cx - ax) * (py - ay)) / area;
+                        let wc = ((bx - ax) * (py - ay) - (px - ax) * (by - ay)) / area;
+                        let wa = 1.0 - wb - wc;
+                        if wa < -1e-6 || wb < -1e-6 || wc < -1e-6 {
+                            continue;
+                        }
+                        covered[y * width + x] = true;
+                        let surface = wa * z(a) + wb * z(b) + wc * z(c);
+                        assert!((surface - z((x, y))).abs() <= max_error + 1e-5);
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&c| c), "mesh must cover the input");
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use wasm_bindgen::prelude::*;
+
+use crate::check_grid;
+use crate::hillshade::{check_lighting, HillshadeOptions};
+use crate::voids::mask_nodata;
+
+const MS_PER_DAY: f64 = 86_400_000.0;
+/// Julian day of the Unix epoch.
+const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+/// Julian day of J2000.0.
+const J2000_JD: f64 = 2_451_545.0;
+
+/// Apparent position of the sun seen from a point on the ground.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SunPosition {
+    /// Degrees clockwise from north.
+    pub azimuth: f64,
+    /// Degrees above the horizon (geometric, without refraction); negative
+    /// at night.
+    pub altitude: f64,
+}
+
+#[wasm_bindgen]
+impl SunPosition {
+    /// Sun position at `timestamp` (milliseconds since the Unix epoch, UTC,
+    /// as `Date.now()`) for a point in degrees. NOAA's low-precision solar
+    /// algorithm, good to about 0.01° for the years 1800–2100.
+    pub fn at(timestamp: f64, latitude: f64, longitude: f64) -> SunPosition {
+        let jd = timestamp / MS_PER_DAY + UNIX_EPOCH_JD;
+        let t = (jd - J2000_JD) / 36525.0; // Julian centuries
+
+        let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+        let mean_anomaly = 357.52911 + t * (35999.05029 - 0.0001537 * t);
+        let eccentricity = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+        let m = mean_anomaly.to_radians();
+        let centre = m.sin() * (1.914602 - t * (0.004817 + 0.000014 * t))
+            + (2.0 * m).sin() * (0.019993 - 0.000101 * t)
+            + (3.0 * m).sin() * 0.000289;
+        let omega = (125.04 - 1934.136 * t).to_radians();
+        let apparent_longitude =
+            (mean_longitude + centre - 0.00569 - 0.00478 * omega.sin()).to_radians();
+
+        let mean_obliquity =
+            23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0;
+        let obliquity = (mean_obliquity + 0.00256 * omega.cos()).to_radians();
+        let declination = (obliquity.sin() * apparent_longitude.sin()).asin();
+
+        // Equation of time, in minutes
+        let y = (obliquity / 2.0).tan().powi(2);
+        let l = mean_longitude.to_radians();
+        let equation_of_time = 4.0
+            * (y * (2.0 * l).sin() - 2.0 * eccentricity * m.sin()
+                + 4.0 * eccentricity * y * m.sin() * (2.0 * l).cos()
+                - 0.5 * y * y * (4.0 * l).sin()
+                - 1.25 * eccentricity * eccentricity * (2.0 * m).sin())
+            .to_degrees();
+
+        let minutes = timestamp.rem_euclid(MS_PER_DAY) / 60_000.0;
+        let solar_time = (minutes + equation_of_time + 4.0 * longitude).rem_euclid(1440.0);
+        let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+
+        let lat = latitude.to_radians();
+        let cos_zenith = (lat.sin() * declination.sin()
+            + lat.cos() * declination.cos() * hour_angle.cos())
+        .clamp(-1.0, 1.0);
+        let zenith = cos_zenith.acos();
+
+        let denominator = lat.cos() * zenith.sin();
+        let azimuth = if denominator.abs() < 1e-12 {
+            // Sun at the zenith or observer at a pole
+            if lat >= 0.0 {
+                180.0
+            } else {
+                0.0
+            }
+        } else {
+            let from_south = ((lat.sin() * cos_zenith - declination.sin()) / denominator)
+                .clamp(-1.0, 1.0)
+                .acos()
+                .to_degrees();
+            if hour_angle > 0.0 {
+                (from_south + 180.0).rem_euclid(360.0)
+            } else {
+                (540.0 - from_south).rem_euclid(360.0)
+            }
+        };
+
+        SunPosition {
+            azimuth,
+            altitude: 90.0 - zenith.to_degrees(),
+        }
+    }
+
+    /// Sun position at `timestamp` over the centre of a DEM's longitude /
+    /// latitude bounds (see `ElevationGrid.lon_lat_bounds`).
+    pub fn for_bounds(
+        timestamp: f64,
+        min_lon: f64,
+        min_lat: f64,
+        max_lon: f64,
+        max_lat: f64,
+    ) -> SunPosition {
+        SunPosition::at(
+            timestamp,
+            (min_lat + max_lat) * 0.5,
+            (min_lon + max_lon) * 0.5,
+        )
+    }
+
+    /// Whether the sun is above the horizon.
+    #[wasm_bindgen(getter)]
+    pub fn is_up(&self) -> bool {
+        self.altitude > 0.0
+    }
+
+    /// Unit vector toward the sun in mesh space (x east, y south since row 0
+    /// is north, z up), e.g. for the renderer's light direction.
+    #[wasm_bindgen(getter)]
+    pub fn direction(&self) -> Vec<f32> {
+        let (az, alt) = (self.azimuth.to_radians(), self.altitude.to_radians());
+        vec![
+            (az.sin() * alt.cos()) as f32,
+            (-az.cos() * alt.cos()) as f32,
+            alt.sin() as f32,
+        ]
+    }
+
+    /// Point `options` at this sun position.
+    pub fn apply_to(&self, options: &mut HillshadeOptions) {
+        options.azimuth = self.azimuth as f32;
+        options.altitude = self.altitude as f32;
+    }
+}
+
+/// Cast-shadow mask for the sun at `options.azimuth` / `options.altitude`:
+/// 1 where the terrain between a sample and the sun hides it, 0 where it is
+/// lit. Every sample is shadowed with the sun below the horizon; voids are 0.
+/// Slopes merely facing away from the sun are left to `hillshade`.
+#[wasm_bindgen]
+pub fn shadow_mask(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &HillshadeOptions,
+) -> Result<Vec<u8>, JsError> {
+    check_grid(elevations, width, height)?;
+    check_lighting(options)?;
+    let heights = mask_nodata(elevations, options.nodata);
+    Ok(cast_shadows(&heights, width, height, options)
+        .into_iter()
+        .map(u8::from)
+        .collect())
+}
+
+/// Per sample whether it is in cast shadow, for heights with NaN voids.
+///
+/// A ray is marched from every sample toward the sun one cell at a time
+/// along the major axis, and the sample is shadowed as soon as the terrain
+/// (bilinearly interpolated) rises above the ray. Rays stop at the grid edge
+/// or once above the highest sample. Options failing `check_lighting` give
+/// no shadows.
+pub(crate) fn cast_shadows(
+    heights: &[f32],
+    width: usize,
+    height: usize,
+    options: &HillshadeOptions,
+) -> Vec<bool> {
+    if options.altitude <= 0.0 {
+        return heights.iter().map(|z| !z.is_nan()).collect();
+    }
+    if options.altitude >= 90.0 {
+        // Overhead; tan() of the rounded angle would even turn negative
+        return vec![false; heights.len()];
+    }
+
+    let top = heights
+        .iter()
+        .filter(|z| !z.is_nan())
+        .fold(f32::MIN, |a, &b| a.max(b))
+        * options.z_factor;
+    let az = options.azimuth.to_radians();
+    // Toward the sun in cells per metre; rows grow southward
+    let (dx, dy) = (
+        az.sin() / options.cell_size_x.abs(),
+        -az.cos() / options.cell_size_y.abs(),
+    );
+    let metres_per_step = 1.0 / dx.abs().max(dy.abs());
+    let (dx, dy) = (dx * metres_per_step, dy * metres_per_step);
+    let rise_per_step = metres_per_step * options.altitude.to_radians().tan();
+    if !(dx.is_finite() && dy.is_finite() && rise_per_step.is_finite()) {
+        // The ray would never leave its sample
+        return vec![false; heights.len()];
+    }
+
+    let at = |x: f32, y: f32| {
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let z = |x: usize, y: usize| heights[y * width + x];
+        let upper = z(x0, y0) + (z(x1, y0) - z(x0, y0)) * fx;
+        let lower = z(x0, y1) + (z(x1, y1) - z(x0, y1)) * fx;
+        (upper + (lower - upper) * fy) * options.z_factor
+    };
+    let (max_x, max_y) = ((width - 1) as f32, (height - 1) as f32);
+
+    let mut shadowed = vec![false; heights.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let start = heights[y * width + x];
+            if start.is_nan() {
+                continue;
+            }
+            let (mut px, mut py) = (x as f32, y as f32);
+            let mut ray = start * options.z_factor;
+            loop {
+                px += dx;
+                py += dy;
+                ray += rise_per_step;
+                if ray > top || px < 0.0 || py < 0.0 || px > max_x || py > max_y {
+                    break;
+                }
+                // Voids compare false and let the ray through
+                if at(px, py) > ray {
+                    shadowed[y * width + x] = true;
+                    break;
+                }
+            }
+        }
+    }
+    shadowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-03-20 00:00 UTC, the day of the March equinox.
+    const EQUINOX_DAY: f64 = 1_710_892_800_000.0;
+    const HOUR: f64 = 3_600_000.0;
+
+    #[test]
+    fn equinox_sun_is_overhead_at_the_equator_at_solar_noon() {
+        // The equation of time is about -7.5 minutes, so solar noon on the
+        // Greenwich meridian falls at 12:07:30 UTC
+        let noon = EQUINOX_DAY + 12.125 * HOUR;
+        let sun = SunPosition::at(noon, 0.0, 0.0);
+        assert!((sun.altitude - 90.0).abs() < 0.5, "{sun:?}");
+        // 45° north the noon sun is due south, 45° up
+        let sun = SunPosition::at(noon, 45.0, 0.0);
+        assert!((sun.altitude - 45.0).abs() < 0.5, "{sun:?}");
+        assert!((sun.azimuth - 180.0).abs() < 1.0, "{sun:?}");
+        // Six hours of longitude east it is already evening
+        let sun = SunPosition::at(noon, 0.0, 90.0);
+        assert!(sun.altitude.abs() < 0.5, "{sun:?}");
+    }
+
+    #[test]
+    fn morning_sun_is_in_the_east_and_afternoon_sun_in_the_west() {
+        for latitude in [-40.0, 0.0, 40.0] {
+            let morning = SunPosition::at(EQUINOX_DAY + 9.0 * HOUR, latitude, 0.0);
+            assert!(morning.altitude > 0.0, "{latitude}: {morning:?}");
+            assert!(
+                morning.azimuth > 0.0 && morning.azimuth < 180.0,
+                "{latitude}: {morning:?}"
+            );
+            let afternoon = SunPosition::at(EQUINOX_DAY + 15.0 * HOUR, latitude, 0.0);
+            assert!(afternoon.altitude > 0.0, "{latitude}: {afternoon:?}");
+            assert!(
+                afternoon.azimuth > 180.0 && afternoon.azimuth < 360.0,
+                "{latitude}: {afternoon:?}"
+            );
+        }
+        // Midnight is below the horizon
+        assert!(SunPosition::at(EQUINOX_DAY, 0.0, 0.0).altitude < -80.0);
+    }
+
+    /// 20 × 3 flat grid with a wall of height 5 down column 10.
+    fn wall() -> Vec<f32> {
+        (0..60)
+            .map(|i| if i % 20 == 10 { 5.0 } else { 0.0 })
+            .collect()
+    }
+
+    fn shadowed_columns(heights: &[f32], options: &HillshadeOptions) -> Vec<usize> {
+        let mask = cast_shadows(heights, 20, 3, options);
+        (0..20).filter(|&x| mask[20 + x]).collect()
+    }
+
+    #[test]
+    fn wall_casts_a_shadow_as_long_as_it_is_high() {
+        // Sun in the east at 45°: the shadow covers the 4 cells west of the
+        // wall; 5 cells out the ray is back down at the ground
+        let options = HillshadeOptions {
+            azimuth: 90.0,
+            altitude: 45.0,
+            ..HillshadeOptions::default()
+        };
+        assert_eq!(shadowed_columns(&wall(), &options), vec![6, 7, 8, 9]);
+
+        let west = HillshadeOptions {
+            azimuth: 270.0,
+            ..options.clone()
+        };
+        assert_eq!(shadowed_columns(&wall(), &west), vec![11, 12, 13, 14]);
+
+        let night = HillshadeOptions {
+            altitude: -5.0,
+            ..options.clone()
+        };
+        assert_eq!(shadowed_columns(&wall(), &night).len(), 20);
+        let noon = HillshadeOptions {
+            altitude: 90.0,
+            ..options
+        };
+        assert!(shadowed_columns(&wall(), &noon).is_empty());
+    }
+
+    #[test]
+    fn degenerate_sun_or_cells_cast_no_shadow() {
+        // Each of these used to stall the march on its first sample
+        for options in [
+            HillshadeOptions {
+                cell_size_x: 0.0,
+                cell_size_y: 0.0,
+                ..HillshadeOptions::default()
+            },
+            HillshadeOptions {
+                azimuth: f32::NAN,
+                ..HillshadeOptions::default()
+            },
+        ] {
+            assert!(shadowed_columns(&wall(), &options).is_empty());
+        }
+    }
+}
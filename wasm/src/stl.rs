@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use wasm_bindgen::prelude::*;
+
+use crate::MeshComputeData;
+
+type Point = [f32; 3];
+
+/// Serialize a mesh as a watertight STL solid for 3D printing.
+///
+/// The terrain surface is closed with vertical walls along its outer border
+/// and a flat bottom `base_thickness` below the lowest point. Output is Z-up
+/// with north (row 0 of the grid) toward +Y; `extent_x`/`extent_y` are the
+/// model's footprint and `vertical_scale` the size of one unit of mesh
+/// height, all in the unit the slicer expects (usually millimetres).
+/// The surface must be a heightfield, as produced by `mesh_compute`; voids
+/// left out of it get walls around them too. Facets are wound
+/// counter-clockwise seen from outside.
+#[wasm_bindgen]
+pub fn export_stl(
+    mesh: &MeshComputeData,
+    extent_x: f32,
+    extent_y: f32,
+    vertical_scale: f32,
+    base_thickness: f32,
+    binary: bool,
+) -> Vec<u8> {
+    let triangles = solid_triangles(mesh, extent_x, extent_y, vertical_scale, base_thickness);
+    if binary {
+        write_binary(&triangles)
+    } else {
+        write_ascii(&triangles).into_bytes()
+    }
+}
+
+fn solid_triangles(
+    mesh: &MeshComputeData,
+    extent_x: f32,
+    extent_y: f32,
+    vertical_scale: f32,
+    base_thickness: f32,
+) -> Vec<[Point; 3]> {
+    // Weld the (possibly triangle-soup) mesh so shared edges can be found.
+    // Duplicated soup vertices are bit-identical, so exact matching is enough.
+    let mut points: Vec<Point> = Vec::new();
+    let mut welded: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut remap = Vec::with_capacity(mesh.vertex_count);
+    let [half_x, half_y] = mesh.half_extent.map(|h| h.max(f32::MIN_POSITIVE));
+    for v in mesh.vertices.chunks_exact(3) {
+        // Flipping y puts north up and turns the left-handed mesh frame
+        // right-handed; surface winding is reversed below to match.
+        let p = [
+            v[0] * extent_x * 0.5 / half_x,
+            -v[1] * extent_y * 0.5 / half_y,
+            v[2] * vertical_scale,
+        ];
+        let key = [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+        let next = points.len() as u32;
+        let id = *welded.entry(key).or_insert_with(|| {
+            points.push(p);
+            next
+        });
+        remap.push(id);
+    }
+
+    let mut surface: Vec<[u32; 3]> = Vec::new();
+    for t in mesh.triangle_indices().chunks_exact(3) {
+        let tri = [
+            remap[t[0] as usize],
+            remap[t[2] as usize],
+            remap[t[1] as usize],
+        ];
+        let finite = tri
+            .iter()
+            .all(|&i| points[i as usize].iter().all(|c| c.is_finite()));
+        let degenerate = tri[0] == tri[1] || tri[1] == tri[2] || tri[0] == tri[2];
+        if finite && !degenerate {
+            surface.push(tri);
+        }
+    }
+
+    // Directed edges used once are on the border; with the surface wound
+    // counter-clockwise from above they run around it with the terrain on
+    // their left.
+    let edges: HashSet<(u32, u32)> = surface
+        .iter()
+        .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .collect();
+    let border: Vec<(u32, u32)> = edges
+        .iter()
+        .filter(|&&(a, b)| !edges.contains(&(b, a)))
+        .copied()
+        .collect();
+
+    let used: HashSet<u32> = surface.iter().flatten().copied().collect();
+    let min_z = used
+        .iter()
+        .map(|&i| points[i as usize][2])
+        .fold(f32::MAX, f32::min);
+    let base_z = min_z - base_thickness.max(0.0);
+
+    let mut triangles: Vec<[Point; 3]> = surface
+        .iter()
+        .map(|t| t.map(|i| points[i as usize]))
+        .collect();
+
+    let base = |i: u32| [points[i as usize][0], points[i as usize][1], base_z];
+    // The bottom repeats the surface triangulation flattened onto the base,
+    // facing down, so it covers any footprint: notched by voids on the
+    // border or with holes around inner voids
+    triangles.extend(surface.iter().map(|&[a, b, c]| [base(a), base(c), base(b)]));
+    // One wall per border edge, on the outer border and around every hole
+    for &(a, b) in &border {
+        let (top_a, top_b) = (points[a as usize], points[b as usize]);
+        // Wall quad facing away from the terrain
+        triangles.push([top_b, top_a, base(a)]);
+        triangles.push([top_b, base(a), base(b)]);
+    }
+
+    triangles
+}
+
+fn facet_normal(t: &[Point; 3]) -> Point {
+    let e1 = [t[1][0] - t[0][0], t[1][1] - t[0][1], t[1][2] - t[0][2]];
+    let e2 = [t[2][0] - t[0][0], t[2][1] - t[0][1], t[2][2] - t[0][2]];
+    let n = [
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if len == 0.0 {
+        return [0.0, 0.0, 0.0];
+    }
+    [n[0] / len, n[1] / len, n[2] / len]
+}
+
+fn write_binary(triangles: &[[Point; 3]]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(84 + triangles.len() * 50);
+    let mut header = [b' '; 80];
+    let title = b"mesh_compute terrain";
+    header[..title.len()].copy_from_slice(title);
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+
+    for t in triangles {
+        for v in std::iter::once(facet_normal(t)).chain(t.iter().copied()) {
+            for c in v {
+                out.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&0u16.to_le_bytes());
+    }
+    out
+}
+
+fn write_ascii(triangles: &[[Point; 3]]) -> String {
+    let mut out = String::from("solid terrain\n");
+    for t in triangles {
+        let n = facet_normal(t);
+        let _ = writeln!(out, "  facet normal {:e} {:e} {:e}", n[0], n[1], n[2]);
+        out.push_str("    outer loop\n");
+        for v in t {
+            let _ = writeln!(out, "      vertex {:e} {:e} {:e}", v[0], v[1], v[2]);
+        }
+        out.push_str("    endloop\n  endfacet\n");
+    }
+    out.push_str("endsolid terrain\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mesh_compute, MeshOptions};
+
+    /// Every edge of a closed, consistently wound solid is used exactly
+    /// once in each direction.
+    fn assert_manifold(triangles: &[[Point; 3]]) {
+        let key = |p: &Point| p.map(f32::to_bits);
+        let mut edges: HashMap<([u32; 3], [u32; 3]), usize> = HashMap::new();
+        for t in triangles {
+            for k in 0..3 {
+                *edges.entry((key(&t[k]), key(&t[(k + 1) % 3]))).or_default() += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge used {count} times in one direction");
+            assert_eq!(edges.get(&(b, a)), Some(&1), "open edge");
+        }
+    }
+
+    #[test]
+    fn terrain_solid_is_closed_and_outward_facing() {
+        let (width, height) = (6, 4);
+        let elevations: Vec<f32> = (0..width * height)
+            .map(|i| ((i % width) as f32 * 0.7).sin() * 0.2 + (i / width) as f32 * 0.05)
+            .collect();
+        let mesh = mesh_compute(&elevations, width, height, 1, &MeshOptions::default()).unwrap();
+        let triangles = solid_triangles(&mesh, 100.0, 80.0, 10.0, 2.0);
+
+        // Surface and bottom, and two wall triangles per border edge
+        let border_edges = 2 * (width - 1 + height - 1);
+        assert_eq!(
+            triangles.len(),
+            (width - 1) * (height - 1) * 4 + border_edges * 2
+        );
+        assert_manifold(&triangles);
+
+        // Divergence theorem: positive volume means normals face outward
+        let volume: f32 = triangles
+            .iter()
+            .map(|[a, b, c]| {
+                (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0]))
+                    / 6.0
+            })
+            .sum();
+        assert!(volume > 0.0, "volume {volume}");
+    }
+
+    /// Volume enclosed by `triangles` (divergence theorem).
+    fn volume(triangles: &[[Point; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|[a, b, c]| {
+                (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                    + a[2] * (b[0] * c[1] - b[1] * c[0]))
+                    / 6.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn voids_on_the_border_and_inside_stay_closed() {
+        // A flat 7 × 7 plate with a void notching the border and
+        // one punching a hole in the middle
+        let mut elevations = vec![1.0; 49];
+        elevations[3] = f32::NAN;
+        elevations[3 * 7 + 3] = f32::NAN;
+        let indexed =
+            crate::mesh_compute_indexed(&elevations, 7, 7, 1, &MeshOptions::default()).unwrap();
+        let soup = mesh_compute(&elevations, 7, 7, 1, &MeshOptions::default()).unwrap();
+        for mesh in [indexed, soup] {
+            let triangles = solid_triangles(&mesh, 6.0, 6.0, 1.0, 1.0);
+            assert_manifold(&triangles);
+            // Flat top over a flat base: volume is the top area times the
+            // thickness, so the bottom mirrors the notched, holed top
+            let top = triangles
+                .iter()
+                .flatten()
+                .map(|p| p[2])
+                .fold(f32::MIN, f32::max);
+            let bottom = triangles
+                .iter()
+                .flatten()
+                .map(|p| p[2])
+                .fold(f32::MAX, f32::min);
+            let area: f32 = triangles
+                .iter()
+                .filter(|t| t.iter().all(|p| p[2] == top))
+                .map(|[a, b, c]| {
+                    ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])) / 2.0
+                })
+                .sum();
+            assert!(area > 0.0 && area < 36.0, "area {area}");
+            let expected = area * (top - bottom);
+            assert!((volume(&triangles) - expected).abs() < 1e-3 * expected);
+        }
+    }
+
+    #[test]
+    fn binary_stl_has_a_record_per_facet() {
+        let elevations = vec![0.0; 9];
+        let mesh = mesh_compute(&elevations, 3, 3, 1, &MeshOptions::default()).unwrap();
+        let triangles = solid_triangles(&mesh, 10.0, 10.0, 1.0, 1.0);
+        let bytes = export_stl(&mesh, 10.0, 10.0, 1.0, 1.0, true);
+        assert_eq!(bytes.len(), 84 + 50 * triangles.len());
+        assert_eq!(
+            u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize,
+            triangles.len()
+        );
+    }
+
+    #[test]
+    fn solid_spans_the_requested_footprint() {
+        // Twice as wide as deep, so the mesh spans ±1 by ±0.5
+        let options = MeshOptions {
+            elevation_input: crate::ElevationInput::Metres,
+            cell_size_x: 20.0,
+            cell_size_y: 10.0,
+            ..MeshOptions::default()
+        };
+        let mesh = mesh_compute(&[5.0; 9], 3, 3, 1, &options).unwrap();
+        let triangles = solid_triangles(&mesh, 120.0, 60.0, 1.0, 1.0);
+        let points = || triangles.iter().flatten();
+        let span = |k: usize| {
+            let lo = points().map(|p| p[k]).fold(f32::MAX, f32::min);
+            let hi = points().map(|p| p[k]).fold(f32::MIN, f32::max);
+            hi - lo
+        };
+        assert_eq!((span(0), span(1)), (120.0, 60.0));
+    }
+}
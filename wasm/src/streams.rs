@@ -0,0 +1,255 @@
+use js_sys::{Float32Array, Uint32Array, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::hydrology::{
+    check_hydrology, d8_accumulation, d8_directions, d8_target, downhill_order, priority_flood,
+    HydrologyOptions,
+};
+use crate::voids::{fill_voids_in_place, mask_nodata};
+use crate::{check_grid, ElevationInput, MeshFrame, MeshOptions};
+
+/// Rise per cell across filled depressions, in metres, so that D8 can route
+/// over them.
+const FILL_INCREMENT: f32 = 1e-3;
+
+/// Stream segments packed like `ContourData`: `vertices` are xyz in mesh
+/// space, `indices` are line-list pairs, and segment `i` spans
+/// `vertices[polyline_offsets[i]..polyline_offsets[i + 1]]`. A segment runs
+/// from a source or confluence down to the next confluence or outlet, so
+/// segments meet at shared end points.
+#[wasm_bindgen]
+pub struct StreamNetwork {
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    polyline_offsets: Vec<u32>,
+    orders: Vec<u8>,
+    areas: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl StreamNetwork {
+    #[wasm_bindgen(getter)]
+    pub fn vertices(&self) -> Float32Array {
+        Float32Array::from(self.vertices.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> Uint32Array {
+        Uint32Array::from(self.indices.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn polyline_offsets(&self) -> Uint32Array {
+        Uint32Array::from(self.polyline_offsets.as_slice())
+    }
+
+    /// Strahler order of each segment: 1 for headwaters, rising by one where
+    /// two streams of equal order meet.
+    #[wasm_bindgen(getter)]
+    pub fn orders(&self) -> Uint8Array {
+        Uint8Array::from(self.orders.as_slice())
+    }
+
+    /// Contributing area at the downstream end of each segment, in m².
+    #[wasm_bindgen(getter)]
+    pub fn areas(&self) -> Float32Array {
+        Float32Array::from(self.areas.as_slice())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn max_order(&self) -> u8 {
+        self.orders.iter().copied().max().unwrap_or(0)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn polyline_count(&self) -> usize {
+        self.orders.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / 3
+    }
+}
+
+/// Stream network of every cell draining at least `min_area` m²: depressions
+/// are filled, flow is routed D8 and channels are traced from cell centre to
+/// cell centre. Vertices are draped on the `mesh_compute` surface for the
+/// same `options` and lifted by `z_offset` mesh units to stay clear of it
+/// (a few thousandths is usually enough).
+#[wasm_bindgen]
+pub fn extract_streams(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    min_area: f32,
+    z_offset: f32,
+    options: &MeshOptions,
+) -> Result<StreamNetwork, JsError> {
+    check_grid(elevations, width, height)?;
+    if !(min_area.is_finite() && min_area > 0.0) {
+        return Err(JsError::new("extract_streams: min_area must be positive"));
+    }
+    let hydrology = HydrologyOptions {
+        cell_size_x: options.cell_size_x,
+        cell_size_y: options.cell_size_y,
+        ..HydrologyOptions::default()
+    };
+    check_hydrology(&hydrology, height)?;
+
+    let mut heights = mask_nodata(elevations, options.nodata);
+    fill_voids_in_place(&mut heights, width, height, options.void_fill);
+
+    // Route on metres so the fill increment means the same for any input
+    let mut routing: Vec<f32> = match options.elevation_input {
+        ElevationInput::Normalized => heights
+            .iter()
+            .map(|z| z * options.metres_per_unit)
+            .collect(),
+        ElevationInput::Metres => heights.clone(),
+    };
+    priority_flood(&mut routing, width, height, FILL_INCREMENT);
+    let directions = d8_directions(&routing, width, height, &hydrology);
+    let accumulation = d8_accumulation(&routing, &directions, width);
+    let cell_area = (options.cell_size_x * options.cell_size_y).abs();
+    let is_stream = |i: usize| accumulation[i] * cell_area >= min_area;
+    let downstream = |i: usize| d8_target(directions[i], i % width, i / width, width);
+
+    let (order, donors) = strahler_orders(
+        heights.len(),
+        &downhill_order(&routing),
+        is_stream,
+        downstream,
+    );
+
+    let range = options.elevation_range(&heights);
+    let frame = MeshFrame::new(width, height, range.0, options);
+    let mut network = StreamNetwork {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        polyline_offsets: vec![0],
+        orders: Vec::new(),
+        areas: Vec::new(),
+    };
+
+    // Segments start at sources and confluences, the cells without exactly
+    // one stream donor
+    for head in (0..heights.len()).filter(|&i| is_stream(i) && donors[i] != 1) {
+        let mut cells = vec![head];
+        let mut cell = head;
+        while let Some(next) = downstream(cell) {
+            cells.push(next);
+            if donors[next] != 1 {
+                break;
+            }
+            cell = next;
+        }
+        if cells.len() < 2 {
+            continue;
+        }
+
+        let start = (network.vertices.len() / 3) as u32;
+        for &i in &cells {
+            let [x, y, z] = frame.position(i % width, i / width, width, height, heights[i]);
+            network.vertices.extend_from_slice(&[x, y, z + z_offset]);
+        }
+        let end = (network.vertices.len() / 3) as u32;
+        for v in start..end - 1 {
+            network.indices.extend_from_slice(&[v, v + 1]);
+        }
+        network.polyline_offsets.push(end);
+        network.orders.push(order[head]);
+        network
+            .areas
+            .push(accumulation[cells[cells.len() - 1]] * cell_area);
+    }
+
+    Ok(network)
+}
+
+/// Strahler order and number of stream donors per cell, handed down from
+/// donors to receivers. `upstream_first` lists the cells with every donor
+/// before its receiver; cells that are not streams keep order 0.
+fn strahler_orders(
+    len: usize,
+    upstream_first: &[usize],
+    is_stream: impl Fn(usize) -> bool,
+    downstream: impl Fn(usize) -> Option<usize>,
+) -> (Vec<u8>, Vec<u8>) {
+    let mut order = vec![0u8; len];
+    let mut donors = vec![0u8; len];
+    let mut top_order = vec![0u8; len];
+    let mut top_count = vec![0u8; len];
+    for &i in upstream_first {
+        if !is_stream(i) {
+            continue;
+        }
+        order[i] = match (donors[i], top_count[i]) {
+            (0, _) => 1,
+            (_, count) if count >= 2 => top_order[i].saturating_add(1),
+            _ => top_order[i],
+        };
+        if let Some(n) = downstream(i) {
+            donors[n] += 1;
+            if order[i] > top_order[n] {
+                top_order[n] = order[i];
+                top_count[n] = 1;
+            } else if order[i] == top_order[n] {
+                top_count[n] += 1;
+            }
+        }
+    }
+    (order, donors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strahler_order_rises_only_where_equal_orders_meet() {
+        // Sources 0, 1, 2, 3 and 7:
+        //   0 + 1 -> 4   two order 1 streams make order 2
+        //   4 + 2 -> 5   order 1 joining order 2 stays 2
+        //   3 + 7 -> 6   order 2
+        //   5 + 6 -> 8   two order 2 streams make order 3
+        let receivers = [4, 4, 5, 6, 5, 8, 8, 6, usize::MAX];
+        let upstream_first = [0, 1, 2, 3, 7, 4, 6, 5, 8];
+        let (order, donors) = strahler_orders(
+            receivers.len(),
+            &upstream_first,
+            |_| true,
+            |i| Some(receivers[i]).filter(|&n| n != usize::MAX),
+        );
+        assert_eq!(order, [1, 1, 1, 1, 2, 2, 2, 1, 3]);
+        assert_eq!(donors, [0, 0, 0, 0, 2, 2, 2, 0, 2]);
+
+        // Cells that are not streams neither get an order nor count as donors
+        let (order, donors) = strahler_orders(
+            receivers.len(),
+            &upstream_first,
+            |i| i != 1,
+            |i| Some(receivers[i]).filter(|&n| n != usize::MAX),
+        );
+        assert_eq!(order[1], 0);
+        assert_eq!((order[4], donors[4]), (1, 1));
+        // ...so 4 and 2 now meet as equals
+        assert_eq!(order[5], 2);
+        assert_eq!(order[8], 3);
+    }
+
+    #[test]
+    fn side_streams_feed_a_second_order_channel() {
+        // Valley down column 2: both slopes flow sideways into it
+        let elevations: Vec<f32> = (0..25)
+            .map(|i: usize| (i % 5).abs_diff(2) as f32 * 10.0 + (4 - i / 5) as f32)
+            .collect();
+        let options = MeshOptions::default();
+        let network = extract_streams(&elevations, 5, 5, 1e-9, 0.0, &options).unwrap();
+
+        // Ten side streams from the outer columns, four channel segments
+        let ones = network.orders.iter().filter(|&&o| o == 1).count();
+        let twos = network.orders.iter().filter(|&&o| o == 2).count();
+        assert_eq!((ones, twos, network.orders.len()), (10, 4, 14));
+    }
+}
@@ -0,0 +1,241 @@
+use wasm_bindgen::prelude::*;
+
+use crate::check_grid;
+
+/// Aspect value for cells with no downslope direction.
+pub const FLAT_ASPECT: f32 = -1.0;
+
+/// Finite-difference scheme for the surface gradient.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DerivativeMethod {
+    /// Horn (1981): weighted 3×3 differences, robust to noise (GDAL/ArcGIS).
+    Horn,
+    /// Zevenbergen & Thorne (1987): plain central differences, sharper.
+    ZevenbergenThorne,
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlopeUnits {
+    Degrees,
+    /// Rise over run × 100.
+    Percent,
+}
+
+/// Surface gradient (dz/dx east, dz/dy toward increasing rows) per sample.
+/// `cell_x`/`cell_y` are the sample spacing in the same unit as the heights,
+/// borders use one-sided differences and any void in the window yields NaN.
+pub fn gradients(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    cell_x: f32,
+    cell_y: f32,
+    method: DerivativeMethod,
+) -> Vec<(f32, f32)> {
+    let at = |x: usize, y: usize| elevations[y * width + x];
+    let mut out = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+        // Rows (and columns below) actually spanned: 1 at the border
+        let span_y = (down - up) as f32 * cell_y;
+        for x in 0..width {
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let span_x = (right - left) as f32 * cell_x;
+
+            let gradient = match method {
+                // Central differences skip the sample itself
+                _ if at(x, y).is_nan() => (f32::NAN, f32::NAN),
+                DerivativeMethod::Horn => {
+                    let (a, b, c) = (at(left, up), at(x, up), at(right, up));
+                    let (d, f) = (at(left, y), at(right, y));
+                    let (g, h, i) = (at(left, down), at(x, down), at(right, down));
+                    (
+                        ((c + 2.0 * f + i) - (a + 2.0 * d + g)) / (4.0 * span_x),
+                        ((g + 2.0 * h + i) - (a + 2.0 * b + c)) / (4.0 * span_y),
+                    )
+                }
+                DerivativeMethod::ZevenbergenThorne => (
+                    (at(right, y) - at(left, y)) / span_x,
+                    (at(x, down) - at(x, up)) / span_y,
+                ),
+            };
+            out.push(gradient);
+        }
+    }
+
+    out
+}
+
+pub fn slope_from_gradient((dzdx, dzdy): (f32, f32), units: SlopeUnits) -> f32 {
+    let rise = (dzdx * dzdx + dzdy * dzdy).sqrt();
+    match units {
+        SlopeUnits::Degrees => rise.atan().to_degrees(),
+        SlopeUnits::Percent => rise * 100.0,
+    }
+}
+
+/// Compass direction the slope faces (downhill), clockwise from north, with
+/// row 0 as the northern edge. `FLAT_ASPECT` when there is no gradient.
+pub fn aspect_from_gradient((dzdx, dzdy): (f32, f32)) -> f32 {
+    if dzdx.is_nan() || dzdy.is_nan() {
+        return f32::NAN;
+    }
+    if dzdx == 0.0 && dzdy == 0.0 {
+        return FLAT_ASPECT;
+    }
+    // Downhill is -gradient; rows grow southward so north is -y.
+    let aspect = (-dzdx).atan2(dzdy).to_degrees();
+    (aspect + 360.0) % 360.0
+}
+
+/// Slope grid for a heightfield with cell sizes in metres (see
+/// `ElevationGrid.cell_size` / `cell_size_metres`) and heights in metres.
+#[wasm_bindgen]
+pub fn slope(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    cell_size_x: f32,
+    cell_size_y: f32,
+    method: DerivativeMethod,
+    units: SlopeUnits,
+) -> Result<Vec<f32>, JsError> {
+    check_grid(elevations, width, height)?;
+    Ok(slope_values(
+        elevations,
+        width,
+        height,
+        cell_size_x,
+        cell_size_y,
+        method,
+        units,
+    ))
+}
+
+/// `slope` for a grid already known to be `width` × `height`.
+pub(crate) fn slope_values(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    cell_size_x: f32,
+    cell_size_y: f32,
+    method: DerivativeMethod,
+    units: SlopeUnits,
+) -> Vec<f32> {
+    gradients(elevations, width, height, cell_size_x, cell_size_y, method)
+        .into_iter()
+        .map(|g| slope_from_gradient(g, units))
+        .collect()
+}
+
+/// Aspect grid in degrees (0–360, clockwise from north); flat cells are -1.
+#[wasm_bindgen]
+pub fn aspect(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    cell_size_x: f32,
+    cell_size_y: f32,
+    method: DerivativeMethod,
+) -> Result<Vec<f32>, JsError> {
+    check_grid(elevations, width, height)?;
+    Ok(
+        gradients(elevations, width, height, cell_size_x, cell_size_y, method)
+            .into_iter()
+            .map(aspect_from_gradient)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: [DerivativeMethod; 2] =
+        [DerivativeMethod::Horn, DerivativeMethod::ZevenbergenThorne];
+
+    /// 5 × 4 grid with the given height per column and per row.
+    fn plane(per_x: f32, per_y: f32) -> Vec<f32> {
+        (0..20)
+            .map(|i| 10.0 + (i % 5) as f32 * per_x + (i / 5) as f32 * per_y)
+            .collect()
+    }
+
+    #[test]
+    fn plane_has_the_same_gradient_at_the_border() {
+        for method in METHODS {
+            // 2 per 0.5-wide column, -3 per 2-high row
+            let g = gradients(&plane(2.0, -3.0), 5, 4, 0.5, 2.0, method);
+            for (i, &(dx, dy)) in g.iter().enumerate() {
+                assert!((dx - 4.0).abs() < 1e-5, "{method:?} sample {i}: {dx}");
+                assert!((dy + 1.5).abs() < 1e-5, "{method:?} sample {i}: {dy}");
+            }
+            let slopes = slope_values(
+                &plane(1.0, 0.0),
+                5,
+                4,
+                1.0,
+                1.0,
+                method,
+                SlopeUnits::Degrees,
+            );
+            assert!(slopes.iter().all(|s| (s - 45.0).abs() < 1e-4));
+            let percent = slope_values(
+                &plane(0.0, 0.5),
+                5,
+                4,
+                1.0,
+                1.0,
+                method,
+                SlopeUnits::Percent,
+            );
+            assert!(percent.iter().all(|s| (s - 50.0).abs() < 1e-4));
+        }
+    }
+
+    #[test]
+    fn aspect_faces_downhill_in_each_quadrant() {
+        // Row 0 is north: heights growing with the row fall toward the north
+        let cases = [
+            ((-1.0, 1.0), 45.0),
+            ((-1.0, -1.0), 135.0),
+            ((1.0, -1.0), 225.0),
+            ((1.0, 1.0), 315.0),
+            ((-1.0, 0.0), 90.0),
+            ((0.0, 1.0), 0.0),
+        ];
+        for ((per_x, per_y), expected) in cases {
+            for method in METHODS {
+                let g = gradients(&plane(per_x, per_y), 5, 4, 1.0, 1.0, method);
+                for &gradient in &g {
+                    let aspect = aspect_from_gradient(gradient);
+                    assert!(
+                        (aspect - expected).abs() < 1e-4,
+                        "{per_x}, {per_y}: {aspect}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flat_ground_has_no_aspect_and_voids_stay_undefined() {
+        let g = gradients(&[3.0; 20], 5, 4, 1.0, 1.0, DerivativeMethod::Horn);
+        assert!(g
+            .into_iter()
+            .all(|g| aspect_from_gradient(g) == FLAT_ASPECT));
+
+        let mut z = plane(1.0, 1.0);
+        z[7] = f32::NAN;
+        for method in METHODS {
+            let g = gradients(&z, 5, 4, 1.0, 1.0, method);
+            assert!(slope_from_gradient(g[7], SlopeUnits::Degrees).is_nan());
+            assert!(aspect_from_gradient(g[8]).is_nan());
+            // Far from the void the plane is intact
+            assert_eq!(g[19], (1.0, 1.0));
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use wasm_bindgen::prelude::*;
+
+use crate::voids::{fill_voids_in_place, mask_nodata};
+use crate::{
+    check_grid, grid_indices, mesh_from_samples, MeshComputeData, MeshError, MeshFrame, MeshOptions,
+};
+
+/// A heightfield split into `tiles_x` × `tiles_y` tiles that can each be
+/// meshed at their own level of detail.
+///
+/// Neighbouring tiles share their border samples. Level 0 is the full input
+/// resolution and every level halves it by keeping every 2^lod-th sample
+/// (plus the tile's last row and column). Bounds and the geometric error of
+/// every level are computed up front, in mesh units (the same space as the
+/// vertices), so a renderer can pick levels by screen-space error before
+/// building any geometry.
+#[wasm_bindgen]
+pub struct TerrainTiles {
+    width: usize,
+    height: usize,
+    tiles_x: usize,
+    tiles_y: usize,
+    lod_count: usize,
+    heights: Vec<f32>,
+    frame: MeshFrame,
+    /// Per tile: min x, y, z then max x, y, z.
+    bounds: Vec<[f32; 6]>,
+    /// Per tile, per level.
+    errors: Vec<Vec<f32>>,
+    options: MeshOptions,
+}
+
+/// One tile mesh with the metadata needed for LOD selection.
+#[wasm_bindgen]
+pub struct TerrainTile {
+    x: usize,
+    y: usize,
+    lod: usize,
+    bounds: [f32; 6],
+    geometric_error: f32,
+    mesh: MeshComputeData,
+}
+
+#[wasm_bindgen]
+impl TerrainTiles {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        elevations: &[f32],
+        width: usize,
+        height: usize,
+        tiles_x: usize,
+        tiles_y: usize,
+        options: &MeshOptions,
+    ) -> Result<TerrainTiles, JsError> {
+        Ok(TerrainTiles::build(
+            elevations, width, height, tiles_x, tiles_y, options,
+        )?)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn tiles_x(&self) -> usize {
+        self.tiles_x
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn tiles_y(&self) -> usize {
+        self.tiles_y
+    }
+
+    /// Number of levels of detail; the coarsest is `lod_count - 1`.
+    #[wasm_bindgen(getter)]
+    pub fn lod_count(&self) -> usize {
+        self.lod_count
+    }
+
+    /// Axis-aligned bounds of tile (x, y) in mesh space as
+    /// `[min_x, min_y, min_z, max_x, max_y, max_z]`, valid for every level.
+    pub fn bounds(&self, x: usize, y: usize) -> Result<Vec<f32>, JsError> {
+        Ok(self.bounds[self.tile_index(x, y)?].to_vec())
+    }
+
+    /// Largest vertical distance, in mesh units, between tile (x, y) at `lod`
+    /// and the full-resolution surface. Levels past the coarsest are clamped.
+    pub fn geometric_error(&self, x: usize, y: usize, lod: usize) -> Result<f32, JsError> {
+        let errors = &self.errors[self.tile_index(x, y)?];
+        Ok(errors[lod.min(self.lod_count - 1)])
+    }
+
+    /// Mesh tile (x, y) at `lod` (clamped to the coarsest level). Its border
+    /// gets a skirt hanging `skirt_depth` mesh units straight down, hiding
+    /// the cracks against neighbours at other levels; it should be at least
+    /// the largest geometric error of the levels used next to it.
+    pub fn tile(
+        &self,
+        x: usize,
+        y: usize,
+        lod: usize,
+        skirt_depth: f32,
+    ) -> Result<TerrainTile, JsError> {
+        Ok(self.build_tile(x, y, lod, skirt_depth)?)
+    }
+}
+
+impl TerrainTiles {
+    /// Native counterpart of the constructor.
+    pub fn build(
+        elevations: &[f32],
+        width: usize,
+        height: usize,
+        tiles_x: usize,
+        tiles_y: usize,
+        options: &MeshOptions,
+    ) -> Result<TerrainTiles, MeshError> {
+        check_grid(elevations, width, height)?;
+        if tiles_x == 0 || tiles_y == 0 || tiles_x >= width || tiles_y >= height {
+            return Err(MeshError::InvalidTiling { tiles_x, tiles_y });
+        }
+        if width * height > options.max_vertices {
+            return Err(MeshError::OutputTooLarge {
+                vertices: width * height,
+                limit: options.max_vertices,
+            });
+        }
+
+        let mut heights = mask_nodata(elevations, options.nodata);
+        fill_voids_in_place(&mut heights, width, height, options.void_fill);
+
+        let range = options.elevation_range(&heights);
+        let frame = MeshFrame::new(width, height, range.0, options);
+
+        // The smallest tile decides how many halvings every tile can take
+        let min_span = ((width - 1) / tiles_x).min((height - 1) / tiles_y);
+        let lod_count = min_span.ilog2() as usize + 1;
+
+        let mut tiles = TerrainTiles {
+            width,
+            height,
+            tiles_x,
+            tiles_y,
+            lod_count,
+            heights,
+            frame,
+            bounds: Vec::new(),
+            errors: Vec::new(),
+            options: options.clone(),
+        };
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                tiles.bounds.push(tiles.tile_bounds(tx, ty));
+                // Keep the error non-decreasing with the level, as LOD
+                // selection expects, even where a coarse level fits by luck
+                let mut errors: Vec<f32> = Vec::with_capacity(lod_count);
+                for lod in 0..lod_count {
+                    let previous = errors.last().copied().unwrap_or(0.0);
+                    errors.push(tiles.tile_error(tx, ty, lod).max(previous));
+                }
+                tiles.errors.push(errors);
+            }
+        }
+        Ok(tiles)
+    }
+
+    /// Native counterpart of `tile`.
+    pub fn build_tile(
+        &self,
+        x: usize,
+        y: usize,
+        lod: usize,
+        skirt_depth: f32,
+    ) -> Result<TerrainTile, MeshError> {
+        let index = self.tile_index(x, y)?;
+        let lod = lod.min(self.lod_count - 1);
+        let (xs, ys) = self.lod_samples(x, y, lod);
+        let (tile_w, tile_h) = (xs.len(), ys.len());
+
+        let samples: Vec<usize> = ys
+            .iter()
+            .flat_map(|&sy| xs.iter().map(move |&sx| sy * self.width + sx))
+            .collect();
+        let mut mesh = mesh_from_samples(
+            &self.heights,
+            self.width,
+            self.height,
+            (self.width, self.height),
+            &samples,
+            grid_indices(tile_w, tile_h),
+            &self.options,
+        );
+
+        // Walk the border with the tile on the right-hand side (in the
+        // y-down grid) so the skirt walls face outward.
+        let mut border: Vec<usize> = Vec::with_capacity(2 * (tile_w + tile_h));
+        border.extend(0..tile_w);
+        border.extend((1..tile_h).map(|j| j * tile_w + tile_w - 1));
+        border.extend((0..tile_w - 1).rev().map(|i| (tile_h - 1) * tile_w + i));
+        border.extend((1..tile_h - 1).rev().map(|j| j * tile_w));
+        border.push(0);
+        add_skirt(&mut mesh, &border, skirt_depth);
+
+        Ok(TerrainTile {
+            x,
+            y,
+            lod,
+            bounds: self.bounds[index],
+            geometric_error: self.errors[index][lod],
+            mesh,
+        })
+    }
+
+    fn tile_index(&self, x: usize, y: usize) -> Result<usize, MeshError> {
+        if x >= self.tiles_x || y >= self.tiles_y {
+            return Err(MeshError::TileOutOfRange { x, y });
+        }
+        Ok(y * self.tiles_x + x)
+    }
+
+    /// First and last input column (or row) of tile `t` of `tiles` along an
+    /// axis of `n` samples.
+    fn span(t: usize, tiles: usize, n: usize) -> (usize, usize) {
+        (t * (n - 1) / tiles, (t + 1) * (n - 1) / tiles)
+    }
+
+    /// Input columns and rows kept by tile (x, y) at `lod`.
+    fn lod_samples(&self, x: usize, y: usize, lod: usize) -> (Vec<usize>, Vec<usize>) {
+        let (x0, x1) = Self::span(x, self.tiles_x, self.width);
+        let (y0, y1) = Self::span(y, self.tiles_y, self.height);
+        (
+            kept_samples(x0, x1, 1 << lod),
+            kept_samples(y0, y1, 1 << lod),
+        )
+    }
+
+    fn tile_bounds(&self, x: usize, y: usize) -> [f32; 6] {
+        let (x0, x1) = Self::span(x, self.tiles_x, self.width);
+        let (y0, y1) = Self::span(y, self.tiles_y, self.height);
+        let size = (self.width, self.height);
+        sample_bounds(&self.heights, size, &self.frame, (x0, x1), (y0, y1))
+    }
+
+    /// Largest vertical gap between the full-resolution samples of tile
+    /// (x, y) and the bilinear surface through its samples at `lod`.
+    fn tile_error(&self, x: usize, y: usize, lod: usize) -> f32 {
+        if lod == 0 {
+            return 0.0;
+        }
+        let (xs, ys) = self.lod_samples(x, y, lod);
+        decimation_error(&self.heights, self.width, &xs, &ys) * self.frame.z_scale.abs()
+    }
+}
+
+/// Every `stride`-th index from `first`, plus `last`.
+pub(crate) fn kept_samples(first: usize, last: usize, stride: usize) -> Vec<usize> {
+    let mut kept: Vec<usize> = (first..last).step_by(stride).collect();
+    kept.push(last);
+    kept
+}
+
+/// Mesh-space bounds `[min x, y, z, max x, y, z]` of the samples in columns
+/// `xs` and rows `ys` (inclusive ranges) of a `size` grid, ignoring voids.
+pub(crate) fn sample_bounds(
+    heights: &[f32],
+    (width, height): (usize, usize),
+    frame: &MeshFrame,
+    (x0, x1): (usize, usize),
+    (y0, y1): (usize, usize),
+) -> [f32; 6] {
+    let mut bounds = [f32::MAX, f32::MAX, f32::MAX, f32::MIN, f32::MIN, f32::MIN];
+    for sy in y0..=y1 {
+        for sx in x0..=x1 {
+            let z = heights[sy * width + sx];
+            if z.is_nan() {
+                continue;
+            }
+            let p = frame.position(sx, sy, width, height, z);
+            for k in 0..3 {
+                bounds[k] = bounds[k].min(p[k]);
+                bounds[k + 3] = bounds[k + 3].max(p[k]);
+            }
+        }
+    }
+    if bounds[0] > bounds[3] {
+        // All void: a flat box over the footprint
+        let a = frame.position(x0, y0, width, height, 0.0);
+        let b = frame.position(x1, y1, width, height, 0.0);
+        bounds = [a[0], a[1], 0.0, b[0], b[1], 0.0];
+    }
+    bounds
+}
+
+/// Largest vertical gap (in input units) between the samples of a grid and
+/// the bilinear surface through only the kept columns `xs` and rows `ys`.
+pub(crate) fn decimation_error(heights: &[f32], width: usize, xs: &[usize], ys: &[usize]) -> f32 {
+    let at = |sx: usize, sy: usize| heights[sy * width + sx];
+    let mut error = 0.0f32;
+
+    for (ys0, ys1) in ys.iter().zip(&ys[1..]) {
+        for (xs0, xs1) in xs.iter().zip(&xs[1..]) {
+            let (z00, z10) = (at(*xs0, *ys0), at(*xs1, *ys0));
+            let (z01, z11) = (at(*xs0, *ys1), at(*xs1, *ys1));
+            for sy in *ys0..=*ys1 {
+                let v = (sy - ys0) as f32 / (ys1 - ys0) as f32;
+                for sx in *xs0..=*xs1 {
+                    let u = (sx - xs0) as f32 / (xs1 - xs0) as f32;
+                    let surface =
+                        (z00 * (1.0 - u) + z10 * u) * (1.0 - v) + (z01 * (1.0 - u) + z11 * u) * v;
+                    let gap = (surface - at(sx, sy)).abs();
+                    // Voids compare false and are skipped
+                    if gap > error {
+                        error = gap;
+                    }
+                }
+            }
+        }
+    }
+    error
+}
+
+/// Append a vertical skirt below the closed vertex loop `border` (indices
+/// into `mesh`, first repeated last). Segments touching voids are skipped.
+fn add_skirt(mesh: &mut MeshComputeData, border: &[usize], depth: f32) {
+    let mut bottom_of = vec![u32::MAX; mesh.vertex_count];
+    for &v in border {
+        if bottom_of[v] != u32::MAX || mesh.vertices[v * 3 + 2].is_nan() {
+            continue;
+        }
+        bottom_of[v] = mesh.vertex_count as u32;
+        let p = [
+            mesh.vertices[v * 3],
+            mesh.vertices[v * 3 + 1],
+            mesh.vertices[v * 3 + 2] - depth,
+        ];
+        mesh.vertices.extend_from_slice(&p);
+        mesh.colors.extend_from_within(v * 3..v * 3 + 3);
+        mesh.normals.extend_from_within(v * 3..v * 3 + 3);
+        if !mesh.occlusion.is_empty() {
+            mesh.occlusion.push(mesh.occlusion[v]);
+        }
+        mesh.vertex_count += 1;
+    }
+
+    for pair in border.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (bottom_a, bottom_b) = (bottom_of[a], bottom_of[b]);
+        if bottom_a == u32::MAX || bottom_b == u32::MAX {
+            continue;
+        }
+        let (a, b) = (a as u32, b as u32);
+        mesh.indices
+            .extend_from_slice(&[a, bottom_a, b, b, bottom_a, bottom_b]);
+    }
+}
+
+#[wasm_bindgen]
+impl TerrainTile {
+    #[wasm_bindgen(getter)]
+    pub fn x(&self) -> usize {
+        self.x
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn y(&self) -> usize {
+        self.y
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn lod(&self) -> usize {
+        self.lod
+    }
+
+    /// `[min_x, min_y, min_z, max_x, max_y, max_z]` in mesh space.
+    #[wasm_bindgen(getter)]
+    pub fn bounds(&self) -> Vec<f32> {
+        self.bounds.to_vec()
+    }
+
+    /// Vertical error of this level in mesh units.
+    #[wasm_bindgen(getter)]
+    pub fn geometric_error(&self) -> f32 {
+        self.geometric_error
+    }
+
+    /// The tile geometry, skirt included.
+    #[wasm_bindgen(getter)]
+    pub fn mesh(&self) -> MeshComputeData {
+        self.mesh.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `size` × `size` rolling hills in 0..1.
+    fn hills(size: usize) -> Vec<f32> {
+        (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as f32, (i / size) as f32);
+                0.5 + 0.25 * (x * 0.7).sin() * (y * 0.5).cos()
+            })
+            .collect()
+    }
+
+    fn tiles(size: usize, tiles_x: usize, tiles_y: usize) -> Result<TerrainTiles, MeshError> {
+        TerrainTiles::build(
+            &hills(size),
+            size,
+            size,
+            tiles_x,
+            tiles_y,
+            &MeshOptions::default(),
+        )
+    }
+
+    fn corner(mesh: &MeshComputeData, v: u32) -> [f32; 3] {
+        let i = v as usize * 3;
+        [mesh.vertices[i], mesh.vertices[i + 1], mesh.vertices[i + 2]]
+    }
+
+    fn normal(mesh: &MeshComputeData, t: &[u32]) -> [f32; 3] {
+        let [a, b, c] = [corner(mesh, t[0]), corner(mesh, t[1]), corner(mesh, t[2])];
+        let (u, v) = (
+            [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+            [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+        );
+        [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ]
+    }
+
+    #[test]
+    fn level_count_follows_the_smallest_tile() {
+        assert_eq!(tiles(17, 2, 2).unwrap().lod_count, 4);
+        assert_eq!(tiles(17, 1, 1).unwrap().lod_count, 5);
+        // 9 cells over 3 tiles: 3 cells each, one halving
+        assert_eq!(tiles(10, 3, 1).unwrap().lod_count, 2);
+    }
+
+    #[test]
+    fn geometric_error_grows_with_the_level() {
+        let tiles = tiles(33, 2, 2).unwrap();
+        for errors in &tiles.errors {
+            assert_eq!(errors.len(), tiles.lod_count);
+            assert_eq!(errors[0], 0.0);
+            assert!(errors.windows(2).all(|w| w[0] <= w[1]), "{errors:?}");
+            assert!(errors[tiles.lod_count - 1] > 0.0);
+        }
+        // Levels past the coarsest are clamped
+        let coarsest = tiles.build_tile(1, 1, 99, 0.1).unwrap();
+        assert_eq!(coarsest.lod, tiles.lod_count - 1);
+        assert_eq!(
+            coarsest.geometric_error,
+            tiles.errors[3][tiles.lod_count - 1]
+        );
+    }
+
+    #[test]
+    fn skirt_closes_the_tile_and_faces_outward() {
+        let tiles = tiles(17, 2, 2).unwrap();
+        let tile = tiles.build_tile(1, 0, 1, 0.2).unwrap();
+        let mesh = &tile.mesh;
+        // 5 × 5 samples at level 1, plus one skirt vertex per border sample
+        let surface = 25;
+        assert_eq!(mesh.vertex_count, surface + 16);
+        assert_eq!(mesh.indices.len() / 3, 2 * 4 * 4 + 2 * 16);
+
+        // Every directed edge is used once and, except along the bottom of
+        // the skirt, its reverse is too: surface and skirt wind alike
+        let mut edges = std::collections::HashSet::new();
+        for t in mesh.indices.chunks_exact(3) {
+            for k in 0..3 {
+                assert!(edges.insert((t[k], t[(k + 1) % 3])));
+            }
+        }
+        for &(a, b) in &edges {
+            let skirt_bottom = a as usize >= surface && b as usize >= surface;
+            assert!(skirt_bottom || edges.contains(&(b, a)), "open edge {a}-{b}");
+        }
+
+        let [x0, y0, _, x1, y1, _] = tile.bounds;
+        let centre = [(x0 + x1) / 2.0, (y0 + y1) / 2.0];
+        let (top, skirt) = mesh.indices.split_at(2 * 4 * 4 * 3);
+        assert!(top.chunks_exact(3).all(|t| normal(mesh, t)[2] > 0.0));
+        for t in skirt.chunks_exact(3) {
+            let n = normal(mesh, t);
+            let p = corner(mesh, t[0]);
+            let out = n[0] * (p[0] - centre[0]) + n[1] * (p[1] - centre[1]);
+            assert!(n[2].abs() < 1e-6 && out > 0.0, "{t:?}");
+        }
+        // The skirt hangs `skirt_depth` below the border
+        let bottom = corner(mesh, surface as u32);
+        assert!((corner(mesh, 0)[2] - bottom[2] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_bad_tilings_and_tiles() {
+        for (tiles_x, tiles_y) in [(0, 1), (1, 0), (17, 1), (2, 20)] {
+            assert_eq!(
+                tiles(17, tiles_x, tiles_y).err(),
+                Some(MeshError::InvalidTiling { tiles_x, tiles_y })
+            );
+        }
+        let tiles = tiles(17, 2, 2).unwrap();
+        assert!(tiles.build_tile(1, 1, 0, 0.0).is_ok());
+        for (x, y) in [(2, 0), (0, 2)] {
+            assert_eq!(
+                tiles.build_tile(x, y, 0, 0.0).err(),
+                Some(MeshError::TileOutOfRange { x, y })
+            );
+        }
+    }
+}
@@ -0,0 +1,505 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::resample::{sample_elevation, Interpolation};
+use crate::voids::mask_nodata;
+use crate::{check_grid, MeshError};
+
+/// Mean earth radius in metres, for the curvature correction.
+const EARTH_RADIUS: f32 = 6_371_000.0;
+
+/// Observer, target and terrain settings for `viewshed` and `line_of_sight`.
+/// Positions are fractional sample coordinates (column, row), e.g. from
+/// `ElevationGrid.lon_lat_to_grid`; heights and distances are in metres.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ViewshedOptions {
+    /// Eye or antenna height above the ground at the observer.
+    pub observer_height: f32,
+    /// Height above the ground that has to be seen at each target; 0 asks
+    /// whether the ground itself is visible.
+    pub target_height: f32,
+    pub cell_size_x: f32,
+    pub cell_size_y: f32,
+    /// Targets farther away than this are not visible; unlimited when unset.
+    pub max_distance: Option<f32>,
+    /// Elevation marking missing samples. Voids never block a sight line
+    /// and are never visible themselves.
+    pub nodata: Option<f32>,
+    /// How the terrain is sampled between grid samples along a sight line.
+    pub interpolation: Interpolation,
+    /// Lower distant terrain for the curvature of the earth.
+    pub earth_curvature: bool,
+    /// Atmospheric refraction coefficient offsetting the curvature: about
+    /// 0.13 for visible light and 0.25 for radio (the "4/3 earth").
+    pub refraction: f32,
+}
+
+#[wasm_bindgen]
+impl ViewshedOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ViewshedOptions {
+        ViewshedOptions::default()
+    }
+}
+
+impl Default for ViewshedOptions {
+    fn default() -> Self {
+        ViewshedOptions {
+            observer_height: 1.7,
+            target_height: 0.0,
+            cell_size_x: 1.0,
+            cell_size_y: 1.0,
+            max_distance: None,
+            nodata: None,
+            interpolation: Interpolation::Bilinear,
+            earth_curvature: false,
+            refraction: 0.13,
+        }
+    }
+}
+
+/// Invalid input to `viewshed` and `line_of_sight`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViewshedError {
+    Grid(MeshError),
+    /// Cell sizes must be finite and non-zero for distances to mean
+    /// anything.
+    InvalidCellSize {
+        x: f32,
+        y: f32,
+    },
+    /// The observer or target lies off the grid.
+    OutsideGrid {
+        what: &'static str,
+        x: f32,
+        y: f32,
+        width: usize,
+        height: usize,
+    },
+    /// The observer or target lies on a void.
+    OnVoid(&'static str),
+}
+
+impl fmt::Display for ViewshedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViewshedError::Grid(error) => error.fmt(f),
+            ViewshedError::InvalidCellSize { x, y } => {
+                write!(f, "cell size {} × {} must be finite and non-zero", x, y)
+            }
+            ViewshedError::OutsideGrid {
+                what,
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "{} ({}, {}) is outside the {} × {} grid",
+                what, x, y, width, height
+            ),
+            ViewshedError::OnVoid(what) => write!(f, "{} is on a void", what),
+        }
+    }
+}
+
+impl std::error::Error for ViewshedError {}
+
+impl From<MeshError> for ViewshedError {
+    fn from(error: MeshError) -> Self {
+        ViewshedError::Grid(error)
+    }
+}
+
+/// Outcome of a `line_of_sight` query.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct LineOfSight {
+    obstruction: Option<[f32; 3]>,
+    obstruction_distance: Option<f32>,
+    clearance: f32,
+    distance: f32,
+}
+
+#[wasm_bindgen]
+impl LineOfSight {
+    #[wasm_bindgen(getter)]
+    pub fn visible(&self) -> bool {
+        self.obstruction.is_none()
+    }
+
+    /// First terrain point above the sight line, seen from the observer, as
+    /// `[column, row, elevation]`.
+    #[wasm_bindgen(getter)]
+    pub fn obstruction(&self) -> Option<Vec<f32>> {
+        self.obstruction.map(|p| p.to_vec())
+    }
+
+    /// Ground distance from the observer to `obstruction`, in metres.
+    #[wasm_bindgen(getter)]
+    pub fn obstruction_distance(&self) -> Option<f32> {
+        self.obstruction_distance
+    }
+
+    /// Smallest height of the sight line above the terrain between the two
+    /// points, in metres; negative when blocked. Useful against a Fresnel
+    /// zone radius for radio links.
+    #[wasm_bindgen(getter)]
+    pub fn clearance(&self) -> f32 {
+        self.clearance
+    }
+
+    /// Ground distance between the two points, in metres.
+    #[wasm_bindgen(getter)]
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+}
+
+/// Visibility raster for an observer at sample position (`observer_x`,
+/// `observer_y`): 1 where a target `options.target_height` above the ground
+/// can be seen from `options.observer_height` above the observer, 0
+/// elsewhere. Each sample is tested along its own sight line (Franklin's
+/// R3), so the result is exact up to the terrain interpolation.
+#[wasm_bindgen]
+pub fn viewshed(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    observer_x: f32,
+    observer_y: f32,
+    options: &ViewshedOptions,
+) -> Result<Vec<u8>, JsError> {
+    Ok(visibility(
+        elevations, width, height, observer_x, observer_y, options,
+    )?)
+}
+
+/// Native counterpart of `viewshed`.
+pub fn visibility(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    observer_x: f32,
+    observer_y: f32,
+    options: &ViewshedOptions,
+) -> Result<Vec<u8>, ViewshedError> {
+    let heights = checked_heights(elevations, width, height, options)?;
+    let terrain = Terrain {
+        heights: &heights,
+        width,
+        height,
+        options,
+    };
+    let observer = (observer_x, observer_y);
+    let eye = terrain.point_height(observer, options.observer_height, "observer")?;
+
+    // Only samples within `max_distance` can be visible
+    let (x_range, y_range) = match options.max_distance {
+        Some(max) => {
+            let reach_x = (max / options.cell_size_x.abs()).ceil();
+            let reach_y = (max / options.cell_size_y.abs()).ceil();
+            let clamp = |v: f32, last: usize| v.clamp(0.0, last as f32) as usize;
+            (
+                clamp(observer_x - reach_x, width - 1)..=clamp(observer_x + reach_x, width - 1),
+                clamp(observer_y - reach_y, height - 1)..=clamp(observer_y + reach_y, height - 1),
+            )
+        }
+        None => (0..=width - 1, 0..=height - 1),
+    };
+
+    let mut visible = vec![0u8; width * height];
+    for y in y_range {
+        for x in x_range.clone() {
+            let ground = heights[y * width + x];
+            let target = (x as f32, y as f32);
+            let distance = terrain.distance(observer, target);
+            if ground.is_nan() || options.max_distance.is_some_and(|max| distance > max) {
+                continue;
+            }
+            let sight = terrain.trace(observer, eye, target, ground + options.target_height, true);
+            visible[y * width + x] = u8::from(sight.obstruction.is_none());
+        }
+    }
+    Ok(visible)
+}
+
+/// Whether a point `options.target_height` above the ground at (`to_x`,
+/// `to_y`) is visible from `options.observer_height` above (`from_x`,
+/// `from_y`), with the first obstruction and the clearance along the way.
+/// `options.max_distance` is ignored.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn line_of_sight(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    from_x: f32,
+    from_y: f32,
+    to_x: f32,
+    to_y: f32,
+    options: &ViewshedOptions,
+) -> Result<LineOfSight, JsError> {
+    Ok(sight_line(
+        elevations,
+        width,
+        height,
+        (from_x, from_y),
+        (to_x, to_y),
+        options,
+    )?)
+}
+
+/// Native counterpart of `line_of_sight`.
+pub fn sight_line(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    from: (f32, f32),
+    to: (f32, f32),
+    options: &ViewshedOptions,
+) -> Result<LineOfSight, ViewshedError> {
+    let heights = checked_heights(elevations, width, height, options)?;
+    let terrain = Terrain {
+        heights: &heights,
+        width,
+        height,
+        options,
+    };
+    let eye = terrain.point_height(from, options.observer_height, "observer")?;
+    let target = terrain.point_height(to, options.target_height, "target")?;
+    Ok(terrain.trace(from, eye, to, target, false))
+}
+
+/// The grid with voids as NaN, once the grid and cell sizes are checked.
+fn checked_heights(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    options: &ViewshedOptions,
+) -> Result<Vec<f32>, ViewshedError> {
+    check_grid(elevations, width, height)?;
+    let (x, y) = (options.cell_size_x, options.cell_size_y);
+    if !(x.is_finite() && y.is_finite() && x != 0.0 && y != 0.0) {
+        return Err(ViewshedError::InvalidCellSize { x, y });
+    }
+    Ok(mask_nodata(elevations, options.nodata))
+}
+
+struct Terrain<'a> {
+    heights: &'a [f32],
+    width: usize,
+    height: usize,
+    options: &'a ViewshedOptions,
+}
+
+impl Terrain<'_> {
+    fn ground(&self, (x, y): (f32, f32)) -> f32 {
+        sample_elevation(
+            self.heights,
+            self.width,
+            self.height,
+            x,
+            y,
+            self.options.interpolation,
+        )
+    }
+
+    /// Elevation `above` the ground at a query point, checked to be on the
+    /// grid and not in a void.
+    fn point_height(
+        &self,
+        p: (f32, f32),
+        above: f32,
+        what: &'static str,
+    ) -> Result<f32, ViewshedError> {
+        let inside = (0.0..=(self.width - 1) as f32).contains(&p.0)
+            && (0.0..=(self.height - 1) as f32).contains(&p.1);
+        if !inside {
+            return Err(ViewshedError::OutsideGrid {
+                what,
+                x: p.0,
+                y: p.1,
+                width: self.width,
+                height: self.height,
+            });
+        }
+        let ground = self.ground(p);
+        if ground.is_nan() {
+            return Err(ViewshedError::OnVoid(what));
+        }
+        Ok(ground + above)
+    }
+
+    fn distance(&self, a: (f32, f32), b: (f32, f32)) -> f32 {
+        ((b.0 - a.0) * self.options.cell_size_x).hypot((b.1 - a.1) * self.options.cell_size_y)
+    }
+
+    /// How far terrain `distance` metres away sits below the observer's
+    /// horizontal plane due to the earth's curvature, net of refraction.
+    fn curvature_drop(&self, distance: f32) -> f32 {
+        if self.options.earth_curvature {
+            distance * distance * (1.0 - self.options.refraction) / (2.0 * EARTH_RADIUS)
+        } else {
+            0.0
+        }
+    }
+
+    /// Follow the straight sight line from elevation `eye` at `from` to
+    /// elevation `target` at `to`, sampling the terrain once per sample
+    /// step in between. With `first_only` the walk stops at the first
+    /// obstruction and `clearance` is only valid up to it.
+    fn trace(
+        &self,
+        from: (f32, f32),
+        eye: f32,
+        to: (f32, f32),
+        target: f32,
+        first_only: bool,
+    ) -> LineOfSight {
+        let distance = self.distance(from, to);
+        let target = target - self.curvature_drop(distance);
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = dx.abs().max(dy.abs()).ceil() as usize;
+
+        let mut sight = LineOfSight {
+            obstruction: None,
+            obstruction_distance: None,
+            clearance: f32::INFINITY,
+            distance,
+        };
+        for i in 1..steps {
+            let t = i as f32 / steps as f32;
+            let p = (from.0 + dx * t, from.1 + dy * t);
+            let ground = self.ground(p);
+            if ground.is_nan() {
+                continue;
+            }
+            let clearance = eye + (target - eye) * t - (ground - self.curvature_drop(distance * t));
+            sight.clearance = sight.clearance.min(clearance);
+            if clearance < 0.0 && sight.obstruction.is_none() {
+                sight.obstruction = Some([p.0, p.1, ground]);
+                sight.obstruction_distance = Some(distance * t);
+                if first_only {
+                    break;
+                }
+            }
+        }
+        sight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 9 × 9 flat ground with a 10 m wall along column 5.
+    fn walled() -> Vec<f32> {
+        (0..81)
+            .map(|i| if i % 9 == 5 { 10.0 } else { 0.0 })
+            .collect()
+    }
+
+    #[test]
+    fn flat_ground_is_visible_everywhere() {
+        let visible = visibility(&[0.0; 81], 9, 9, 4.0, 4.0, &ViewshedOptions::default()).unwrap();
+        assert!(visible.iter().all(|&v| v == 1));
+    }
+
+    #[test]
+    fn wall_hides_what_lies_behind_it() {
+        let visible = visibility(&walled(), 9, 9, 2.0, 4.0, &ViewshedOptions::default()).unwrap();
+        for (i, &v) in visible.iter().enumerate() {
+            assert_eq!(v, u8::from(i % 9 <= 5), "sample {i}");
+        }
+    }
+
+    #[test]
+    fn targets_beyond_max_distance_and_voids_are_not_visible() {
+        let mut z = vec![0.0; 81];
+        z[4 * 9 + 5] = -9999.0;
+        let options = ViewshedOptions {
+            max_distance: Some(5.0),
+            nodata: Some(-9999.0),
+            cell_size_x: 2.0,
+            ..ViewshedOptions::default()
+        };
+        let visible = visibility(&z, 9, 9, 4.0, 4.0, &options).unwrap();
+        for (i, &v) in visible.iter().enumerate() {
+            let (dx, dy) = ((i % 9) as f32 - 4.0, (i / 9) as f32 - 4.0);
+            let near = (dx * 2.0).hypot(dy) <= 5.0;
+            assert_eq!(v, u8::from(near && i != 41), "sample {i}");
+        }
+        // The void does not block the sample behind it
+        assert_eq!(visible[4 * 9 + 6], 1);
+    }
+
+    #[test]
+    fn earth_curvature_hides_distant_low_ground() {
+        let options = ViewshedOptions {
+            observer_height: 2.0,
+            cell_size_x: 1000.0,
+            cell_size_y: 1000.0,
+            ..ViewshedOptions::default()
+        };
+        let flat = visibility(&[0.0; 100], 50, 2, 0.0, 0.0, &options).unwrap();
+        assert_eq!((flat[1], flat[49]), (1, 1));
+
+        let curved = ViewshedOptions {
+            earth_curvature: true,
+            ..options
+        };
+        // The horizon of a 2 m eye is about 5 km away
+        let round = visibility(&[0.0; 100], 50, 2, 0.0, 0.0, &curved).unwrap();
+        assert_eq!((round[1], round[49]), (1, 0));
+    }
+
+    #[test]
+    fn line_of_sight_reports_the_first_obstruction() {
+        let options = ViewshedOptions::default();
+        let sight = sight_line(&walled(), 9, 9, (2.0, 4.0), (8.0, 4.0), &options).unwrap();
+        assert!(!sight.visible());
+        assert_eq!(sight.obstruction, Some([5.0, 4.0, 10.0]));
+        assert_eq!(sight.obstruction_distance, Some(3.0));
+        assert_eq!(sight.distance, 6.0);
+        // The sight line crosses the wall at half the eye height
+        assert!((sight.clearance - (0.85 - 10.0)).abs() < 1e-5);
+
+        let clear = sight_line(&walled(), 9, 9, (2.0, 4.0), (4.0, 6.0), &options).unwrap();
+        assert!(clear.visible());
+        assert!(clear.clearance > 0.0);
+    }
+
+    #[test]
+    fn rejects_bad_query_points_and_cell_sizes() {
+        let mut z = vec![0.0; 81];
+        z[0] = f32::NAN;
+        let options = ViewshedOptions::default();
+        assert!(matches!(
+            visibility(&z, 9, 9, 9.5, 4.0, &options),
+            Err(ViewshedError::OutsideGrid {
+                what: "observer",
+                ..
+            })
+        ));
+        assert_eq!(
+            visibility(&z, 9, 9, 0.0, 0.0, &options),
+            Err(ViewshedError::OnVoid("observer"))
+        );
+        assert_eq!(
+            sight_line(&z, 9, 9, (4.0, 4.0), (0.0, 0.0), &options).unwrap_err(),
+            ViewshedError::OnVoid("target")
+        );
+        for (x, y) in [(0.0, 1.0), (1.0, f32::NAN), (f32::INFINITY, 1.0)] {
+            let options = ViewshedOptions {
+                cell_size_x: x,
+                cell_size_y: y,
+                ..ViewshedOptions::default()
+            };
+            let result = visibility(&z, 9, 9, 4.0, 4.0, &options);
+            assert!(matches!(result, Err(ViewshedError::InvalidCellSize { .. })));
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use wasm_bindgen::prelude::*;
+
+use crate::check_grid;
+
+/// How voids (NoData / NaN samples) are filled before meshing.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VoidFill {
+    /// Leave voids as holes in the mesh.
+    None,
+    /// Inverse-distance-squared average of the nearest valid sample in each
+    /// of the eight compass directions.
+    InverseDistance,
+    /// Harmonic (Laplacian) inpainting: the smoothest surface that meets the
+    /// void's border, like a membrane stretched over the hole.
+    Laplacian,
+}
+
+const LAPLACIAN_MAX_ITERATIONS: usize = 2000;
+/// Void count below which the Laplacian fill relaxes an inverse-distance
+/// seed directly instead of recursing to a coarser grid.
+const LAPLACIAN_DIRECT_VOIDS: usize = 256;
+
+/// Copy of `elevations` with every `nodata` sample (and any infinity)
+/// replaced by NaN, the single void marker used by the mesher.
+pub fn mask_nodata(elevations: &[f32], nodata: Option<f32>) -> Vec<f32> {
+    elevations
+        .iter()
+        .map(|&z| {
+            if !z.is_finite() || Some(z) == nodata {
+                f32::NAN
+            } else {
+                z
+            }
+        })
+        .collect()
+}
+
+/// Fill NaN samples in place. Voids stay NaN if the grid has no valid sample.
+pub fn fill_voids_in_place(elev: &mut [f32], width: usize, height: usize, method: VoidFill) {
+    let voids: Vec<usize> = (0..elev.len()).filter(|&i| elev[i].is_nan()).collect();
+    if voids.is_empty() || voids.len() == elev.len() {
+        return;
+    }
+
+    match method {
+        VoidFill::None => {}
+        VoidFill::InverseDistance => inverse_distance_fill(elev, width, height, &voids),
+        VoidFill::Laplacian => laplacian_fill(elev, width, height, &voids),
+    }
+}
+
+/// Unit steps to the eight compass neighbours.
+const DIRECTIONS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// Fill each void with the inverse-distance-squared average of the nearest
+/// valid sample in each of the eight compass directions, as GDAL's
+/// `FillNodata` does. A walk only crosses the void it starts in, so this is
+/// linear in the void's diameter rather than its area. The rare void no walk
+/// gets out of (a valid sample only off the eight directions) is filled in a
+/// later pass from its filled neighbours.
+fn inverse_distance_fill(elev: &mut [f32], width: usize, height: usize, voids: &[usize]) {
+    let mut pending = voids.to_vec();
+    while !pending.is_empty() {
+        let estimates: Vec<f32> = pending
+            .iter()
+            .map(|&i| compass_average(elev, width, height, i % width, i / width))
+            .collect();
+        let mut unreached = Vec::new();
+        for (&i, &z) in pending.iter().zip(&estimates) {
+            if z.is_nan() {
+                unreached.push(i);
+            } else {
+                elev[i] = z;
+            }
+        }
+        if unreached.len() == pending.len() {
+            break;
+        }
+        pending = unreached;
+    }
+}
+
+fn compass_average(elev: &[f32], width: usize, height: usize, x: usize, y: usize) -> f32 {
+    let (mut sum, mut weights) = (0.0f64, 0.0f64);
+    for (dx, dy) in DIRECTIONS {
+        let (mut wx, mut wy) = (x as isize, y as isize);
+        for step in 1.. {
+            wx += dx;
+            wy += dy;
+            if wx < 0 || wy < 0 || wx >= width as isize || wy >= height as isize {
+                break;
+            }
+            let z = elev[wy as usize * width + wx as usize];
+            if !z.is_nan() {
+                let d2 = (step * step * (dx * dx + dy * dy)) as f64;
+                sum += z as f64 / d2;
+                weights += 1.0 / d2;
+                break;
+            }
+        }
+    }
+    (sum / weights) as f32
+}
+
+/// Harmonic fill, solved coarse to fine: the voids are first filled on a
+/// half-resolution copy of the grid and that solution, interpolated, seeds
+/// Gauss-Seidel here. Plain Gauss-Seidel needs on the order of diameter²
+/// sweeps to settle a large hole; from the coarse seed a few dozen do.
+fn laplacian_fill(elev: &mut [f32], width: usize, height: usize, voids: &[usize]) {
+    let (coarse_w, coarse_h) = (width.div_ceil(2), height.div_ceil(2));
+    let mut coarse: Vec<f32> = (0..coarse_w * coarse_h)
+        .map(|i| elev[(i / coarse_w * 2) * width + i % coarse_w * 2])
+        .collect();
+    let coarse_voids: Vec<usize> = (0..coarse.len()).filter(|&i| coarse[i].is_nan()).collect();
+
+    if voids.len() <= LAPLACIAN_DIRECT_VOIDS
+        || coarse_w < 2
+        || coarse_h < 2
+        || coarse_voids.len() == coarse.len()
+    {
+        inverse_distance_fill(elev, width, height, voids);
+    } else {
+        if !coarse_voids.is_empty() {
+            laplacian_fill(&mut coarse, coarse_w, coarse_h, &coarse_voids);
+        }
+        for &i in voids {
+            let (x, y) = ((i % width) as f32 * 0.5, (i / width) as f32 * 0.5);
+            elev[i] = bilinear(&coarse, coarse_w, coarse_h, x, y);
+        }
+    }
+    relax(elev, width, height, voids);
+}
+
+/// Bilinear sample of a grid at fractional (x, y), clamped to its edges.
+fn bilinear(grid: &[f32], width: usize, height: usize, x: f32, y: f32) -> f32 {
+    let x = x.min((width - 1) as f32);
+    let y = y.min((height - 1) as f32);
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let top = grid[y0 * width + x0] * (1.0 - fx) + grid[y0 * width + x1] * fx;
+    let bottom = grid[y1 * width + x0] * (1.0 - fx) + grid[y1 * width + x1] * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Successive over-relaxation of the discrete Laplace equation over the void
+/// samples, with the valid samples as fixed boundary values. The relaxation
+/// factor is the optimal one for a square as wide as the voids' extent.
+fn relax(elev: &mut [f32], width: usize, height: usize, voids: &[usize]) {
+    let (lo, hi) = elev
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &z| (lo.min(z), hi.max(z)));
+    let tolerance = (hi - lo).max(1e-6) * 1e-5;
+    let (x0, x1, y0, y1) =
+        voids
+            .iter()
+            .fold((usize::MAX, 0, usize::MAX, 0), |(x0, x1, y0, y1), &i| {
+                let (x, y) = (i % width, i / width);
+                (x0.min(x), x1.max(x), y0.min(y), y1.max(y))
+            });
+    let extent = (x1.saturating_sub(x0)).max(y1.saturating_sub(y0)) + 2;
+    let omega = 2.0 / (1.0 + (std::f32::consts::PI / extent as f32).sin());
+
+    for _ in 0..LAPLACIAN_MAX_ITERATIONS {
+        let mut max_change = 0.0f32;
+        for &i in voids {
+            let (x, y) = (i % width, i / width);
+            let mut sum = 0.0;
+            let mut count = 0.0;
+            if x > 0 {
+                sum += elev[i - 1];
+                count += 1.0;
+            }
+            if x + 1 < width {
+                sum += elev[i + 1];
+                count += 1.0;
+            }
+            if y > 0 {
+                sum += elev[i - width];
+                count += 1.0;
+            }
+            if y + 1 < height {
+                sum += elev[i + width];
+                count += 1.0;
+            }
+            let change = omega * (sum / count - elev[i]);
+            max_change = max_change.max(change.abs());
+            elev[i] += change;
+        }
+        if max_change < tolerance {
+            break;
+        }
+    }
+}
+
+/// Fill NoData voids of a heightfield, returning the filled grid. Samples
+/// equal to `nodata` or non-finite count as voids.
+#[wasm_bindgen]
+pub fn fill_voids(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    nodata: Option<f32>,
+    method: VoidFill,
+) -> Result<Vec<f32>, JsError> {
+    check_grid(elevations, width, height)?;
+    let mut filled = mask_nodata(elevations, nodata);
+    fill_voids_in_place(&mut filled, width, height, method);
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(width: usize, height: usize) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| 3.0 + 0.5 * (i % width) as f32 - 0.25 * (i / width) as f32)
+            .collect()
+    }
+
+    /// `plane` with a `size` × `size` square void at (`x0`, `y0`).
+    fn holed_plane(width: usize, height: usize, x0: usize, y0: usize, size: usize) -> Vec<f32> {
+        let mut grid = plane(width, height);
+        for y in y0..y0 + size {
+            grid[y * width + x0..y * width + x0 + size].fill(f32::NAN);
+        }
+        grid
+    }
+
+    #[test]
+    fn laplacian_fill_reproduces_a_plane() {
+        // A plane is harmonic, so the fill must rebuild it exactly, also for
+        // a hole large enough to go through the coarse levels
+        let (width, height) = (80, 60);
+        let mut grid = holed_plane(width, height, 10, 12, 40);
+        fill_voids_in_place(&mut grid, width, height, VoidFill::Laplacian);
+        for (filled, expected) in grid.iter().zip(plane(width, height)) {
+            assert!((filled - expected).abs() < 1e-3, "{filled} vs {expected}");
+        }
+    }
+
+    #[test]
+    fn inverse_distance_stays_within_the_surrounding_values() {
+        let (width, height) = (30, 20);
+        let mut grid = holed_plane(width, height, 0, 5, 12);
+        let valid = plane(width, height);
+        let (lo, hi) = valid
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &z| (lo.min(z), hi.max(z)));
+        fill_voids_in_place(&mut grid, width, height, VoidFill::InverseDistance);
+        assert!(grid.iter().all(|&z| z >= lo && z <= hi));
+    }
+
+    #[test]
+    fn voids_off_the_compass_lines_are_filled_from_their_neighbours() {
+        // Only (2, 1) is valid, which no compass walk from (0, 0) or (0, 2)
+        // reaches
+        let mut grid = vec![f32::NAN; 9];
+        grid[5] = 7.0;
+        fill_voids_in_place(&mut grid, 3, 3, VoidFill::InverseDistance);
+        assert_eq!(grid, vec![7.0; 9]);
+    }
+
+    #[test]
+    fn grids_without_valid_samples_stay_void() {
+        for method in [VoidFill::InverseDistance, VoidFill::Laplacian] {
+            let mut grid = vec![f32::NAN; 6];
+            fill_voids_in_place(&mut grid, 3, 2, method);
+            assert!(grid.iter().all(|z| z.is_nan()));
+        }
+    }
+}
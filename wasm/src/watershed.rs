@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use js_sys::{Float32Array, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::check_grid;
+use crate::hydrology::{
+    check_hydrology, d8_accumulation, d8_directions, d8_target, neighbours, priority_flood,
+    HydrologyOptions,
+};
+use crate::voids::mask_nodata;
+
+/// Catchment draining through a pour point.
+#[wasm_bindgen]
+pub struct Watershed {
+    mask: Vec<u8>,
+    outline: Vec<f32>,
+    pour_point: [usize; 2],
+    cell_count: usize,
+    area: f64,
+}
+
+#[wasm_bindgen]
+impl Watershed {
+    /// 1 for cells draining through the pour point, 0 elsewhere.
+    #[wasm_bindgen(getter)]
+    pub fn mask(&self) -> Uint8Array {
+        Uint8Array::from(self.mask.as_slice())
+    }
+
+    /// Outer boundary along the cell edges as `x, y` pairs in sample
+    /// coordinates (cell edges at half-integers), closed by repeating the
+    /// first point, clockwise on screen with row 0 on top.
+    #[wasm_bindgen(getter)]
+    pub fn outline(&self) -> Float32Array {
+        Float32Array::from(self.outline.as_slice())
+    }
+
+    /// `[column, row]` of the pour point after snapping.
+    #[wasm_bindgen(getter)]
+    pub fn pour_point(&self) -> Vec<u32> {
+        self.pour_point.map(|v| v as u32).to_vec()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cell_count(&self) -> usize {
+        self.cell_count
+    }
+
+    /// Catchment area in km², summed from the cell areas (see
+    /// `HydrologyOptions.set_row_cell_areas`).
+    #[wasm_bindgen(getter)]
+    pub fn area_km2(&self) -> f64 {
+        self.area / 1e6
+    }
+}
+
+/// Catchment upstream of the pour point at sample position (`pour_x`,
+/// `pour_y`), e.g. from `ElevationGrid.lon_lat_to_grid`. The point is first
+/// moved to the highest-accumulation cell within `snap_radius` cells, so a
+/// click next to a channel lands on it. Depressions are filled (with
+/// `options.fill_increment`) and flow is routed D8.
+#[wasm_bindgen]
+pub fn delineate_watershed(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    pour_x: f32,
+    pour_y: f32,
+    snap_radius: f32,
+    options: &HydrologyOptions,
+) -> Result<Watershed, JsError> {
+    check_grid(elevations, width, height)?;
+    check_hydrology(options, height)?;
+    let (px, py) = (pour_x.round(), pour_y.round());
+    if !(px >= 0.0 && py >= 0.0 && px < width as f32 && py < height as f32) {
+        return Err(JsError::new(&format!(
+            "pour point ({}, {}) is outside the {} × {} grid",
+            pour_x, pour_y, width, height
+        )));
+    }
+    let (px, py) = (px as usize, py as usize);
+
+    let mut heights = mask_nodata(elevations, options.nodata);
+    priority_flood(&mut heights, width, height, options.fill_increment);
+    let directions = d8_directions(&heights, width, height, options);
+    let accumulation = d8_accumulation(&heights, &directions, width);
+
+    // Snap to the largest flow nearby; voids (NaN) never win
+    let reach = snap_radius.max(0.0);
+    let r = reach.floor() as usize;
+    let mut pour = None;
+    let mut best = f32::MIN;
+    for y in py.saturating_sub(r)..=(py + r).min(height - 1) {
+        for x in px.saturating_sub(r)..=(px + r).min(width - 1) {
+            let (dx, dy) = (x as f32 - px as f32, y as f32 - py as f32);
+            let a = accumulation[y * width + x];
+            if dx.hypot(dy) <= reach && a > best {
+                best = a;
+                pour = Some(y * width + x);
+            }
+        }
+    }
+    let Some(pour) = pour else {
+        return Err(JsError::new(
+            "pour point has no valid cell within the snap radius",
+        ));
+    };
+
+    // Walk upstream: a neighbour belongs to the catchment if it drains here
+    let mut mask = vec![0u8; heights.len()];
+    mask[pour] = 1;
+    let mut stack = vec![pour];
+    let mut area = 0.0;
+    let mut cell_count = 0;
+    while let Some(i) = stack.pop() {
+        let (x, y) = (i % width, i / width);
+        cell_count += 1;
+        area += options.cell_area(y);
+        for (_, n) in neighbours(x, y, width, height) {
+            if mask[n] == 0 && d8_target(directions[n], n % width, n / width, width) == Some(i) {
+                mask[n] = 1;
+                stack.push(n);
+            }
+        }
+    }
+
+    Ok(Watershed {
+        outline: outline(&mask, width, height),
+        mask,
+        pour_point: [pour % width, pour / width],
+        cell_count,
+        area,
+    })
+}
+
+/// Outer boundary ring (the one enclosing the largest area) of the cells set
+/// in `mask`, along cell edges.
+///
+/// Every inside cell side facing an outside cell (or the grid edge) becomes
+/// an edge between cell corners, directed clockwise around the cell; the
+/// edges then chain into rings. Where two inside cells touch only at a
+/// corner the ring turns toward the other cell, keeping diagonally connected
+/// cells (as D8 drains them) in one outline. Straight runs are merged.
+fn outline(mask: &[u8], width: usize, height: usize) -> Vec<f32> {
+    let inside = |x: isize, y: isize| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && mask[y as usize * width + x as usize] != 0
+    };
+
+    // Outgoing boundary edges per corner, as direction vectors
+    type Corner = (isize, isize);
+    let mut edges: HashMap<Corner, Vec<Corner>> = HashMap::new();
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            if !inside(x, y) {
+                continue;
+            }
+            let sides = [
+                ((0, -1), (x, y), (1, 0)),
+                ((1, 0), (x + 1, y), (0, 1)),
+                ((0, 1), (x + 1, y + 1), (-1, 0)),
+                ((-1, 0), (x, y + 1), (0, -1)),
+            ];
+            for ((nx, ny), start, direction) in sides {
+                if !inside(x + nx, y + ny) {
+                    edges.entry(start).or_default().push(direction);
+                }
+            }
+        }
+    }
+
+    let mut best: Vec<Corner> = Vec::new();
+    let mut best_area = 0;
+    let mut starts: Vec<Corner> = edges.keys().copied().collect();
+    starts.sort_unstable();
+    for start in starts {
+        while let Some(first) = edges.get_mut(&start).and_then(|out| out.pop()) {
+            let mut ring = vec![start];
+            let (mut corner, mut direction) = (start, first);
+            loop {
+                corner = (corner.0 + direction.0, corner.1 + direction.1);
+                let Some(out) = edges.get_mut(&corner).filter(|out| !out.is_empty()) else {
+                    break;
+                };
+                // At a pinch prefer the turn with the smaller cross product:
+                // toward the diagonal neighbour rather than around this cell
+                let pick = (0..out.len())
+                    .min_by_key(|&k| direction.0 * out[k].1 - direction.1 * out[k].0)
+                    .unwrap_or(0);
+                let next = out.swap_remove(pick);
+                if next != direction {
+                    ring.push(corner);
+                }
+                direction = next;
+            }
+            // Shoelace formula, doubled
+            let area: isize = ring
+                .iter()
+                .zip(ring.iter().cycle().skip(1))
+                .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+                .sum();
+            if area.abs() > best_area {
+                best_area = area.abs();
+                best = ring;
+            }
+        }
+    }
+
+    if let Some(&first) = best.first() {
+        best.push(first);
+    }
+    best.iter()
+        .flat_map(|&(cx, cy)| [cx as f32 - 0.5, cy as f32 - 0.5])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 7 × 5 roof with its ridge on column 3, both halves falling toward
+    /// the bottom row.
+    fn roof() -> Vec<f32> {
+        (0..35)
+            .map(|i: usize| 10.0 - (i % 7).abs_diff(3) as f32 - 0.5 * (i / 7) as f32)
+            .collect()
+    }
+
+    #[test]
+    fn catchment_stops_at_the_ridge() {
+        let options = HydrologyOptions::default();
+        let shed = delineate_watershed(&roof(), 7, 5, 1.0, 3.0, 1.5, &options).unwrap();
+        // Snapped to the bottom-left corner, where the left half drains
+        assert_eq!(shed.pour_point, [0, 4]);
+        for (i, &m) in shed.mask.iter().enumerate() {
+            match i % 7 {
+                0..=2 => assert_eq!(m, 1, "cell {i} drains to the pour point"),
+                4..=6 => assert_eq!(m, 0, "cell {i} lies beyond the ridge"),
+                _ => {}
+            }
+        }
+        let cells = shed.mask.iter().filter(|&&m| m != 0).count();
+        assert_eq!(shed.cell_count, cells);
+        assert_eq!(shed.area, cells as f64 * options.cell_area(0));
+    }
+
+    #[test]
+    fn outline_rings_the_mask_clockwise() {
+        // An L of three cells and a fourth touching it only at a corner
+        let mask = [
+            1, 0, 0, //
+            1, 1, 0, //
+            0, 0, 1, //
+        ];
+        let ring = outline(&mask, 3, 3);
+        let points: Vec<[f32; 2]> = ring.chunks_exact(2).map(|p| [p[0], p[1]]).collect();
+        assert_eq!(points.first(), points.last());
+        // Doubled shoelace area: four unit cells, positive when clockwise on
+        // screen (y down)
+        let area: f32 = points
+            .windows(2)
+            .map(|w| w[0][0] * w[1][1] - w[1][0] * w[0][1])
+            .sum();
+        assert_eq!(area, 8.0);
+        for p in &points {
+            assert!(p
+                .iter()
+                .all(|&v| (-0.5..=2.5).contains(&v) && v.fract().abs() == 0.5));
+        }
+    }
+}